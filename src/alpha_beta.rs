@@ -1,44 +1,146 @@
-pub fn compute_alpha_beta(domain: &[f32], range: &[f32]) -> (f32, f32) {
-    let mut sum_r = 0.0;
-    let mut sum_d = 0.0;
-    let mut sum_dr = 0.0;
-    let mut sum_d2 = 0.0;
-
-    for i in (0..domain.len()) {
-        let d = domain[i];
-        let r = range[i];
-
-        sum_r += r;
-        sum_d += d;
-        sum_dr += d * r;
-        sum_d2 += d * d;
-    }
+use crate::block_extractor::BlockPixels;
+use crate::encode::Luminance;
+use crate::simd;
+
+// Least-squares alpha and the stored offset: beta for the affine model, the
+// range mean for the orthogonal one. The polynomial model is fitted in
+// `polynomial`; here it gets its affine part. The searches fill `BlockSums`
+// from cached block statistics instead of calling this per pair.
+#[allow(dead_code)]
+pub fn compute_alpha_beta<D, R>(domain: &D, range: &R, luminance: Luminance) -> (f32, f32)
+where
+    D: BlockPixels + ?Sized,
+    R: BlockPixels + ?Sized,
+{
+    let (domain, range) = (domain.contiguous(), range.contiguous());
+    let (sum_d, sum_r) = (simd::sum(&domain), simd::sum(&range));
+    let sum_dr = simd::dot(&domain, &range);
+    let sum_d2 = simd::dot(&domain, &domain);
 
-    let n = domain.len() as f32;
-    let numerator = n * sum_dr - sum_d * sum_r;
-    let denominator = n * sum_d2 - sum_d * sum_d;
-    let alpha = if denominator.abs() < 1e-6 {
-        0.0
-    } else {
-        numerator / denominator
+    let sums = BlockSums {
+        n: domain.len() as f32,
+        sum_d,
+        sum_r,
+        sum_dr,
+        sum_d2,
+        sum_r2: 0.0,
     };
-    let beta = (sum_r - alpha * sum_d) / n;
+    sums.coefficients(luminance)
+}
 
-    (alpha, beta)
+// Statistics of a domain/range pair, enough to fit and score it without
+// touching the pixels again
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockSums {
+    pub n: f32,
+    pub sum_d: f32,
+    pub sum_r: f32,
+    pub sum_dr: f32,
+    pub sum_d2: f32,
+    pub sum_r2: f32,
 }
 
-pub fn compute_mse(domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
-    let mut mse = 0.0;
-    let n = domain.len() as f32;
+impl BlockSums {
+    // Least-squares fit of `alpha * d + beta` to r
+    pub fn alpha_beta(&self) -> (f32, f32) {
+        let n = self.n;
+        let numerator = n * self.sum_dr - self.sum_d * self.sum_r;
+        let denominator = n * self.sum_d2 - self.sum_d * self.sum_d;
+        let alpha = if denominator.abs() < 1e-6 {
+            0.0
+        } else {
+            numerator / denominator
+        };
+        let beta = (self.sum_r - alpha * self.sum_d) / n;
+
+        (alpha, beta)
+    }
+
+    // Same value as `compute_mse`, expanded as
+    // sum((r - alpha*d - beta)^2) = sum_r2 + alpha^2 sum_d2 + n beta^2
+    //                              - 2 alpha sum_dr - 2 beta sum_r + 2 alpha beta sum_d
+    // and evaluated in f64 because the terms nearly cancel
+    pub fn mse(&self, alpha: f32, beta: f32) -> f32 {
+        let n = self.n as f64;
+        let (sum_d, sum_r, sum_dr) = (self.sum_d as f64, self.sum_r as f64, self.sum_dr as f64);
+        let (sum_d2, sum_r2) = (self.sum_d2 as f64, self.sum_r2 as f64);
+        let (a, b) = (alpha as f64, beta as f64);
+        let sse = sum_r2 + a * a * sum_d2 + n * b * b - 2.0 * a * sum_dr - 2.0 * b * sum_r
+            + 2.0 * a * b * sum_d;
+        (sse.max(0.0) / n) as f32
+    }
 
-    for i in 0..domain.len() {
-        let d = alpha * domain[i] + beta;
-        let r = range[i];
-        let error = r - d;
-        mse += error * error;
+    // Removing the domain mean doesn't change the least-squares slope, and
+    // beta = mean(r) - alpha * mean(d), so both models are the same fit
+    pub fn coefficients(&self, luminance: Luminance) -> (f32, f32) {
+        let (alpha, beta) = self.alpha_beta();
+        match luminance {
+            Luminance::Affine | Luminance::Polynomial => (alpha, beta),
+            Luminance::Orthogonal => (alpha, self.sum_r / self.n),
+        }
     }
 
-    mse /= n;
+    // Quantizes alpha first, then refits and quantizes the offset for that
+    // alpha so it absorbs the rounding of the scale
+    pub fn quantized_coefficients(&self, alpha: f32, bits: u8, luminance: Luminance) -> (f32, f32) {
+        match luminance {
+            Luminance::Affine | Luminance::Polynomial => self.quantized_alpha_beta(alpha, bits),
+            Luminance::Orthogonal => (
+                quantize(alpha, QUANT_ALPHA_RANGE, bits),
+                quantize(self.sum_r / self.n, QUANT_MEAN_RANGE, bits),
+            ),
+        }
+    }
+
+    // Best coefficients for these sums, quantized when `quant_bits` is set,
+    // along with the MSE they give
+    pub fn fit(&self, quant_bits: Option<u8>, luminance: Luminance) -> (f32, f32, f32) {
+        let (mut alpha, mut offset) = self.coefficients(luminance);
+        if let Some(bits) = quant_bits {
+            (alpha, offset) = self.quantized_coefficients(alpha, bits, luminance);
+        }
+        let beta = affine_beta(alpha, offset, self.sum_d / self.n, luminance);
+        (alpha, offset, self.mse(alpha, beta))
+    }
+
+    pub fn quantized_alpha_beta(&self, alpha: f32, bits: u8) -> (f32, f32) {
+        let alpha = quantize(alpha, QUANT_ALPHA_RANGE, bits);
+        let beta = quantize(
+            self.sum_r / self.n - alpha * (self.sum_d / self.n),
+            QUANT_BETA_RANGE,
+            bits,
+        );
+        (alpha, beta)
+    }
+}
+
+pub fn compute_mse<D, R>(domain: &D, range: &R, alpha: f32, beta: f32) -> f32
+where
+    D: BlockPixels + ?Sized,
+    R: BlockPixels + ?Sized,
+{
+    let (domain, range) = (domain.contiguous(), range.contiguous());
+    simd::sse(&domain, &range, alpha, beta) / domain.len() as f32
+}
+
+// Ranges used when alpha/beta are quantized. Alpha is clamped to keep the
+// map contractive, and beta covers every offset such an alpha can need.
+pub const QUANT_ALPHA_RANGE: (f32, f32) = (-1.0, 1.0);
+pub const QUANT_BETA_RANGE: (f32, f32) = (-255.0, 510.0);
+// The orthogonal model's offset is a pixel mean
+pub const QUANT_MEAN_RANGE: (f32, f32) = (0.0, 255.0);
+
+// The beta of the affine map a stored (alpha, offset) pair stands for
+pub fn affine_beta(alpha: f32, offset: f32, domain_mean: f32, luminance: Luminance) -> f32 {
+    match luminance {
+        Luminance::Affine | Luminance::Polynomial => offset,
+        Luminance::Orthogonal => offset - alpha * domain_mean,
+    }
+}
 
-    return mse;
+pub fn quantize(value: f32, (min, max): (f32, f32), bits: u8) -> f32 {
+    let levels = ((1u32 << bits) - 1) as f32;
+    let step = (max - min) / levels;
+    let clamped = value.clamp(min, max);
+    min + ((clamped - min) / step).round() * step
 }
@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, Delay, DynamicImage, Frames};
+
+use crate::batch;
+use crate::video::Frame;
+
+// Frames of a clip for the video encoder: every frame of an animated GIF or
+// APNG with its own delay, the images of a directory in name order, or a
+// single still image. Only directories and stills use `default_delay`.
+pub fn load_frames(path: &Path, default_delay: Delay) -> Result<Vec<Frame>, Box<dyn Error>> {
+    if path.is_dir() {
+        let inputs = batch::collect_inputs(&path.to_string_lossy(), batch::IMAGE_EXTENSIONS)?;
+        if inputs.is_empty() {
+            return Err(format!("no images in {}", path.display()).into());
+        }
+        return load_stills(&inputs, default_delay);
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("gif") => {
+            let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+            collect(decoder.into_frames())
+        }
+        Some("png" | "apng") => {
+            let decoder = PngDecoder::new(BufReader::new(File::open(path)?))?;
+            if decoder.is_apng()? {
+                collect(decoder.apng()?.into_frames())
+            } else {
+                load_stills(&[path.to_path_buf()], default_delay)
+            }
+        }
+        _ => load_stills(&[path.to_path_buf()], default_delay),
+    }
+}
+
+pub fn load_stills(paths: &[impl AsRef<Path>], delay: Delay) -> Result<Vec<Frame>, Box<dyn Error>> {
+    paths
+        .iter()
+        .map(|path| {
+            Ok(Frame {
+                image: image::open(path)?.to_luma8(),
+                delay,
+            })
+        })
+        .collect()
+}
+
+// The decoders hand out whole composited canvases, so no disposal handling
+// is needed here
+fn collect(frames: Frames) -> Result<Vec<Frame>, Box<dyn Error>> {
+    let frames = frames.collect_frames()?;
+    if frames.is_empty() {
+        return Err("the animation has no frames".into());
+    }
+    Ok(frames
+        .into_iter()
+        .map(|frame| Frame {
+            delay: frame.delay(),
+            image: DynamicImage::ImageRgba8(frame.into_buffer()).to_luma8(),
+        })
+        .collect())
+}
+
+// Looping animated GIF. GIF stores delays in hundredths of a second, so
+// finer timings are rounded.
+pub fn save_gif(path: &Path, frames: &[Frame]) -> Result<(), Box<dyn Error>> {
+    let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(frames.iter().map(|frame| {
+        let rgba = DynamicImage::ImageLuma8(frame.image.clone()).to_rgba8();
+        image::Frame::from_parts(rgba, 0, 0, frame.delay)
+    }))?;
+    Ok(())
+}
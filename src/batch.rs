@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time;
+
+use crate::decode;
+use crate::encode::{self, Backend, EncodeOptions, GpuSession};
+use crate::gpu::shader::ShaderRegistry;
+use crate::metrics;
+use crate::util::read_fic_file;
+
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "tif", "tiff", "webp"];
+pub const FIC_EXTENSIONS: &[&str] = &["fic"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchMode {
+    Encode,
+    Decode,
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    pub mode: BatchMode,
+    pub encode: EncodeOptions,
+    pub backend: Backend,
+    pub iterations: usize,
+    pub jobs: usize,
+    // Defaults to writing each output next to its input
+    pub output_dir: Option<PathBuf>,
+    // Where to find the source images when decoding, to report PSNR
+    pub originals_dir: Option<PathBuf>,
+    // Where GPU kernels come from
+    pub shaders: ShaderRegistry,
+}
+
+#[derive(Debug)]
+pub struct BatchResult {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub fic_bytes: u64,
+    pub ratio: f64,
+    pub psnr: Option<f64>,
+    pub elapsed: time::Duration,
+    pub error: Option<String>,
+}
+
+// Accepts a directory (every file with a matching extension) or a path whose
+// file name contains `*` / `?` wildcards, e.g. `test_imgs/lena*.png`.
+pub fn collect_inputs(pattern: &str, extensions: &[&str]) -> io::Result<Vec<PathBuf>> {
+    let path = Path::new(pattern);
+    let (dir, name_pattern) = if path.is_dir() {
+        (path.to_path_buf(), None)
+    } else {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid pattern '{pattern}'")))?;
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        (dir, Some(name.to_string()))
+    };
+
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry_path = entry?.path();
+        if !entry_path.is_file() {
+            continue;
+        }
+        let Some(name) = entry_path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let matches = match &name_pattern {
+            Some(p) => wildcard_match(p, name),
+            None => has_extension(&entry_path, extensions),
+        };
+        if matches {
+            inputs.push(entry_path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((star_pi, star_ni)) = star {
+            // Let the last `*` swallow one more character and retry
+            pi = star_pi + 1;
+            ni = star_ni + 1;
+            star = Some((star_pi, star_ni + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+fn output_path_for(input: &Path, options: &BatchOptions) -> PathBuf {
+    let file_name = match options.mode {
+        BatchMode::Encode => input.with_extension("fic"),
+        BatchMode::Decode => input.with_extension("decoded.png"),
+    };
+    match &options.output_dir {
+        Some(dir) => dir.join(file_name.file_name().unwrap()),
+        None => file_name,
+    }
+}
+
+fn find_original(fic_path: &Path, originals_dir: &Path) -> Option<PathBuf> {
+    let stem = fic_path.file_stem()?;
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|ext| originals_dir.join(stem).with_extension(ext))
+        .find(|p| p.is_file())
+}
+
+fn process_file(
+    input: &Path,
+    output: &Path,
+    options: &BatchOptions,
+    gpu: &GpuSession,
+) -> Result<(u64, f64, Option<f64>), Box<dyn Error>> {
+    match options.mode {
+        BatchMode::Encode => {
+            let used = encode::encode_with_backend(input, output, &options.encode, options.backend, gpu)?;
+            println!("[batch] {} encoded on the {used}", input.display());
+            let original = image::open(input)?.to_luma8();
+            let (header, blocks) = read_fic_file(output)?;
+            let decoded = decode::reconstruct(&header, &blocks, options.iterations);
+            let fic_bytes = fs::metadata(output)?.len();
+            let ratio = metrics::compression_ratio(&header, fic_bytes);
+            Ok((fic_bytes, ratio, Some(metrics::psnr(&original, &decoded))))
+        }
+        BatchMode::Decode => {
+            let (header, blocks) = read_fic_file(input)?;
+            let decoded = decode::reconstruct(&header, &blocks, options.iterations);
+            decoded.save(output)?;
+            let fic_bytes = fs::metadata(input)?.len();
+            let psnr = match options.originals_dir.as_deref().and_then(|dir| find_original(input, dir)) {
+                Some(original_path) => {
+                    let original = image::open(original_path)?.to_luma8();
+                    Some(metrics::psnr(&original, &decoded))
+                }
+                None => None,
+            };
+            Ok((fic_bytes, metrics::compression_ratio(&header, fic_bytes), psnr))
+        }
+    }
+}
+
+// Inputs that only differ in extension, like a.png and a.jpg, map to the
+// same output. Each of those gets the error to fail it with instead of
+// letting the workers overwrite each other's files.
+fn output_clashes(inputs: &[PathBuf], outputs: &[PathBuf]) -> Vec<Option<String>> {
+    let mut writers: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    for (input, output) in inputs.iter().zip(outputs) {
+        writers.entry(output).or_default().push(input);
+    }
+    outputs
+        .iter()
+        .map(|output| {
+            let writers = &writers[output.as_path()];
+            (writers.len() > 1).then(|| {
+                let names: Vec<String> = writers.iter().map(|p| p.display().to_string()).collect();
+                format!("{} would be written by each of {}", output.display(), names.join(", "))
+            })
+        })
+        .collect()
+}
+
+// Runs every input through a pool of `options.jobs` workers. A failing (or
+// panicking) file is recorded in its result and does not stop the others;
+// inputs whose outputs clash fail before any worker starts.
+pub fn run_batch(inputs: &[PathBuf], options: &BatchOptions) -> Vec<BatchResult> {
+    if let Some(dir) = &options.output_dir
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        eprintln!("[batch] Failed to create {}: {e}", dir.display());
+    }
+
+    let outputs: Vec<PathBuf> = inputs.iter().map(|input| output_path_for(input, options)).collect();
+    let clashes = output_clashes(inputs, &outputs);
+    for clash in clashes.iter().flatten() {
+        eprintln!("[batch] Skipping: {clash}");
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<BatchResult>>> = Mutex::new((0..inputs.len()).map(|_| None).collect());
+    let workers = options.jobs.clamp(1, inputs.len().max(1));
+    // One device and set of pipelines for the whole batch
+    let gpu = GpuSession::new(options.shaders.clone());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(i) else {
+                        break;
+                    };
+                    let output = outputs[i].clone();
+                    println!("[batch] ({}/{}) {}", i + 1, inputs.len(), input.display());
+
+                    let start = time::Instant::now();
+                    let outcome = match &clashes[i] {
+                        Some(clash) => Err(clash.clone()),
+                        None => panic::catch_unwind(AssertUnwindSafe(|| {
+                            process_file(input, &output, options, &gpu).map_err(|e| e.to_string())
+                        }))
+                        .unwrap_or_else(|payload| Err(panic_message(payload))),
+                    };
+                    let elapsed = start.elapsed();
+
+                    let result = match outcome {
+                        Ok((fic_bytes, ratio, psnr)) => BatchResult {
+                            input: input.clone(),
+                            output,
+                            fic_bytes,
+                            ratio,
+                            psnr,
+                            elapsed,
+                            error: None,
+                        },
+                        Err(error) => {
+                            eprintln!("[batch] {} failed: {error}", input.display());
+                            BatchResult {
+                                input: input.clone(),
+                                output,
+                                fic_bytes: 0,
+                                ratio: 0.0,
+                                psnr: None,
+                                elapsed,
+                                error: Some(error),
+                            }
+                        }
+                    };
+                    results.lock().unwrap()[i] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every input is processed"))
+        .collect()
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        format!("panicked: {s}")
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        format!("panicked: {s}")
+    } else {
+        "panicked".to_string()
+    }
+}
+
+pub fn print_summary(results: &[BatchResult]) {
+    let name_width = results
+        .iter()
+        .map(|r| r.input.display().to_string().len())
+        .max()
+        .unwrap_or(4)
+        .max(4);
+
+    println!();
+    println!(
+        "{:<name_width$}  {:>10}  {:>7}  {:>9}  {:>10}  status",
+        "file", "fic bytes", "ratio", "PSNR dB", "time"
+    );
+    for r in results {
+        let name = r.input.display().to_string();
+        match &r.error {
+            None => println!(
+                "{:<name_width$}  {:>10}  {:>6.2}x  {:>9}  {:>10.2?}  ok -> {}",
+                name,
+                r.fic_bytes,
+                r.ratio,
+                r.psnr.map_or("-".to_string(), |p| format!("{p:.2}")),
+                r.elapsed,
+                r.output.display()
+            ),
+            Some(error) => println!(
+                "{:<name_width$}  {:>10}  {:>7}  {:>9}  {:>10.2?}  FAILED: {error}",
+                name, "-", "-", "-", r.elapsed
+            ),
+        }
+    }
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    println!("{} files, {} ok, {} failed", results.len(), results.len() - failed, failed);
+}
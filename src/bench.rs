@@ -0,0 +1,75 @@
+use std::time;
+
+use image::GrayImage;
+
+use crate::decode;
+use crate::encode::{self, EncodeOptions, EncodedBlock, Matcher};
+use crate::metrics;
+use crate::simd;
+use crate::util::FicHeader;
+
+const BENCH_DECODE_ITERATIONS: usize = 10;
+
+pub struct BenchResult {
+    pub matcher: Matcher,
+    pub elapsed: time::Duration,
+    pub blocks: Vec<EncodedBlock>,
+    pub psnr: f64,
+}
+
+// Encodes the same image with each matcher. The first matcher is the
+// baseline the others are timed and compared against.
+pub fn run_bench(
+    image: &GrayImage,
+    options: &EncodeOptions,
+    matchers: &[Matcher],
+) -> Result<Vec<BenchResult>, String> {
+    println!("[bench] Using {} kernels", simd::kernels().name);
+    let mut results = Vec::new();
+    for &matcher in matchers {
+        println!("[bench] Encoding with the {matcher} matcher...");
+        let options = EncodeOptions { matcher, ..*options };
+        let start = time::Instant::now();
+        let blocks = encode::encode_luma(image, &options)?;
+        let elapsed = start.elapsed();
+
+        let header = FicHeader::for_encode(image.width(), image.height(), &options, blocks.len());
+        let decoded = decode::reconstruct(&header, &blocks, BENCH_DECODE_ITERATIONS);
+        results.push(BenchResult {
+            matcher,
+            elapsed,
+            psnr: metrics::psnr(image, &decoded),
+            blocks,
+        });
+    }
+    Ok(results)
+}
+
+pub fn print_summary(results: &[BenchResult]) {
+    let Some(baseline) = results.first() else {
+        return;
+    };
+
+    println!();
+    println!(
+        "{:<8}  {:>12}  {:>8}  {:>14}  {:>9}",
+        "matcher", "time", "speedup", "same matches", "PSNR dB"
+    );
+    for r in results {
+        let same = r
+            .blocks
+            .iter()
+            .zip(&baseline.blocks)
+            .filter(|(a, b)| a.meta == b.meta)
+            .count();
+        println!(
+            "{:<8}  {:>12.2?}  {:>7.2}x  {:>7}/{:<6}  {:>9.2}",
+            r.matcher.to_string(),
+            r.elapsed,
+            baseline.elapsed.as_secs_f64() / r.elapsed.as_secs_f64(),
+            same,
+            baseline.blocks.len(),
+            r.psnr
+        );
+    }
+}
@@ -1,9 +1,194 @@
+use std::borrow::Cow;
+
+use crate::simd;
+
 pub struct BlockExtractor {
     pub image: Vec<f32>,
     pub width: usize,
     pub height: usize,
     pub block_size: usize,
     pub stride: usize,
+    pub integral: IntegralImage,
+}
+
+// Pixels of a square block in row-major order. Implemented by owned
+// buffers and by `BlockView`, so the search and transform code can read
+// blocks in place instead of copying them out of the image.
+pub trait BlockPixels {
+    fn pixel_count(&self) -> usize;
+
+    fn pixel(&self, i: usize) -> f32;
+
+    fn pixels(&self) -> impl Iterator<Item = f32> {
+        (0..self.pixel_count()).map(|i| self.pixel(i))
+    }
+
+    // The pixels as one slice for the SIMD kernels, borrowed when the
+    // block already is one
+    fn contiguous(&self) -> Cow<'_, [f32]> {
+        Cow::Owned(self.pixels().collect())
+    }
+}
+
+impl BlockPixels for [f32] {
+    fn pixel_count(&self) -> usize {
+        self.len()
+    }
+
+    fn pixel(&self, i: usize) -> f32 {
+        self[i]
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = f32> {
+        self.iter().copied()
+    }
+
+    fn contiguous(&self) -> Cow<'_, [f32]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl BlockPixels for Vec<f32> {
+    fn pixel_count(&self) -> usize {
+        self.len()
+    }
+
+    fn pixel(&self, i: usize) -> f32 {
+        self[i]
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = f32> {
+        self.iter().copied()
+    }
+
+    fn contiguous(&self) -> Cow<'_, [f32]> {
+        Cow::Borrowed(self)
+    }
+}
+
+// A size x size block borrowed from a row-major image: each row is a
+// contiguous slice, rows are `image_width` apart
+#[derive(Clone, Copy, Debug)]
+pub struct BlockView<'a> {
+    image: &'a [f32],
+    image_width: usize,
+    pub x: usize,
+    pub y: usize,
+    pub size: usize,
+}
+
+impl<'a> BlockView<'a> {
+    pub fn new(image: &'a [f32], image_width: usize, x: usize, y: usize, size: usize) -> Self {
+        assert!(x + size <= image_width, "block runs past the image width");
+        assert!(size == 0 || (y + size - 1) * image_width + x + size <= image.len(), "block runs past the image");
+        Self {
+            image,
+            image_width,
+            x,
+            y,
+            size,
+        }
+    }
+
+    pub fn row(self, dy: usize) -> &'a [f32] {
+        let start = (self.y + dy) * self.image_width + self.x;
+        &self.image[start..start + self.size]
+    }
+
+    pub fn rows(self) -> impl Iterator<Item = &'a [f32]> {
+        (0..self.size).map(move |dy| self.row(dy))
+    }
+
+    pub fn to_vec(self) -> Vec<f32> {
+        self.rows().flatten().copied().collect()
+    }
+
+    // Dot product with a contiguous block of the same size, read in place
+    pub fn dot(self, block: &[f32]) -> f32 {
+        let start = self.y * self.image_width + self.x;
+        simd::dot_rows(&self.image[start..], self.image_width, self.size, block)
+    }
+}
+
+impl BlockPixels for BlockView<'_> {
+    fn pixel_count(&self) -> usize {
+        self.size * self.size
+    }
+
+    fn pixel(&self, i: usize) -> f32 {
+        self.image[(self.y + i / self.size) * self.image_width + self.x + i % self.size]
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = f32> {
+        self.rows().flatten().copied()
+    }
+}
+
+// Sum and sum of squares of a block, the per-block half of `BlockSums`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BlockStats {
+    pub sum: f32,
+    pub sum_sq: f32,
+}
+
+// Summed-area tables of the pixels and of their squares, with a zero first
+// row and column so any rectangle is four lookups. Kept in f64 so 8-bit
+// images stay exact far beyond any size the format allows.
+pub struct IntegralImage {
+    width: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl IntegralImage {
+    pub fn new(image: &[f32], width: usize, height: usize) -> Self {
+        let stride = width + 1;
+        let mut sum = vec![0.0; stride * (height + 1)];
+        let mut sum_sq = vec![0.0; stride * (height + 1)];
+        for y in 0..height {
+            let (mut row, mut row_sq) = (0.0, 0.0);
+            for x in 0..width {
+                let v = image[y * width + x] as f64;
+                row += v;
+                row_sq += v * v;
+                sum[(y + 1) * stride + x + 1] = sum[y * stride + x + 1] + row;
+                sum_sq[(y + 1) * stride + x + 1] = sum_sq[y * stride + x + 1] + row_sq;
+            }
+        }
+        Self { width, sum, sum_sq }
+    }
+
+    fn rect(&self, table: &[f64], x: usize, y: usize, w: usize, h: usize) -> f64 {
+        let stride = self.width + 1;
+        table[(y + h) * stride + x + w] - table[y * stride + x + w] - table[(y + h) * stride + x]
+            + table[y * stride + x]
+    }
+
+    pub fn rect_sum(&self, x: usize, y: usize, w: usize, h: usize) -> f64 {
+        self.rect(&self.sum, x, y, w, h)
+    }
+
+    pub fn rect_sum_sq(&self, x: usize, y: usize, w: usize, h: usize) -> f64 {
+        self.rect(&self.sum_sq, x, y, w, h)
+    }
+
+    pub fn rect_mean(&self, x: usize, y: usize, w: usize, h: usize) -> f64 {
+        self.rect_sum(x, y, w, h) / (w * h) as f64
+    }
+
+    // Population variance, clamped at zero against rounding on flat areas
+    pub fn rect_variance(&self, x: usize, y: usize, w: usize, h: usize) -> f64 {
+        let n = (w * h) as f64;
+        let mean = self.rect_sum(x, y, w, h) / n;
+        (self.rect_sum_sq(x, y, w, h) / n - mean * mean).max(0.0)
+    }
+
+    pub fn block_stats(&self, x: usize, y: usize, size: usize) -> BlockStats {
+        BlockStats {
+            sum: self.rect_sum(x, y, size, size) as f32,
+            sum_sq: self.rect_sum_sq(x, y, size, size) as f32,
+        }
+    }
 }
 
 impl BlockExtractor {
@@ -14,48 +199,63 @@ impl BlockExtractor {
         block_size: usize,
         stride: usize,
     ) -> Self {
+        let integral = IntegralImage::new(&image, width, height);
         Self {
             image,
             width,
             height,
             block_size,
             stride,
+            integral,
         }
     }
 
-    pub fn extract_blocks(&self, stride: usize) -> Vec<Vec<f32>> {
-        let mut blocks = Vec::new();
-        let b = self.block_size;
-
-        for y in (0..=self.height - b).step_by(stride) {
-            for x in (0..=self.width - b).step_by(stride) {
-                let mut block = Vec::with_capacity(b * b);
-
-                for dy in 0..b {
-                    for dx in 0..b {
-                        let px = x + dx;
-                        let py = y + dy;
-                        let idx = py * self.width + px;
-                        block.push(self.image[idx]);
-                    }
-                }
-                blocks.push(block);
-            }
-        }
-        blocks
+    // Top-left corners of the blocks on a grid with this step, row by row
+    pub fn positions(&self, stride: usize) -> impl Iterator<Item = (usize, usize)> + use<> {
+        let (width, height, b) = (self.width, self.height, self.block_size);
+        (0..=height - b)
+            .step_by(stride)
+            .flat_map(move |y| (0..=width - b).step_by(stride).map(move |x| (x, y)))
     }
 
     // Since Range Blocks don't overlap, use block size as the stride
-    pub fn extract_range_blocks(&self) -> Vec<Vec<f32>> {
-        self.extract_blocks(self.block_size)
+    pub fn range_positions(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
+        self.positions(self.block_size)
     }
 
     // Domain Blocks on the other hand, can and SHOULD overlap in most cases
     // for better accuracy
-    pub fn extract_domain_blocks(&self) -> Vec<Vec<f32>> {
-        self.extract_blocks(self.stride)
+    pub fn domain_positions(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
+        self.positions(self.stride)
+    }
+
+    pub fn view(&self, x: usize, y: usize) -> BlockView<'_> {
+        BlockView::new(&self.image, self.width, x, y, self.block_size)
+    }
+
+    pub fn range_views(&self) -> impl Iterator<Item = BlockView<'_>> {
+        self.range_positions().map(|(x, y)| self.view(x, y))
+    }
+
+    pub fn domain_views(&self) -> impl Iterator<Item = BlockView<'_>> {
+        self.domain_positions().map(|(x, y)| self.view(x, y))
+    }
+
+    pub fn block_stats(&self, stride: usize) -> Vec<BlockStats> {
+        self.positions(stride)
+            .map(|(x, y)| self.integral.block_stats(x, y, self.block_size))
+            .collect()
+    }
+
+    pub fn range_stats(&self) -> Vec<BlockStats> {
+        self.block_stats(self.block_size)
+    }
+
+    pub fn domain_stats(&self) -> Vec<BlockStats> {
+        self.block_stats(self.stride)
     }
 
+    #[allow(dead_code)]
     pub fn downsample(&self, factor: usize) -> Vec<f32> {
         let mut downsampled = Vec::new();
         let step = factor;
@@ -63,8 +263,8 @@ impl BlockExtractor {
         let new_width = self.width / step;
         let new_height = self.height / step;
 
-        for y in (0..new_height) {
-            for x in (0..new_width) {
+        for y in 0..new_height {
+            for x in 0..new_width {
                 let mut sum = 0.0;
 
                 for dy in 0..step {
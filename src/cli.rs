@@ -0,0 +1,905 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time;
+
+use image::Delay;
+
+use crate::animation;
+use crate::batch::{self, BatchMode, BatchOptions};
+use crate::bench;
+use crate::encode::{self, Backend, EncodeOptions, GpuSession, Luminance, Matcher, SearchMode};
+use crate::gpu::shader::{Kernel, ShaderRegistry};
+use crate::export;
+use crate::metrics::{self, QualityReport};
+use crate::report::{self, BlockReport, ReportOptions};
+use crate::sweep::{self, SweepGrid};
+use crate::video::{self, VideoOptions};
+use crate::visualize::{self, RangeFilter};
+use crate::{decode, util};
+
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+
+const DEFAULT_BLOCK_SIZE: usize = 8;
+const DEFAULT_STRIDE: usize = 4;
+const DEFAULT_ITERATIONS: usize = 10;
+
+pub const USAGE: &str = "\
+Usage:
+  fractal_comp                         interactive mode
+  fractal_comp encode <image> [-o <out.fic>] [--block-size N] [--stride N] [--backend cpu|gpu|auto]
+                              [--search full|identity] [--quant-bits N] [--threads N]
+                              [--matcher naive|pool|fft] [--luminance affine|orthogonal|polynomial] [--iterations N]
+                              [--gpu-kernel serial|reduction] [--gpu-adapter hardware|any] [--shader-dir <dir>]
+  fractal_comp decode <in.fic> [-o <out.png>] [--iterations N] [--reference <image>]
+  fractal_comp info <file.fic>
+  fractal_comp visualize <in.fic> [-o <out.png>] [--image <source>] [--scale N] [--ranges 0-15,40]
+                                  [--transforms 1,3] [--domains 12,13] [--min-error MSE]
+  fractal_comp report <in.fic> [-o <heatmap.png>] [--image <source>] [--worst N] [--bins N] [--scale N]
+                               [--max-error MSE]
+  fractal_comp export <in.fic> [-o <out.json|out.csv>] [--reference <image>]
+  fractal_comp import <in.json|in.csv> [-o <out.fic>] [--header <file.fic>]
+  fractal_comp bench <image> [--matchers naive,pool,fft] [--block-size N] [--stride N] [--threads N]
+                             [--search full|identity] [--quant-bits N] [--luminance affine|orthogonal|polynomial]
+  fractal_comp sweep <image> [-o <results.csv>] [--block-sizes 4,8] [--strides 4,8]
+                             [--quant-bits none,6,8] [--search full,identity]
+                             [--luminance affine,polynomial] [--iterations N]
+  fractal_comp batch encode <dir|glob> [-o <out dir>] [--block-size N] [--stride N] [--backend cpu|gpu|auto]
+                                       [--search full|identity] [--quant-bits N] [--threads N]
+                                       [--matcher naive|pool|fft] [--luminance affine|orthogonal|polynomial]
+                                       [--iterations N] [--jobs N] [--gpu-kernel serial|reduction]
+                                       [--gpu-adapter hardware|any] [--shader-dir <dir>]
+  fractal_comp batch decode <dir|glob> [-o <out dir>] [--iterations N] [--jobs N] [--originals <dir>]
+  fractal_comp video encode <anim.gif|anim.png|dir|frame...> [-o <out.fic>] [--block-size N] [--stride N] [--search full|identity]
+                                       [--quant-bits N] [--threads N] [--luminance affine|orthogonal|polynomial]
+                                       [--skip-threshold MSE] [--iterations N] [--fps N]
+  fractal_comp video decode <in.fic> [-o <out.gif|out dir>]
+
+Exit codes: 0 success, 1 encode/decode failure (any file in a batch), 2 invalid arguments";
+
+#[derive(Debug)]
+pub enum Command {
+    Encode {
+        input: PathBuf,
+        output: PathBuf,
+        options: EncodeOptions,
+        backend: Backend,
+        shaders: ShaderRegistry,
+        iterations: usize,
+    },
+    Decode {
+        input: PathBuf,
+        output: PathBuf,
+        iterations: usize,
+        reference: Option<PathBuf>,
+    },
+    Info {
+        input: PathBuf,
+    },
+    Visualize {
+        input: PathBuf,
+        output: PathBuf,
+        // Drawn under the grid, and --min-error uses the collage errors
+        // measured against it. Without it the .fic is decoded instead and
+        // the errors come from the file.
+        image: Option<PathBuf>,
+        scale: Option<usize>,
+        filter: RangeFilter,
+    },
+    Report {
+        input: PathBuf,
+        // Error heatmap
+        output: PathBuf,
+        // Recompute the collage errors against the source, for files that
+        // don't record them
+        image: Option<PathBuf>,
+        options: ReportOptions,
+    },
+    Export {
+        input: PathBuf,
+        output: PathBuf,
+        reference: Option<PathBuf>,
+    },
+    Import {
+        input: PathBuf,
+        output: PathBuf,
+        header: Option<PathBuf>,
+    },
+    Batch {
+        pattern: String,
+        options: BatchOptions,
+    },
+    Bench {
+        input: PathBuf,
+        options: EncodeOptions,
+        matchers: Vec<Matcher>,
+    },
+    Sweep {
+        input: PathBuf,
+        output: PathBuf,
+        grid: SweepGrid,
+        iterations: usize,
+    },
+    VideoEncode {
+        inputs: Vec<PathBuf>,
+        output: PathBuf,
+        options: VideoOptions,
+        delay: Delay,
+    },
+    VideoDecode {
+        input: PathBuf,
+        // An animated GIF, or a directory of numbered PNGs
+        output: PathBuf,
+    },
+    Help,
+}
+
+// Splits `args` into positionals and `--flag value` / `--flag=value` pairs,
+// rejecting any flag not listed in `known`.
+struct ParsedArgs {
+    positional: Vec<String>,
+    flags: Vec<(String, String)>,
+}
+
+impl ParsedArgs {
+    fn parse(args: &[String], known: &[&str]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                positional.push(arg.clone());
+                continue;
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if !known.contains(&name) {
+                return Err(format!("unknown option '{name}'"));
+            }
+            let value = match inline_value {
+                Some(value) => value,
+                None => iter
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("option '{name}' needs a value"))?,
+            };
+            flags.push((name.to_string(), value));
+        }
+
+        Ok(Self { positional, flags })
+    }
+
+    fn value(&self, names: &[&str]) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn number(&self, names: &[&str], default: usize) -> Result<usize, String> {
+        match self.value(names) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("{} expects a number, got '{value}'", names[0])),
+            None => Ok(default),
+        }
+    }
+
+    fn single_input(&self, command: &str) -> Result<PathBuf, String> {
+        match self.positional.as_slice() {
+            [input] => Ok(PathBuf::from(input)),
+            [] => Err(format!("{command}: missing input file")),
+            _ => Err(format!("{command}: expected one input file, got {}", self.positional.len())),
+        }
+    }
+}
+
+// Comma-separated list, e.g. `--strides 2,4,8`
+fn parse_list<T>(
+    parsed: &ParsedArgs,
+    name: &str,
+    default: Vec<T>,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, String> {
+    let Some(value) = parsed.value(&[name]) else {
+        return Ok(default);
+    };
+    value
+        .split(',')
+        .map(|item| parse(item.trim()).ok_or_else(|| format!("{name}: invalid value '{item}'")))
+        .collect()
+}
+
+fn parse_backend(parsed: &ParsedArgs) -> Result<Backend, String> {
+    match parsed.value(&["--backend"]).unwrap_or("cpu") {
+        "cpu" => Ok(Backend::Cpu),
+        "gpu" => Ok(Backend::Gpu),
+        "auto" => Ok(Backend::Auto),
+        other => Err(format!("--backend expects cpu, gpu or auto, got '{other}'")),
+    }
+}
+
+const ENCODE_FLAGS: &[&str] = &[
+    "--block-size",
+    "--stride",
+    "--backend",
+    "--search",
+    "--quant-bits",
+    "--threads",
+    "--matcher",
+    "--luminance",
+    "--gpu-kernel",
+    "--gpu-adapter",
+    "--shader-dir",
+];
+
+// Embedded kernels, or `--shader-dir` overrides of them
+fn parse_shaders(parsed: &ParsedArgs) -> ShaderRegistry {
+    ShaderRegistry::new(parsed.value(&["--shader-dir"]).map(PathBuf::from))
+}
+
+fn parse_encode_options(parsed: &ParsedArgs) -> Result<EncodeOptions, String> {
+    let mut options = EncodeOptions::new(
+        parsed.number(&["--block-size"], DEFAULT_BLOCK_SIZE)?,
+        parsed.number(&["--stride"], DEFAULT_STRIDE)?,
+    );
+    if let Some(search) = parsed.value(&["--search"]) {
+        options.search = SearchMode::parse(search)
+            .ok_or_else(|| format!("--search expects full or identity, got '{search}'"))?;
+    }
+    if let Some(bits) = parsed.value(&["--quant-bits"]) {
+        options.quant_bits = Some(
+            bits.parse()
+                .map_err(|_| format!("--quant-bits expects a number, got '{bits}'"))?,
+        );
+    }
+    options.threads = parsed.number(&["--threads"], options.threads)?;
+    if let Some(matcher) = parsed.value(&["--matcher"]) {
+        options.matcher =
+            Matcher::parse(matcher).ok_or_else(|| format!("--matcher expects naive, pool or fft, got '{matcher}'"))?;
+    }
+    if let Some(luminance) = parsed.value(&["--luminance"]) {
+        options.luminance = Luminance::parse(luminance)
+            .ok_or_else(|| format!("--luminance expects affine, orthogonal or polynomial, got '{luminance}'"))?;
+    }
+    if let Some(kernel) = parsed.value(&["--gpu-kernel"]) {
+        options.gpu_kernel =
+            Kernel::parse(kernel).ok_or_else(|| format!("--gpu-kernel expects serial or reduction, got '{kernel}'"))?;
+    }
+    options.allow_software_gpu = match parsed.value(&["--gpu-adapter"]).unwrap_or("hardware") {
+        "hardware" => false,
+        "any" => true,
+        other => return Err(format!("--gpu-adapter expects hardware or any, got '{other}'")),
+    };
+    Ok(options)
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(Command::Help);
+    };
+
+    match command.as_str() {
+        "encode" => {
+            let parsed = ParsedArgs::parse(rest, &[&["-o", "--output", "--iterations"], ENCODE_FLAGS].concat())?;
+            let input = parsed.single_input("encode")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("fic"));
+            let backend = parse_backend(&parsed)?;
+            Ok(Command::Encode {
+                input,
+                output,
+                options: parse_encode_options(&parsed)?,
+                backend,
+                shaders: parse_shaders(&parsed),
+                iterations: parsed.number(&["--iterations"], DEFAULT_ITERATIONS)?,
+            })
+        }
+        "decode" => {
+            let parsed = ParsedArgs::parse(rest, &["-o", "--output", "--iterations", "--reference"])?;
+            let input = parsed.single_input("decode")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("decoded.png"));
+            Ok(Command::Decode {
+                input,
+                output,
+                iterations: parsed.number(&["--iterations"], DEFAULT_ITERATIONS)?,
+                reference: parsed.value(&["--reference"]).map(PathBuf::from),
+            })
+        }
+        "info" => {
+            let parsed = ParsedArgs::parse(rest, &[])?;
+            Ok(Command::Info {
+                input: parsed.single_input("info")?,
+            })
+        }
+        "visualize" => {
+            let parsed = ParsedArgs::parse(
+                rest,
+                &["-o", "--output", "--image", "--scale", "--ranges", "--transforms", "--domains", "--min-error"],
+            )?;
+            let input = parsed.single_input("visualize")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("map.png"));
+            let scale = match parsed.value(&["--scale"]) {
+                Some(_) => match parsed.number(&["--scale"], 1)? {
+                    0 => return Err("--scale must be at least 1".to_string()),
+                    scale => Some(scale),
+                },
+                None => None,
+            };
+            let filter = RangeFilter {
+                ranges: match parsed.value(&["--ranges"]) {
+                    Some(spans) => RangeFilter::parse_spans(spans)
+                        .ok_or_else(|| format!("--ranges expects indices like 0-15,40, got '{spans}'"))?,
+                    None => Vec::new(),
+                },
+                transforms: parse_list(&parsed, "--transforms", Vec::new(), |t| t.parse().ok().filter(|&t| t < 8))?,
+                domains: parse_list(&parsed, "--domains", Vec::new(), |d| d.parse().ok())?,
+                min_error: match parsed.value(&["--min-error"]) {
+                    Some(value) => Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("--min-error expects a number, got '{value}'"))?,
+                    ),
+                    None => None,
+                },
+            };
+            Ok(Command::Visualize {
+                input,
+                output,
+                image: parsed.value(&["--image"]).map(PathBuf::from),
+                scale,
+                filter,
+            })
+        }
+        "report" => {
+            let parsed = ParsedArgs::parse(
+                rest,
+                &["-o", "--output", "--image", "--worst", "--bins", "--scale", "--max-error"],
+            )?;
+            let input = parsed.single_input("report")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("errors.png"));
+            let options = ReportOptions {
+                worst: parsed.number(&["--worst"], report::DEFAULT_WORST)?,
+                bins: parsed.number(&["--bins"], report::DEFAULT_BINS)?.max(1),
+                scale: match parsed.value(&["--scale"]) {
+                    Some(_) => Some(parsed.number(&["--scale"], 1)?.max(1)),
+                    None => None,
+                },
+                max_error: match parsed.value(&["--max-error"]) {
+                    Some(value) => Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&e: &f32| e > 0.0)
+                            .ok_or_else(|| format!("--max-error expects a positive number, got '{value}'"))?,
+                    ),
+                    None => None,
+                },
+            };
+            Ok(Command::Report {
+                input,
+                output,
+                image: parsed.value(&["--image"]).map(PathBuf::from),
+                options,
+            })
+        }
+        "export" => {
+            let parsed = ParsedArgs::parse(rest, &["-o", "--output", "--reference"])?;
+            let input = parsed.single_input("export")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("json"));
+            export::ExportFormat::from_path(&output)?;
+            Ok(Command::Export {
+                input,
+                output,
+                reference: parsed.value(&["--reference"]).map(PathBuf::from),
+            })
+        }
+        "import" => {
+            let parsed = ParsedArgs::parse(rest, &["-o", "--output", "--header"])?;
+            let input = parsed.single_input("import")?;
+            export::ExportFormat::from_path(&input)?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("fic"));
+            Ok(Command::Import {
+                input,
+                output,
+                header: parsed.value(&["--header"]).map(PathBuf::from),
+            })
+        }
+        "batch" => {
+            let mode = match rest.first().map(String::as_str) {
+                Some("encode") => BatchMode::Encode,
+                Some("decode") => BatchMode::Decode,
+                Some(other) => return Err(format!("batch: expected encode or decode, got '{other}'")),
+                None => return Err("batch: expected encode or decode".to_string()),
+            };
+            let parsed = ParsedArgs::parse(
+                &rest[1..],
+                &[&["-o", "--output", "--iterations", "--jobs", "--originals"], ENCODE_FLAGS].concat(),
+            )?;
+            let pattern = parsed.single_input("batch")?.to_string_lossy().into_owned();
+            let jobs = parsed.number(&["--jobs"], default_jobs())?;
+            let mut encode = parse_encode_options(&parsed)?;
+            // Share the cores between the jobs unless told otherwise
+            if parsed.value(&["--threads"]).is_none() {
+                encode.threads = (default_jobs() / jobs.max(1)).max(1);
+            }
+            Ok(Command::Batch {
+                pattern,
+                options: BatchOptions {
+                    mode,
+                    encode,
+                    backend: parse_backend(&parsed)?,
+                    iterations: parsed.number(&["--iterations"], DEFAULT_ITERATIONS)?,
+                    jobs,
+                    output_dir: parsed.value(&["-o", "--output"]).map(PathBuf::from),
+                    originals_dir: parsed.value(&["--originals"]).map(PathBuf::from),
+                    shaders: parse_shaders(&parsed),
+                },
+            })
+        }
+        "bench" => {
+            let parsed = ParsedArgs::parse(rest, &[&["--matchers"], ENCODE_FLAGS].concat())?;
+            Ok(Command::Bench {
+                input: parsed.single_input("bench")?,
+                options: parse_encode_options(&parsed)?,
+                matchers: parse_list(&parsed, "--matchers", Matcher::ALL.to_vec(), Matcher::parse)?,
+            })
+        }
+        "sweep" => {
+            let parsed = ParsedArgs::parse(
+                rest,
+                &[
+                    "-o",
+                    "--output",
+                    "--block-sizes",
+                    "--strides",
+                    "--quant-bits",
+                    "--search",
+                    "--luminance",
+                    "--iterations",
+                ],
+            )?;
+            let input = parsed.single_input("sweep")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("sweep.csv"));
+            let grid = SweepGrid {
+                block_sizes: parse_list(&parsed, "--block-sizes", vec![4, 8], |v| v.parse().ok())?,
+                strides: parse_list(&parsed, "--strides", vec![4, 8], |v| v.parse().ok())?,
+                quant_bits: parse_list(&parsed, "--quant-bits", vec![None], |v| match v {
+                    "none" => Some(None),
+                    bits => bits.parse().ok().map(Some),
+                })?,
+                searches: parse_list(&parsed, "--search", vec![SearchMode::Full], SearchMode::parse)?,
+                luminances: parse_list(&parsed, "--luminance", vec![Luminance::Affine], Luminance::parse)?,
+            };
+            Ok(Command::Sweep {
+                input,
+                output,
+                grid,
+                iterations: parsed.number(&["--iterations"], DEFAULT_ITERATIONS)?,
+            })
+        }
+        "video" => parse_video(rest),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        other => Err(format!("unknown command '{other}'")),
+    }
+}
+
+const DEFAULT_FPS: usize = 10;
+
+fn parse_video(rest: &[String]) -> Result<Command, String> {
+    match rest.first().map(String::as_str) {
+        Some("encode") => {
+            let parsed = ParsedArgs::parse(
+                &rest[1..],
+                &[&["-o", "--output", "--iterations", "--skip-threshold", "--fps"], ENCODE_FLAGS].concat(),
+            )?;
+            if parsed.positional.is_empty() {
+                return Err("video encode: missing input".to_string());
+            }
+            let inputs: Vec<PathBuf> = parsed.positional.iter().map(PathBuf::from).collect();
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| inputs[0].with_extension("fic"));
+            let skip_threshold = match parsed.value(&["--skip-threshold"]) {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| format!("--skip-threshold expects a number, got '{value}'"))?,
+                None => video::DEFAULT_SKIP_THRESHOLD,
+            };
+            let fps = parsed.number(&["--fps"], DEFAULT_FPS)?;
+            if fps == 0 {
+                return Err("--fps must be at least 1".to_string());
+            }
+            Ok(Command::VideoEncode {
+                inputs,
+                output,
+                options: VideoOptions {
+                    encode: parse_encode_options(&parsed)?,
+                    skip_threshold,
+                    iterations: parsed.number(&["--iterations"], DEFAULT_ITERATIONS)?,
+                },
+                delay: Delay::from_numer_denom_ms(1000, fps as u32),
+            })
+        }
+        Some("decode") => {
+            let parsed = ParsedArgs::parse(&rest[1..], &["-o", "--output"])?;
+            let input = parsed.single_input("video decode")?;
+            let output = parsed
+                .value(&["-o", "--output"])
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_extension("gif"));
+            Ok(Command::VideoDecode { input, output })
+        }
+        Some(other) => Err(format!("video: expected encode or decode, got '{other}'")),
+        None => Err("video: expected encode or decode".to_string()),
+    }
+}
+
+pub fn run(args: &[String]) -> ExitCode {
+    let command = match parse_args(args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {message}");
+            eprintln!("{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let result = match command {
+        Command::Encode {
+            input,
+            output,
+            options,
+            backend,
+            shaders,
+            iterations,
+        } => run_encode(&input, &output, &options, backend, shaders, iterations),
+        Command::Decode {
+            input,
+            output,
+            iterations,
+            reference,
+        } => run_decode(&input, &output, iterations, reference.as_deref()),
+        Command::Info { input } => run_info(&input),
+        Command::Visualize {
+            input,
+            output,
+            image,
+            scale,
+            filter,
+        } => run_visualize(&input, &output, image.as_deref(), scale, &filter),
+        Command::Report {
+            input,
+            output,
+            image,
+            options,
+        } => run_report(&input, &output, image.as_deref(), &options),
+        Command::Export {
+            input,
+            output,
+            reference,
+        } => export::export_file(&input, &output, reference.as_deref()),
+        Command::Import { input, output, header } => run_import(&input, &output, header.as_deref()),
+        Command::Batch { pattern, options } => run_batch(&pattern, &options),
+        Command::Bench {
+            input,
+            options,
+            matchers,
+        } => run_bench(&input, &options, &matchers),
+        Command::Sweep {
+            input,
+            output,
+            grid,
+            iterations,
+        } => run_sweep(&input, &output, &grid, iterations),
+        Command::VideoEncode {
+            inputs,
+            output,
+            options,
+            delay,
+        } => run_video_encode(&inputs, &output, &options, delay),
+        Command::VideoDecode { input, output } => run_video_decode(&input, &output),
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn run_encode(
+    input: &Path,
+    output: &Path,
+    options: &EncodeOptions,
+    backend: Backend,
+    shaders: ShaderRegistry,
+    iterations: usize,
+) -> Result<(), Box<dyn Error>> {
+    let start = time::Instant::now();
+    let used = encode::encode_with_backend(input, output, options, backend, &GpuSession::new(shaders))?;
+    println!("Encoded {} -> {} on the {used} in {:?}", input.display(), output.display(), start.elapsed());
+
+    // Decode in memory to report what this encode is worth
+    let (header, blocks) = util::read_fic_file(output)?;
+    let decoded = decode::reconstruct(&header, &blocks, iterations);
+    let original = image::open(input)?.to_luma8();
+    print_size_report(&header, output)?;
+    println!("Quality:     {} ({iterations} iterations)", QualityReport::compare(&original, &decoded));
+    Ok(())
+}
+
+fn run_decode(
+    input: &Path,
+    output: &Path,
+    iterations: usize,
+    reference: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let start = time::Instant::now();
+    decode::decode_image(input, output, iterations)?;
+    println!("Decoded {} -> {} in {:?}", input.display(), output.display(), start.elapsed());
+
+    let header = util::read_fic_header(input)?;
+    print_size_report(&header, input)?;
+    if let Some(reference) = reference {
+        let original = image::open(reference)?.to_luma8();
+        let decoded = image::open(output)?.to_luma8();
+        if original.dimensions() != decoded.dimensions() {
+            return Err(format!(
+                "reference is {}x{} but the decoded image is {}x{}",
+                original.width(),
+                original.height(),
+                decoded.width(),
+                decoded.height()
+            )
+            .into());
+        }
+        println!("Quality:     {}", QualityReport::compare(&original, &decoded));
+    }
+    Ok(())
+}
+
+fn print_size_report(header: &util::FicHeader, fic_path: &Path) -> Result<(), Box<dyn Error>> {
+    let fic_bytes = std::fs::metadata(fic_path)?.len();
+    println!(
+        "Size:        {} bytes, ratio {:.2}:1, {:.3} bpp",
+        fic_bytes,
+        metrics::compression_ratio(header, fic_bytes),
+        metrics::bits_per_pixel(header, fic_bytes)
+    );
+    Ok(())
+}
+
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+pub fn run_batch(pattern: &str, options: &BatchOptions) -> Result<(), Box<dyn Error>> {
+    let extensions = match options.mode {
+        BatchMode::Encode => batch::IMAGE_EXTENSIONS,
+        BatchMode::Decode => batch::FIC_EXTENSIONS,
+    };
+    let inputs = batch::collect_inputs(pattern, extensions)?;
+    if inputs.is_empty() {
+        return Err(format!("no input files match '{pattern}'").into());
+    }
+
+    println!("[batch] {} files, {} workers", inputs.len(), options.jobs);
+    let results = batch::run_batch(&inputs, options);
+    batch::print_summary(&results);
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    if failed > 0 {
+        return Err(format!("{failed} of {} files failed", results.len()).into());
+    }
+    Ok(())
+}
+
+fn run_bench(input: &Path, options: &EncodeOptions, matchers: &[Matcher]) -> Result<(), Box<dyn Error>> {
+    let image = image::open(input)?.to_luma8();
+    let results = bench::run_bench(&image, options, matchers)?;
+    bench::print_summary(&results);
+    Ok(())
+}
+
+fn run_sweep(input: &Path, output: &Path, grid: &SweepGrid, iterations: usize) -> Result<(), Box<dyn Error>> {
+    let image = image::open(input)?.to_luma8();
+    let mut csv = std::io::BufWriter::new(std::fs::File::create(output)?);
+
+    let start = time::Instant::now();
+    let points = sweep::run_sweep(&image, grid, iterations, &mut csv)?;
+    println!(
+        "Swept {} of {} points in {:?}, results in {}",
+        points.len(),
+        grid.points().len(),
+        start.elapsed(),
+        output.display()
+    );
+    if points.is_empty() {
+        return Err("no grid point could be encoded".into());
+    }
+    Ok(())
+}
+
+fn run_video_encode(
+    inputs: &[PathBuf],
+    output: &Path,
+    options: &VideoOptions,
+    delay: Delay,
+) -> Result<(), Box<dyn Error>> {
+    // One input can be a whole clip, several are the frames in order
+    let frames = match inputs {
+        [input] => animation::load_frames(input, delay)?,
+        _ => animation::load_stills(inputs, delay)?,
+    };
+
+    let start = time::Instant::now();
+    let encoded = video::encode_video(&frames, options)?;
+    for (i, frame) in encoded.frames.iter().enumerate() {
+        let stats = frame.stats;
+        println!(
+            "Frame {i}: {} intra, {} temporal, {} skipped",
+            stats.intra, stats.temporal, stats.skipped
+        );
+    }
+    video::save_video_file(output, &encoded)?;
+    println!("Encoded {} frames -> {} in {:?}", frames.len(), output.display(), start.elapsed());
+
+    let decoded = video::decode_video(&encoded)?;
+    let psnr: f64 = frames
+        .iter()
+        .zip(&decoded)
+        .map(|(original, decoded)| metrics::psnr(&original.image, &decoded.image))
+        .sum::<f64>()
+        / frames.len() as f64;
+    let pixels = frames.len() as u64 * encoded.header.width as u64 * encoded.header.height as u64;
+    let fic_bytes = std::fs::metadata(output)?.len();
+    println!(
+        "Size:        {} bytes, {:.3} bpp",
+        fic_bytes,
+        fic_bytes as f64 * 8.0 / pixels as f64
+    );
+    println!("Quality:     mean PSNR {psnr:.2} dB over {} frames", frames.len());
+    Ok(())
+}
+
+fn run_video_decode(input: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    let start = time::Instant::now();
+    let encoded = video::read_video_file(input)?;
+    let frames = video::decode_video(&encoded)?;
+    if output.extension().is_some_and(|e| e.eq_ignore_ascii_case("gif")) {
+        animation::save_gif(output, &frames)?;
+    } else {
+        std::fs::create_dir_all(output)?;
+        for (i, frame) in frames.iter().enumerate() {
+            frame.image.save(output.join(format!("frame_{i:04}.png")))?;
+        }
+    }
+    println!(
+        "Decoded {} frames from {} -> {} in {:?}",
+        frames.len(),
+        input.display(),
+        output.display(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+fn run_visualize(
+    input: &Path,
+    output: &Path,
+    image: Option<&Path>,
+    scale: Option<usize>,
+    filter: &RangeFilter,
+) -> Result<(), Box<dyn Error>> {
+    let (header, blocks) = util::read_fic_file(input)?;
+    let background = match image {
+        Some(image) => image::open(image)?.to_luma8(),
+        None => decode::reconstruct(&header, &blocks, DEFAULT_ITERATIONS),
+    };
+    let errors = match image {
+        Some(_) => decode::collage_errors(&header, &blocks, &background)?,
+        None => blocks.iter().map(|block| block.error).collect(),
+    };
+
+    let scale = scale.unwrap_or_else(|| visualize::default_scale(&header));
+    let (canvas, counts) = visualize::render(&header, &blocks, &background, scale, filter, &errors)?;
+    canvas.save(output)?;
+
+    println!(
+        "Drew {} of {} ranges at {scale}x to {}",
+        counts.iter().sum::<usize>(),
+        blocks.len(),
+        output.display()
+    );
+    for (transform, count) in counts.iter().enumerate() {
+        let [r, g, b] = visualize::TRANSFORM_COLOURS[transform];
+        println!(
+            "  {transform} {:<18} #{r:02x}{g:02x}{b:02x}  {count} ranges",
+            visualize::TRANSFORM_NAMES[transform]
+        );
+    }
+    Ok(())
+}
+
+fn run_report(input: &Path, output: &Path, image: Option<&Path>, options: &ReportOptions) -> Result<(), Box<dyn Error>> {
+    let (header, blocks) = util::read_fic_file(input)?;
+    let errors = match image {
+        Some(image) => decode::collage_errors(&header, &blocks, &image::open(image)?.to_luma8())?,
+        None => blocks.iter().map(|block| block.error).collect(),
+    };
+    if image.is_none() && !blocks.is_empty() && errors.iter().all(|&e| e == 0.0) {
+        println!("Note: {} records no block errors, pass --image to measure them", input.display());
+    }
+
+    println!("{}", BlockReport::new(&header, &blocks, &errors, options.bins, options.worst));
+
+    let max_error = options
+        .max_error
+        .unwrap_or_else(|| errors.iter().copied().fold(0.0, f32::max));
+    let scale = options.scale.unwrap_or_else(|| visualize::default_scale(&header));
+    report::error_heatmap(&header, &errors, scale, max_error).save(output)?;
+    println!(
+        "Error heatmap at {scale}x in {} (black 0, white RMSE {:.2})",
+        output.display(),
+        max_error.sqrt()
+    );
+    Ok(())
+}
+
+fn run_import(input: &Path, output: &Path, header: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let header = export::import_file(input, output, header)?;
+    print_size_report(&header, output)
+}
+
+fn run_info(input: &Path) -> Result<(), Box<dyn Error>> {
+    let header = util::read_fic_header(input)?;
+    let file_size = std::fs::metadata(input)?.len();
+
+    println!("File:        {}", input.display());
+    println!("Dimensions:  {}x{}", header.width, header.height);
+    println!("Block size:  {}", header.block_size);
+    println!("Stride:      {}", header.stride);
+    println!("Range grid:  {} per row, step {}", header.ranges_per_row(), header.range_step());
+    println!("Domain grid: {}x{}", header.domains_per_row(), header.domains_per_col());
+    println!("Luminance:   {} (header flags {:#04x})", header.luminance, header.flags());
+    println!("Blocks:      {}", header.num_blocks);
+    println!("File size:   {} bytes (expected {})", file_size, header.file_size());
+
+    if header.file_size() as u64 != file_size {
+        return Err("file size does not match the header".into());
+    }
+    Ok(())
+}
@@ -1,97 +1,48 @@
-use crate::encode::EncodedBlock;
+use crate::alpha_beta::affine_beta;
+use crate::block_extractor::{BlockPixels, BlockView, IntegralImage};
+use crate::encode::{EncodedBlock, Luminance};
 use crate::transform::apply_d4_transform;
+use crate::util::{read_fic_file, FicHeader};
 use image::{GrayImage, Luma};
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::error::Error;
 use std::path::Path;
-pub fn call_test(fic_path: &Path, output_path: &Path, iterations: usize) {
-    println!("Entered decode_image()");
-    std::thread::sleep(std::time::Duration::from_secs(1)); // force delay to make sure it runs
-
-    let file = match File::open(fic_path) {
-        Ok(f) => f,
-        Err(e) => {
-            println!("❌ Failed to open .fic: {}", e);
-            return;
-        }
-    };
-
-    println!("Opened .fic file successfully");
-}
 
-pub fn decode_image(fic_path: &Path, output_path: &Path, iterations: usize) {
+pub fn decode_image(
+    fic_path: &Path,
+    output_path: &Path,
+    iterations: usize,
+) -> Result<(), Box<dyn Error>> {
     println!("Opening .fic file: {}", fic_path.display());
 
-    let file = File::open(fic_path).expect("Failed to open .fic file");
-    let mut reader = BufReader::new(file);
-
-    let mut buf2 = [0u8; 2];
-    let mut buf1 = [0u8; 1];
-    let mut buf4 = [0u8; 4];
-
-    reader.read_exact(&mut buf2).unwrap();
-    let width = u16::from_le_bytes(buf2) as usize;
+    let (header, blocks) = read_fic_file(fic_path)?;
 
-    reader.read_exact(&mut buf2).unwrap();
-    let height = u16::from_le_bytes(buf2) as usize;
-
-    reader.read_exact(&mut buf1).unwrap();
-    let block_size = buf1[0] as usize;
+    println!("Header:");
+    println!("-> width: {}, height: {}", header.width, header.height);
+    println!("-> block size: {}, blocks: {}", header.block_size, header.num_blocks);
+    println!("-> luminance: {}", header.luminance);
 
-    reader.read_exact(&mut buf1).unwrap();
-    let stride = buf1[0] as usize;
+    let output = reconstruct(&header, &blocks, iterations);
 
-    reader.read_exact(&mut buf4).unwrap();
-    let num_blocks = u32::from_le_bytes(buf4) as usize;
+    // --- Save output ---
+    output.save(output_path)?;
+    println!("Decoded image saved to {}", output_path.display());
+    Ok(())
+}
 
-    println!("Header:");
-    println!("-> width: {width}, height: {height}");
-    println!("-> block size: {block_size}, blocks: {num_blocks}");
-
-    let expected_data_bytes = num_blocks * 16;
-    let actual_size = std::fs::metadata(fic_path).unwrap().len() as usize;
-    let actual_data_bytes = actual_size - 10;
-
-    println!("-> expected block data: {expected_data_bytes} bytes");
-    println!("-> actual   block data: {actual_data_bytes} bytes");
-
-    assert_eq!(
-        expected_data_bytes, actual_data_bytes,
-        "ERROR: Mismatch in header vs actual .fic block data size"
-    );
-
-    // --- Read block data ---
-    let mut blocks = Vec::with_capacity(num_blocks);
-    for i in 0..num_blocks {
-        let mut read_field = |buf: &mut [u8; 4]| {
-            reader.read_exact(buf).unwrap();
-            *buf
-        };
-        let meta = u32::from_le_bytes(read_field(&mut buf4));
-        let _unused = u32::from_le_bytes(read_field(&mut buf4));
-        let alpha = f32::from_le_bytes(read_field(&mut buf4));
-        let beta = f32::from_le_bytes(read_field(&mut buf4));
-
-        blocks.push(EncodedBlock {
-            meta,
-            _unused,
-            alpha,
-            beta,
-        });
-    }
+pub fn reconstruct(header: &FicHeader, blocks: &[EncodedBlock], iterations: usize) -> GrayImage {
+    let width = header.width as usize;
+    let height = header.height as usize;
 
     // --- Decode image ---
     let mut current = vec![128.0; width * height];
     let mut new_image = vec![0.0; width * height];
-    let bs = block_size;
+    let bs = header.block_size as usize;
 
     for iter in 0..iterations {
         println!("Iteration {iter}...");
+        let integral = IntegralImage::new(&current, width, height);
         for (i, block) in blocks.iter().enumerate() {
-            let blocks_per_row = (width - bs) / stride + 1;
-
-            let bx = (i % blocks_per_row) * stride; // column
-            let by = (i / blocks_per_row) * stride; // row
+            let (bx, by) = header.range_origin(i);
 
             if bx + bs > width || by + bs > height {
                 // Silently skip out-of-bounds blocks
@@ -101,32 +52,19 @@ pub fn decode_image(fic_path: &Path, output_path: &Path, iterations: usize) {
             let dom_idx = block.domain_index();
             let transform_id = block.transform_id();
 
-            let dx = dom_idx % (width - bs + 1);
-            let dy = dom_idx / (width - bs + 1);
-
-            let mut domain_block = Vec::with_capacity(bs * bs);
-            for y in 0..bs {
-                for x in 0..bs {
-                    let idx = (dy + y) * width + (dx + x);
-                    domain_block.push(current[idx]);
-                }
+            let (dx, dy) = header.domain_origin(dom_idx);
+            if dx + bs > width || dy + bs > height {
+                continue;
             }
 
+            let domain_block = BlockView::new(&current, width, dx, dy, bs);
             let transformed = apply_d4_transform(&domain_block, bs, transform_id);
-            let reconstructed: Vec<f32> = transformed
-                .iter()
-                .map(|v| block.alpha * v + block.beta)
-                .collect();
+            let domain = domain_moments(&integral, dx, dy, bs);
+            let reconstructed = apply_block(block, &transformed, domain, header.luminance, bs);
 
             for y in 0..bs {
                 for x in 0..bs {
                     let dst_idx = (by + y) * width + (bx + x);
-                    assert!(
-                        dst_idx < new_image.len(),
-                        "ERROR: dst_idx {} out of bounds (len = {})",
-                        dst_idx,
-                        new_image.len()
-                    );
                     new_image[dst_idx] = reconstructed[y * bs + x].clamp(0.0, 255.0);
                 }
             }
@@ -135,17 +73,80 @@ pub fn decode_image(fic_path: &Path, output_path: &Path, iterations: usize) {
         current.copy_from_slice(&new_image);
     }
 
-    // --- Save output ---
+    to_image(&current, width, height)
+}
+
+pub fn to_image(pixels: &[f32], width: usize, height: usize) -> GrayImage {
     let mut output = GrayImage::new(width as u32, height as u32);
     for y in 0..height {
         for x in 0..width {
-            let val = current[y * width + x].clamp(0.0, 255.0) as u8;
+            let val = pixels[y * width + x].clamp(0.0, 255.0) as u8;
             output.put_pixel(x as u32, y as u32, Luma([val]));
         }
     }
-
     output
-        .save(output_path)
-        .expect("Failed to save decoded image");
-    println!("Decoded image saved to {}", output_path.display());
+}
+
+// Mean and variance of the domain at (x, y), which no isometry changes
+pub fn domain_moments(integral: &IntegralImage, x: usize, y: usize, size: usize) -> (f32, f32) {
+    (
+        integral.rect_mean(x, y, size, size) as f32,
+        integral.rect_variance(x, y, size, size) as f32,
+    )
+}
+
+// The range a block maps its transformed domain to, given the domain's
+// `domain_moments`
+pub fn apply_block(
+    block: &EncodedBlock,
+    transformed: &[f32],
+    (domain_mean, domain_variance): (f32, f32),
+    luminance: Luminance,
+    block_size: usize,
+) -> Vec<f32> {
+    if luminance == Luminance::Polynomial {
+        return block.coefficients().apply(transformed, domain_mean, domain_variance, block_size);
+    }
+    let beta = affine_beta(block.alpha, block.beta, domain_mean, luminance);
+    transformed.iter().map(|v| block.alpha * v + beta).collect()
+}
+
+// MSE of each block's map applied to the original image itself, i.e. how far
+// the range is from its own collage. Nothing is clamped, so this is the error
+// the encoder records in `EncodedBlock::error`, recomputed for files that
+// don't carry it.
+pub fn collage_errors(header: &FicHeader, blocks: &[EncodedBlock], image: &GrayImage) -> Result<Vec<f32>, String> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    if image.dimensions() != (width as u32, height as u32) {
+        return Err(format!(
+            "image is {}x{} but the .fic is {width}x{height}",
+            image.width(),
+            image.height()
+        ));
+    }
+    let pixels: Vec<f32> = image.pixels().map(|p| p[0] as f32).collect();
+    let integral = IntegralImage::new(&pixels, width, height);
+    let bs = header.block_size as usize;
+
+    blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| {
+            let (bx, by) = header.range_origin(i);
+            let (dx, dy) = header.domain_origin(block.domain_index());
+            if bx + bs > width || by + bs > height || dx + bs > width || dy + bs > height {
+                return Err(format!("block {i} lies outside the {width}x{height} image"));
+            }
+            let domain = BlockView::new(&pixels, width, dx, dy, bs);
+            let transformed = apply_d4_transform(&domain, bs, block.transform_id());
+            let collage = apply_block(block, &transformed, domain_moments(&integral, dx, dy, bs), header.luminance, bs);
+            let range = BlockView::new(&pixels, width, bx, by, bs);
+            let sse: f32 = collage
+                .iter()
+                .zip(range.pixels())
+                .map(|(c, r)| (c - r).powi(2))
+                .sum();
+            Ok(sse / (bs * bs) as f32)
+        })
+        .collect()
 }
@@ -0,0 +1,134 @@
+use std::mem::size_of;
+use std::ops::Range;
+
+use crate::alpha_beta::BlockSums;
+use crate::block_extractor::{BlockExtractor, BlockPixels, BlockStats, BlockView};
+use crate::encode::{EncodeOptions, EncodedBlock, Luminance};
+use crate::polynomial::{self, DomainMoments, RangeMoments};
+use crate::transform::{apply_d4_transform, inverse_d4_transform};
+
+// The domains borrowed from the image, with their statistics from the
+// integral image built once per image. Sums are the same for all 8
+// isometries, so only one entry per domain is kept.
+//
+// Nothing is stored per pixel: a transformed domain against the range has
+// the same dot product as the domain against the inversely transformed
+// range, so each range is transformed once instead of every domain, and
+// each domain is read in place from the image. Memory is a fixed number of
+// bytes per domain whatever the block size.
+pub struct DomainPool<'a> {
+    block_size: usize,
+    transform_ids: Range<u8>,
+    domains: Vec<BlockView<'a>>,
+    stats: Vec<BlockStats>,
+    // One per domain, only built for the polynomial model
+    moments: Vec<DomainMoments>,
+}
+
+impl<'a> DomainPool<'a> {
+    pub fn new(extractor: &'a BlockExtractor, options: &EncodeOptions) -> Self {
+        let domains: Vec<BlockView> = extractor.domain_views().collect();
+        let stats = extractor.domain_stats();
+        let moments = if options.luminance == Luminance::Polynomial {
+            domains
+                .iter()
+                .zip(&stats)
+                .map(|(domain, &stats)| DomainMoments::new(domain, stats, options.block_size))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            block_size: options.block_size,
+            transform_ids: options.search.transform_ids(),
+            domains,
+            stats,
+            moments,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    // What the pool keeps on the heap
+    pub fn heap_bytes(&self) -> usize {
+        self.domains.capacity() * size_of::<BlockView>()
+            + self.stats.capacity() * size_of::<BlockStats>()
+            + self.moments.capacity() * size_of::<DomainMoments>()
+    }
+
+    // The range as each isometry of a domain sees it, indexed by transform id
+    fn inverse_ranges(&self, range_block: &BlockView) -> Vec<Vec<f32>> {
+        self.transform_ids
+            .clone()
+            .map(|transform_id| apply_d4_transform(range_block, self.block_size, inverse_d4_transform(transform_id)))
+            .collect()
+    }
+
+    // Same search as `encode_block`, in the same order, but each candidate
+    // only costs a dot product with the range
+    pub fn encode_range(
+        &self,
+        range_block: &BlockView,
+        range: BlockStats,
+        options: &EncodeOptions,
+    ) -> EncodedBlock {
+        if options.luminance == Luminance::Polynomial {
+            return self.encode_range_polynomial(range_block, range, options);
+        }
+        let ranges = self.inverse_ranges(range_block);
+        let n = range_block.pixel_count() as f32;
+
+        let mut best_mse = f32::MAX;
+        let mut best_meta = 0u32;
+        let mut best_alpha = 0.0;
+        let mut best_beta = 0.0;
+
+        for (domain_idx, domain) in self.domains.iter().enumerate() {
+            for transform_id in self.transform_ids.clone() {
+                let sums = BlockSums {
+                    n,
+                    sum_d: self.stats[domain_idx].sum,
+                    sum_r: range.sum,
+                    sum_dr: domain.dot(&ranges[transform_id as usize]),
+                    sum_d2: self.stats[domain_idx].sum_sq,
+                    sum_r2: range.sum_sq,
+                };
+                let (alpha, beta, mse) = sums.fit(options.quant_bits, options.luminance);
+                if mse < best_mse {
+                    best_mse = mse;
+                    best_meta = ((transform_id as u32) << 16) | (domain_idx as u32 & 0xFFFF);
+                    best_alpha = alpha;
+                    best_beta = beta;
+                }
+            }
+        }
+
+        EncodedBlock::affine(best_meta, best_alpha, best_beta).with_error(best_mse)
+    }
+
+    // The domain moments come from the pool, leaving two sums per candidate
+    fn encode_range_polynomial(&self, range_block: &BlockView, range: BlockStats, options: &EncodeOptions) -> EncodedBlock {
+        let range = RangeMoments::new(range_block, range, options.block_size);
+        let ranges = self.inverse_ranges(range_block);
+        let mut best_mse = f32::MAX;
+        let mut best = EncodedBlock::affine(0, 0.0, 0.0);
+
+        for (domain_idx, domain) in self.domains.iter().enumerate() {
+            for transform_id in self.transform_ids.clone() {
+                let (sum_dr, sum_d2r) = polynomial::cross_sums(domain, &ranges[transform_id as usize]);
+                let moments = self.moments[domain_idx].transformed(transform_id);
+                let (coefficients, mse) = polynomial::fit(&moments, &range, sum_dr, sum_d2r);
+                if mse < best_mse {
+                    best_mse = mse;
+                    let meta = ((transform_id as u32) << 16) | (domain_idx as u32 & 0xFFFF);
+                    best = EncodedBlock::polynomial(meta, &coefficients).with_error(mse);
+                }
+            }
+        }
+
+        best
+    }
+}
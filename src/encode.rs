@@ -1,21 +1,76 @@
-use crate::alpha_beta::{compute_alpha_beta, compute_mse};
+use crate::alpha_beta::{affine_beta, compute_mse, BlockSums};
 use crate::block_extractor::*;
-use crate::transform::{self, apply_d4_transform};
+use crate::domain_pool::DomainPool;
+use crate::fft_search::FftSearch;
+use crate::polynomial::{self, Polynomial};
+use crate::simd;
+use crate::transform::apply_d4_transform;
+use crate::gpu;
+use crate::gpu::encoder::GpuEncoder;
+use crate::gpu::shader::{Kernel, ShaderRegistry};
 use crate::util::*;
-use image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma};
+use bytemuck::Zeroable;
+use image::GrayImage;
+use std::error::Error;
+use std::fmt;
 use std::path::Path;
-use std::vec;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
 
 #[repr(C)]
-#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct EncodedBlock {
     pub meta: u32,      // domain_index in lower 16 bits, transform_id in upper 8
-    pub _unused: u32,   // unused for now, can use for extra flags
+    pub error: f32,     // collage MSE of the chosen map, 0 when not recorded
     pub alpha: f32,
     pub beta: f32,
+    // Extra terms of the polynomial luminance model, zero for the others
+    pub gamma: f32,
+    pub ramp_x: f32,
+    pub ramp_y: f32,
 }
 
 impl EncodedBlock {
+    pub fn affine(meta: u32, alpha: f32, beta: f32) -> Self {
+        Self {
+            meta,
+            alpha,
+            beta,
+            ..Self::zeroed()
+        }
+    }
+
+    pub fn polynomial(meta: u32, coefficients: &Polynomial) -> Self {
+        Self {
+            meta,
+            error: 0.0,
+            alpha: coefficients.alpha,
+            beta: coefficients.beta,
+            gamma: coefficients.gamma,
+            ramp_x: coefficients.ramp_x,
+            ramp_y: coefficients.ramp_y,
+        }
+    }
+
+    // The fit error the search picked this block by. Sums-based matchers
+    // can land a hair below zero on exact fits.
+    pub fn with_error(self, error: f32) -> Self {
+        Self {
+            error: error.max(0.0),
+            ..self
+        }
+    }
+
+    pub fn coefficients(&self) -> Polynomial {
+        Polynomial {
+            alpha: self.alpha,
+            gamma: self.gamma,
+            beta: self.beta,
+            ramp_x: self.ramp_x,
+            ramp_y: self.ramp_y,
+        }
+    }
+
     pub fn domain_index(&self) -> usize {
         (self.meta & 0xFFFF) as usize
     }
@@ -25,22 +80,184 @@ impl EncodedBlock {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    // Try all 8 D4 isometries of every domain
+    Full,
+    // Only the untransformed domain: 8x fewer comparisons, no transform bits
+    Identity,
+}
+
+impl SearchMode {
+    pub fn transform_ids(&self) -> std::ops::Range<u8> {
+        match self {
+            SearchMode::Full => 0..8,
+            SearchMode::Identity => 0..1,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(SearchMode::Full),
+            "identity" => Some(SearchMode::Identity),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SearchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchMode::Full => write!(f, "full"),
+            SearchMode::Identity => write!(f, "identity"),
+        }
+    }
+}
+
+// How the CPU search evaluates candidates. All of them find the same matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Matcher {
+    // Transform and fit every domain for every range (`encode_block`)
+    Naive,
+    // Domain sums cached up front and the range transformed instead of the
+    // domains (`DomainPool`)
+    Pool,
+    // Dot products with every domain from an FFT cross-correlation (`FftSearch`)
+    Fft,
+}
+
+impl Matcher {
+    pub const ALL: [Matcher; 3] = [Matcher::Naive, Matcher::Pool, Matcher::Fft];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Matcher::ALL.into_iter().find(|m| m.to_string() == name)
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matcher::Naive => write!(f, "naive"),
+            Matcher::Pool => write!(f, "pool"),
+            Matcher::Fft => write!(f, "fft"),
+        }
+    }
+}
+
+// How a range is fitted from its domain and what the block's `beta` field holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Luminance {
+    // r = alpha * d + beta
+    Affine,
+    // Øien's orthogonal form, r = alpha * (d - mean(d)) + mean(r): the
+    // domain is mean-removed and `beta` stores the range mean, which
+    // decorrelates the two coefficients
+    Orthogonal,
+    // The orthogonal form plus a quadratic term in d and x/y ramps, for
+    // ranges with gradients the domain lacks (see `Polynomial`). Adds three
+    // coefficients per block.
+    Polynomial,
+}
+
+impl Luminance {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "affine" => Some(Luminance::Affine),
+            "orthogonal" => Some(Luminance::Orthogonal),
+            "polynomial" => Some(Luminance::Polynomial),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Luminance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Luminance::Affine => write!(f, "affine"),
+            Luminance::Orthogonal => write!(f, "orthogonal"),
+            Luminance::Polynomial => write!(f, "polynomial"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub block_size: usize,
+    pub stride: usize,
+    pub search: SearchMode,
+    // Quantize alpha/beta to this many bits each (None keeps full f32)
+    pub quant_bits: Option<u8>,
+    // Worker threads for the range search, the output does not depend on it
+    pub threads: usize,
+    pub matcher: Matcher,
+    pub luminance: Luminance,
+    // Search kernel of the GPU backend
+    pub gpu_kernel: Kernel,
+    // Let the GPU backend run on a software adapter (llvmpipe, WARP), which
+    // is usually slower than the CPU encoder and refuses searches longer
+    // than its loop limit
+    pub allow_software_gpu: bool,
+}
+
+impl EncodeOptions {
+    pub fn new(block_size: usize, stride: usize) -> Self {
+        Self {
+            block_size,
+            stride,
+            search: SearchMode::Full,
+            quant_bits: None,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            matcher: Matcher::Pool,
+            luminance: Luminance::Affine,
+            gpu_kernel: Kernel::Reduction,
+            allow_software_gpu: false,
+        }
+    }
+}
+
+// `range` and `domain_stats` hold the blocks' sums, e.g. from the integral
+// image. They are the same for every isometry of a domain, so only the
+// cross term and the error are computed from the pixels.
+pub fn encode_block<R, D>(
+    range_block: &R,
+    range: BlockStats,
+    domain_blocks: &[D],
+    domain_stats: &[BlockStats],
+    options: &EncodeOptions,
+) -> EncodedBlock
+where
+    R: BlockPixels + ?Sized,
+    D: BlockPixels,
+{
+    assert_eq!(domain_blocks.len(), domain_stats.len(), "every domain needs its stats");
+    if options.luminance == Luminance::Polynomial {
+        return encode_block_polynomial(range_block, range, domain_blocks, domain_stats, options);
+    }
 
-pub fn encode_block(
-    range_block: &[f32],
-    domain_blocks: &[Vec<f32>],
-    block_size: usize,
-) -> EncodedBlock {
+    let range_block = range_block.contiguous();
+    let n = range_block.len() as f32;
     let mut best_mse = f32::MAX;
     let mut best_meta = 0u32;
     let mut best_alpha = 0.0;
     let mut best_beta = 0.0;
 
-    for (domain_idx, domain) in domain_blocks.iter().enumerate() {
-        for transform_id in 0..8 {
-            let transformed = apply_d4_transform(domain, block_size, transform_id);
-            let (alpha, beta) = compute_alpha_beta(&transformed, &range_block);
-            let mse = compute_mse(&transformed, &range_block, alpha, beta);
+    for (domain_idx, (domain, stats)) in domain_blocks.iter().zip(domain_stats).enumerate() {
+        for transform_id in options.search.transform_ids() {
+            let transformed = apply_d4_transform(domain, options.block_size, transform_id);
+            let sums = BlockSums {
+                n,
+                sum_d: stats.sum,
+                sum_r: range.sum,
+                sum_dr: simd::dot(&transformed, &range_block),
+                sum_d2: stats.sum_sq,
+                sum_r2: range.sum_sq,
+            };
+            let (mut alpha, mut beta) = sums.coefficients(options.luminance);
+            if let Some(bits) = options.quant_bits {
+                (alpha, beta) = sums.quantized_coefficients(alpha, bits, options.luminance);
+            }
+            let affine = affine_beta(alpha, beta, stats.sum / n, options.luminance);
+            let mse = compute_mse(&transformed, &*range_block, alpha, affine);
             if mse < best_mse {
                 best_mse = mse;
                 best_meta = ((transform_id as u32) << 16) | (domain_idx as u32 & 0xFFFF);
@@ -50,24 +267,119 @@ pub fn encode_block(
         }
     }
 
-    EncodedBlock {
-        meta: best_meta,
-        _unused: 0,
-        alpha: best_alpha,
-        beta: best_beta,
+    EncodedBlock::affine(best_meta, best_alpha, best_beta).with_error(best_mse)
+}
+
+// `encode_block` for the polynomial model: five coefficients per candidate,
+// solved from the normal equations
+fn encode_block_polynomial<R, D>(
+    range_block: &R,
+    range: BlockStats,
+    domain_blocks: &[D],
+    domain_stats: &[BlockStats],
+    options: &EncodeOptions,
+) -> EncodedBlock
+where
+    R: BlockPixels + ?Sized,
+    D: BlockPixels,
+{
+    let range = polynomial::RangeMoments::new(range_block, range, options.block_size);
+    let mut best_mse = f32::MAX;
+    let mut best = EncodedBlock::zeroed();
+
+    for (domain_idx, (domain, &stats)) in domain_blocks.iter().zip(domain_stats).enumerate() {
+        for transform_id in options.search.transform_ids() {
+            let transformed = apply_d4_transform(domain, options.block_size, transform_id);
+            let moments = polynomial::DomainMoments::new(&transformed, stats, options.block_size);
+            let (sum_dr, sum_d2r) = polynomial::cross_sums(&transformed, range_block);
+            let (coefficients, mse) = polynomial::fit(&moments, &range, sum_dr, sum_d2r);
+            if mse < best_mse {
+                best_mse = mse;
+                let meta = ((transform_id as u32) << 16) | (domain_idx as u32 & 0xFFFF);
+                best = EncodedBlock::polynomial(meta, &coefficients).with_error(mse);
+            }
+        }
     }
+
+    best
 }
 
-pub fn encode_image(img_path: &Path, block_size: usize, stride: usize) {
-    println!("Trying to load: {}", img_path.display());
-    let img = image::open(img_path).expect("Failed to open image!");
-    let gs_image = img.to_luma8();
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Cpu,
+    Gpu,
+    // The GPU when there is a usable adapter and it supports the options,
+    // the CPU otherwise
+    Auto,
+}
+
+// The encoder that actually ran
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BackendUsed {
+    Cpu { threads: usize },
+    Gpu { adapter: String },
+}
 
+impl fmt::Display for BackendUsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendUsed::Cpu { threads } => write!(f, "CPU ({threads} threads)"),
+            BackendUsed::Gpu { adapter } => write!(f, "GPU ({adapter})"),
+        }
+    }
+}
+
+// Domain indices are stored in 16 bits of `EncodedBlock::meta`
+pub const MAX_DOMAIN_BLOCKS: usize = 1 << 16;
+
+pub fn check_encode_params(
+    width: usize,
+    height: usize,
+    options: &EncodeOptions,
+) -> Result<(), String> {
+    let EncodeOptions {
+        block_size, stride, ..
+    } = *options;
+    if let Some(bits) = options.quant_bits
+        && !(1..=16).contains(&bits)
+    {
+        return Err(format!("quantization bits must be between 1 and 16, got {bits}"));
+    }
+    if options.luminance == Luminance::Polynomial {
+        if options.quant_bits.is_some() {
+            return Err("the polynomial luminance model can't be quantized yet".to_string());
+        }
+        if options.matcher == Matcher::Fft {
+            return Err("the fft matcher doesn't support the polynomial luminance model; use naive or pool".to_string());
+        }
+    }
+    if options.threads == 0 {
+        return Err("thread count must be at least 1".to_string());
+    }
+    if block_size == 0 || block_size > u8::MAX as usize {
+        return Err(format!("block size must be between 1 and 255, got {block_size}"));
+    }
+    if stride == 0 || stride > u8::MAX as usize {
+        return Err(format!("stride must be between 1 and 255, got {stride}"));
+    }
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("image is {width}x{height}, the .fic format allows at most 65535x65535"));
+    }
+    if block_size > width || block_size > height {
+        return Err(format!("block size {block_size} does not fit a {width}x{height} image"));
+    }
+    let domains = ((width - block_size) / stride + 1) * ((height - block_size) / stride + 1);
+    if domains > MAX_DOMAIN_BLOCKS {
+        return Err(format!(
+            "{domains} domain blocks exceed the 16-bit domain index ({MAX_DOMAIN_BLOCKS}); use a larger stride"
+        ));
+    }
+    Ok(())
+}
+
+pub fn encode_luma(gs_image: &GrayImage, options: &EncodeOptions) -> Result<Vec<EncodedBlock>, String> {
     let (width, height) = gs_image.dimensions();
-    println!(
-        "Loaded image: {:?} with width: {} and height: {}",
-        img_path, width, height
-    );
+    check_encode_params(width as usize, height as usize, options)?;
 
     let image_data: Vec<f32> = gs_image.pixels().map(|p| p[0] as f32).collect();
 
@@ -75,31 +387,218 @@ pub fn encode_image(img_path: &Path, block_size: usize, stride: usize) {
         image_data,
         width as usize,
         height as usize,
-        block_size,
-        stride,
+        options.block_size,
+        options.stride,
     );
-    let range_blocks = extractor.extract_range_blocks();
+    let range_blocks: Vec<BlockView> = extractor.range_views().collect();
+    let range_stats = extractor.range_stats();
+    println!("Extracted {} range blocks!", range_blocks.len());
 
-    let domain_blocks = extractor.extract_domain_blocks();
+    let search = RangeSearch::new(&extractor, options);
+    Ok(encode_ranges(&range_blocks, &range_stats, &search, options))
+}
 
-    println!("Extracted {} range blocks!", range_blocks.len());
-    println!("Extracted {} domain blocks!", domain_blocks.len());
+// The state each matcher prepares once per image before searching ranges.
+// The naive search reads its domains straight out of the image, with their
+// sums from the integral image.
+pub enum RangeSearch<'a> {
+    Naive(Vec<BlockView<'a>>, Vec<BlockStats>),
+    Pool(DomainPool<'a>),
+    Fft(FftSearch),
+}
 
-    let mut encoded_blocks = Vec::new();
+impl<'a> RangeSearch<'a> {
+    pub fn new(extractor: &'a BlockExtractor, options: &EncodeOptions) -> Self {
+        if options.matcher == Matcher::Fft {
+            let search = FftSearch::new(extractor, options);
+            println!("Correlating against {} domain positions!", search.len());
+            return RangeSearch::Fft(search);
+        }
 
-    for range in &range_blocks {
-        let encoded = encode_block(range, &domain_blocks, block_size);
-        encoded_blocks.push(encoded);
+        if options.matcher == Matcher::Pool {
+            let pool = DomainPool::new(extractor, options);
+            println!("Extracted {} domain blocks ({} KiB pooled)!", pool.len(), pool.heap_bytes().div_ceil(1024));
+            return RangeSearch::Pool(pool);
+        }
+
+        let domain_blocks: Vec<BlockView> = extractor.domain_views().collect();
+        println!("Extracted {} domain blocks!", domain_blocks.len());
+        RangeSearch::Naive(domain_blocks, extractor.domain_stats())
+    }
+
+    // `range` holds the range's sums from the integral image
+    pub fn encode_range(
+        &self,
+        range_block: &BlockView,
+        range: BlockStats,
+        options: &EncodeOptions,
+    ) -> EncodedBlock {
+        match self {
+            RangeSearch::Naive(domain_blocks, domain_stats) => {
+                encode_block(range_block, range, domain_blocks, domain_stats, options)
+            }
+            RangeSearch::Pool(pool) => pool.encode_range(range_block, range, options),
+            RangeSearch::Fft(search) => search.encode_range(range_block, range, options),
+        }
     }
+}
+
+pub fn encode_ranges(
+    range_blocks: &[BlockView],
+    range_stats: &[BlockStats],
+    search: &RangeSearch,
+    options: &EncodeOptions,
+) -> Vec<EncodedBlock> {
+    encode_in_parallel(range_blocks.len(), options.threads, |i| {
+        search.encode_range(&range_blocks[i], range_stats[i], options)
+    })
+}
 
-    let output_path = Path::new("output.fic");
+// Every range is searched independently, so split them into one contiguous
+// chunk per thread. Each result lands at its range index, which keeps the
+// output identical to a single-threaded encode.
+pub fn encode_in_parallel<F>(ranges: usize, threads: usize, encode_range: F) -> Vec<EncodedBlock>
+where
+    F: Fn(usize) -> EncodedBlock + Sync,
+{
+    let mut encoded_blocks = vec![EncodedBlock::zeroed(); ranges];
+    let threads = threads.clamp(1, ranges.max(1));
+    let chunk_len = ranges.div_ceil(threads).max(1);
+    let encode_range = &encode_range;
 
-    save_fic_file(
-        output_path,
-        &encoded_blocks,
-        width as u16,
-        height as u16,
-        block_size as u8,
-        stride as u8,
+    thread::scope(|scope| {
+        for (chunk, out) in encoded_blocks.chunks_mut(chunk_len).enumerate() {
+            scope.spawn(move || {
+                for (i, encoded) in out.iter_mut().enumerate() {
+                    *encoded = encode_range(chunk * chunk_len + i);
+                }
+            });
+        }
+    });
+
+    encoded_blocks
+}
+
+pub fn encode_image(
+    img_path: &Path,
+    fic_path: &Path,
+    options: &EncodeOptions,
+) -> Result<(), Box<dyn Error>> {
+    println!("Trying to load: {}", img_path.display());
+    let img = image::open(img_path)?;
+    let gs_image = img.to_luma8();
+
+    let (width, height) = gs_image.dimensions();
+    println!(
+        "Loaded image: {:?} with width: {} and height: {}",
+        img_path, width, height
     );
+
+    let encoded_blocks = encode_luma(&gs_image, options)?;
+
+    let header = FicHeader::for_encode(width, height, options, encoded_blocks.len());
+    save_fic_file(fic_path, &header, &encoded_blocks)?;
+    Ok(())
+}
+
+// What the GPU backend can't do with these options, if anything
+fn gpu_unsupported(options: &EncodeOptions) -> Option<&'static str> {
+    (options.search != SearchMode::Full || options.quant_bits.is_some() || options.luminance != Luminance::Affine)
+        .then_some("the GPU backend only supports full search with the affine model and no quantization")
+}
+
+// A GPU context for a run of encodes: set up by the first one that uses the
+// GPU, with kernels from `shaders`, and reused by the rest, which wait for
+// each other's GPU work
+#[derive(Default)]
+pub struct GpuSession {
+    shaders: ShaderRegistry,
+    encoder: Mutex<Option<GpuEncoder>>,
+}
+
+impl GpuSession {
+    pub fn new(shaders: ShaderRegistry) -> Self {
+        Self {
+            shaders,
+            encoder: Mutex::new(None),
+        }
+    }
+}
+
+// The context in `slot`, set up on the first call that needs one
+fn gpu_encoder<'a>(
+    slot: &'a mut Option<GpuEncoder>,
+    shaders: &ShaderRegistry,
+    options: &EncodeOptions,
+) -> Result<&'a mut GpuEncoder, Box<dyn Error>> {
+    if let Some(encoder) = slot {
+        return Ok(encoder);
+    }
+    let adapter = gpu::encoder::request_adapter(options.allow_software_gpu).ok_or(if options.allow_software_gpu {
+        "no GPU adapter found"
+    } else {
+        "no hardware GPU adapter found (--gpu-adapter any allows software ones)"
+    })?;
+    Ok(slot.insert(GpuEncoder::new(&adapter)?.with_shaders(shaders.clone())))
+}
+
+fn encode_on_gpu(
+    gpu: &GpuSession,
+    img_path: &Path,
+    fic_path: &Path,
+    options: &EncodeOptions,
+) -> Result<BackendUsed, Box<dyn Error>> {
+    if let Some(reason) = gpu_unsupported(options) {
+        return Err(reason.into());
+    }
+    let (width, height) = image::image_dimensions(img_path)?;
+    check_encode_params(width as usize, height as usize, options)?;
+    let encode_params = gpu::encoder::EncodeParams {
+        image_width: width,
+        image_height: height,
+        range_size: options.block_size as u32,
+        domain_size: options.block_size as u32,
+        stride: options.stride as u32,
+        kernel: options.gpu_kernel,
+    };
+    // Every encode rewrites the buffers it reads, so a context whose last
+    // encode panicked is still fine to use
+    let mut slot = gpu.encoder.lock().unwrap_or_else(PoisonError::into_inner);
+    let encoder = gpu_encoder(&mut slot, &gpu.shaders, options)?;
+    encoder.encode_file(img_path, fic_path, encode_params)?;
+    Ok(BackendUsed::Gpu {
+        adapter: encoder.adapter_name().to_string(),
+    })
+}
+
+fn encode_on_cpu(img_path: &Path, fic_path: &Path, options: &EncodeOptions) -> Result<BackendUsed, Box<dyn Error>> {
+    encode_image(img_path, fic_path, options)?;
+    Ok(BackendUsed::Cpu {
+        threads: options.threads,
+    })
+}
+
+// Encodes on `backend` and returns what actually ran. `Auto` falls back to
+// the CPU encoder with the same options when there is no GPU adapter, the
+// options need the CPU, or the GPU encode fails, e.g. because the search is
+// too long for a software adapter's loop limit; both write the same .fic.
+// The GPU context is kept in `gpu` for the next image.
+pub fn encode_with_backend(
+    img_path: &Path,
+    fic_path: &Path,
+    options: &EncodeOptions,
+    backend: Backend,
+    gpu: &GpuSession,
+) -> Result<BackendUsed, Box<dyn Error>> {
+    match backend {
+        Backend::Cpu => encode_on_cpu(img_path, fic_path, options),
+        Backend::Gpu => encode_on_gpu(gpu, img_path, fic_path, options),
+        Backend::Auto => match encode_on_gpu(gpu, img_path, fic_path, options) {
+            Ok(used) => Ok(used),
+            Err(e) => {
+                println!("[backend] {e}, encoding on the CPU");
+                encode_on_cpu(img_path, fic_path, options)
+            }
+        },
+    }
 }
@@ -0,0 +1,494 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::decode;
+use crate::encode::{EncodedBlock, Luminance};
+use crate::util::{self, FicHeader};
+
+// Blocks of a still .fic as JSON or CSV, one record per range in range order:
+//   range, x, y        position of the range (derived, ignored on import)
+//   domain, transform  what the range is mapped from
+//   alpha, beta        and its luminance coefficients, plus gamma, ramp_x and
+//                      ramp_y for the polynomial model
+//   error              collage MSE the encoder recorded, or the one measured
+//                      against a reference image
+// Floats are written in Rust's shortest round-trip form (`{:?}`), so
+// importing an untouched export rebuilds the .fic bit for bit. The header goes
+// in the JSON object, or in a leading `# width=.. height=..` line of the CSV.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    // Picked from the file extension
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("json") => Ok(ExportFormat::Json),
+            Some("csv") => Ok(ExportFormat::Csv),
+            _ => Err(format!("{}: expected a .json or .csv file", path.display())),
+        }
+    }
+}
+
+const POLYNOMIAL_COLUMNS: [&str; 3] = ["gamma", "ramp_x", "ramp_y"];
+
+fn columns(luminance: Luminance) -> Vec<&'static str> {
+    let mut columns = vec!["range", "x", "y", "domain", "transform", "alpha", "beta"];
+    if luminance == Luminance::Polynomial {
+        columns.extend(POLYNOMIAL_COLUMNS);
+    }
+    columns.push("error");
+    columns
+}
+
+// One value per column, already formatted
+fn row(header: &FicHeader, index: usize, block: &EncodedBlock) -> Vec<String> {
+    let (x, y) = header.range_origin(index);
+    let mut row = vec![
+        index.to_string(),
+        x.to_string(),
+        y.to_string(),
+        block.domain_index().to_string(),
+        block.transform_id().to_string(),
+        format!("{:?}", block.alpha),
+        format!("{:?}", block.beta),
+    ];
+    if header.luminance == Luminance::Polynomial {
+        row.extend([block.gamma, block.ramp_x, block.ramp_y].map(|v| format!("{v:?}")));
+    }
+    row.push(format!("{:?}", block.error));
+    row
+}
+
+// Only what the columns describe can be exported, and JSON has no NaN
+fn check_exportable(blocks: &[EncodedBlock]) -> Result<(), String> {
+    for (i, block) in blocks.iter().enumerate() {
+        let meta = (block.transform_id() as u32) << 16 | block.domain_index() as u32;
+        if block.meta != meta {
+            return Err(format!("block {i} has unknown bits set in meta ({:#010x})", block.meta));
+        }
+        let values = [block.alpha, block.beta, block.gamma, block.ramp_x, block.ramp_y, block.error];
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err(format!("block {i} has a non-finite value"));
+        }
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(writer: &mut W, header: &FicHeader, blocks: &[EncodedBlock]) -> Result<(), Box<dyn Error>> {
+    check_exportable(blocks)?;
+    let columns = columns(header.luminance);
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"width\": {},", header.width)?;
+    writeln!(writer, "  \"height\": {},", header.height)?;
+    writeln!(writer, "  \"block_size\": {},", header.block_size)?;
+    writeln!(writer, "  \"stride\": {},", header.stride)?;
+    writeln!(writer, "  \"luminance\": \"{}\",", header.luminance)?;
+    writeln!(writer, "  \"blocks\": [")?;
+    for (i, block) in blocks.iter().enumerate() {
+        let fields: Vec<String> = columns
+            .iter()
+            .zip(row(header, i, block))
+            .map(|(name, value)| format!("\"{name}\": {value}"))
+            .collect();
+        let separator = if i + 1 < blocks.len() { "," } else { "" };
+        writeln!(writer, "    {{{}}}{separator}", fields.join(", "))?;
+    }
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+pub fn write_csv<W: Write>(writer: &mut W, header: &FicHeader, blocks: &[EncodedBlock]) -> Result<(), Box<dyn Error>> {
+    check_exportable(blocks)?;
+    writeln!(
+        writer,
+        "# width={} height={} block_size={} stride={} luminance={}",
+        header.width, header.height, header.block_size, header.stride, header.luminance
+    )?;
+    writeln!(writer, "{}", columns(header.luminance).join(","))?;
+    for (i, block) in blocks.iter().enumerate() {
+        writeln!(writer, "{}", row(header, i, block).join(","))?;
+    }
+    Ok(())
+}
+
+// Integer columns may come back from a notebook as floats ("12.0")
+fn parse_integer(value: &str, what: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .ok()
+        .or_else(|| {
+            let float: f64 = value.parse().ok()?;
+            (float >= 0.0 && float.fract() == 0.0 && float <= u32::MAX as f64).then_some(float as u64)
+        })
+        .ok_or_else(|| format!("{what}: expected a whole number, got '{value}'"))
+}
+
+fn parse_float(value: &str, what: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| format!("{what}: expected a number, got '{value}'"))
+}
+
+fn header_from_fields<'a>(field: impl Fn(&str) -> Result<&'a str, String>) -> Result<FicHeader, String> {
+    let dimension = |name: &str, max: u64| -> Result<u64, String> {
+        let value = parse_integer(field(name)?, name)?;
+        if value > max {
+            return Err(format!("{name}: {value} is too large"));
+        }
+        Ok(value)
+    };
+    let luminance = field("luminance")?;
+    let header = FicHeader {
+        width: dimension("width", u16::MAX as u64)? as u16,
+        height: dimension("height", u16::MAX as u64)? as u16,
+        block_size: dimension("block_size", u8::MAX as u64)? as u8,
+        stride: dimension("stride", u8::MAX as u64)? as u8,
+        num_blocks: 0,
+        luminance: Luminance::parse(luminance).ok_or_else(|| format!("luminance: unknown model '{luminance}'"))?,
+    };
+    header.check()?;
+    Ok(header)
+}
+
+// Rebuilds block `index` from its record. `field` returns None for a column
+// the record doesn't have.
+fn block_from_fields<'a>(
+    header: &FicHeader,
+    index: usize,
+    field: impl Fn(&str) -> Option<&'a str>,
+) -> Result<EncodedBlock, String> {
+    let what = |name: &str| format!("block {index}, {name}");
+    let required = |name: &str| field(name).ok_or_else(|| format!("block {index}: missing {name}"));
+
+    if let Some(range) = field("range") {
+        let range = parse_integer(range, &what("range"))?;
+        if range != index as u64 {
+            return Err(format!("block {index}: found range {range}, the blocks must be in range order"));
+        }
+    }
+    let domains = header.domains_per_row() * header.domains_per_col();
+    let domain = parse_integer(required("domain")?, &what("domain"))?;
+    if domain >= domains.min(0x10000) as u64 {
+        return Err(format!("block {index}: domain {domain} is out of range (the image has {domains})"));
+    }
+    let transform = parse_integer(required("transform")?, &what("transform"))?;
+    if transform >= 8 {
+        return Err(format!("block {index}: transform {transform} is out of range (0-7)"));
+    }
+    let meta = (transform as u32) << 16 | domain as u32;
+
+    let alpha = parse_float(required("alpha")?, &what("alpha"))?;
+    let beta = parse_float(required("beta")?, &what("beta"))?;
+    // Edited blocks may drop the error rather than recompute it
+    let error = field("error").map(|e| parse_float(e, &what("error"))).transpose()?;
+    let block = EncodedBlock::affine(meta, alpha, beta).with_error(error.unwrap_or(0.0));
+    if header.luminance != Luminance::Polynomial {
+        return Ok(block);
+    }
+    let [gamma, ramp_x, ramp_y] = POLYNOMIAL_COLUMNS.map(|name| parse_float(required(name)?, &what(name)));
+    Ok(EncodedBlock {
+        gamma: gamma?,
+        ramp_x: ramp_x?,
+        ramp_y: ramp_y?,
+        ..block
+    })
+}
+
+pub fn parse_json(text: &str) -> Result<(FicHeader, Vec<EncodedBlock>), String> {
+    let root = JsonParser::parse(text)?;
+    let object = root.as_object("the top level")?;
+    let header = header_from_fields(|name| {
+        let value = field(object, name).ok_or_else(|| format!("missing {name}"))?;
+        value.as_scalar().ok_or_else(|| format!("{name}: expected a number or a string"))
+    })?;
+
+    let records = match field(object, "blocks") {
+        Some(Json::Array(records)) => records,
+        Some(_) => return Err("blocks: expected an array".to_string()),
+        None => return Err("missing blocks".to_string()),
+    };
+    let blocks = records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let record = record.as_object(&format!("block {i}"))?;
+            block_from_fields(&header, i, |name| field(record, name).and_then(Json::as_scalar))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((FicHeader { num_blocks: blocks.len() as u32, ..header }, blocks))
+}
+
+// `fallback` supplies the header when the CSV lost its comment line, as it
+// does after a trip through most dataframe libraries
+pub fn parse_csv(text: &str, fallback: Option<FicHeader>) -> Result<(FicHeader, Vec<EncodedBlock>), String> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty()).peekable();
+
+    let mut header = fallback;
+    if let Some(comment) = lines.next_if(|line| line.starts_with('#')) {
+        let pairs: Vec<(&str, &str)> = comment[1..]
+            .split_whitespace()
+            .map(|pair| pair.split_once('=').ok_or_else(|| format!("header line: expected key=value, got '{pair}'")))
+            .collect::<Result<_, _>>()?;
+        header = Some(header_from_fields(|name| {
+            pairs
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| format!("header line: missing {name}"))
+        })?);
+    }
+    let header = header.ok_or("the CSV has no `# width=...` header line, pass `--header <file.fic>` to take it from an encoded file")?;
+
+    let names: Vec<&str> = lines
+        .next()
+        .ok_or("the CSV has no column names")?
+        .split(',')
+        .map(|name| name.trim().trim_matches('"'))
+        .collect();
+    let blocks = lines
+        .enumerate()
+        .map(|(i, line)| {
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values.len() != names.len() {
+                return Err(format!("block {i}: {} values for {} columns", values.len(), names.len()));
+            }
+            block_from_fields(&header, i, |name| {
+                names.iter().position(|n| *n == name).map(|column| values[column])
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((FicHeader { num_blocks: blocks.len() as u32, ..header }, blocks))
+}
+
+pub fn export_file(fic_path: &Path, output: &Path, reference: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let format = ExportFormat::from_path(output)?;
+    let (header, mut blocks) = util::read_fic_file(fic_path)?;
+    if let Some(reference) = reference {
+        let errors = decode::collage_errors(&header, &blocks, &image::open(reference)?.to_luma8())?;
+        for (block, error) in blocks.iter_mut().zip(errors) {
+            *block = block.with_error(error);
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    match format {
+        ExportFormat::Json => write_json(&mut writer, &header, &blocks)?,
+        ExportFormat::Csv => write_csv(&mut writer, &header, &blocks)?,
+    }
+    writer.flush()?;
+    println!("Exported {} blocks of {} to {}", blocks.len(), fic_path.display(), output.display());
+    Ok(())
+}
+
+// `header_from` is only read when a CSV has no header line of its own
+pub fn import_file(input: &Path, fic_path: &Path, header_from: Option<&Path>) -> Result<FicHeader, Box<dyn Error>> {
+    let text = fs::read_to_string(input)?;
+    let (header, blocks) = match ExportFormat::from_path(input)? {
+        ExportFormat::Json => parse_json(&text)?,
+        ExportFormat::Csv => {
+            let fallback = header_from.map(util::read_fic_header).transpose()?;
+            parse_csv(&text, fallback)?
+        }
+    };
+    util::save_fic_file(fic_path, &header, &blocks)?;
+    println!("Imported {} blocks from {} to {}", blocks.len(), input.display(), fic_path.display());
+    Ok(header)
+}
+
+// Just enough JSON to read an export back after a notebook has rewritten it.
+// Numbers keep their text so each field is parsed straight to its own type.
+#[derive(Debug)]
+enum Json {
+    Null,
+    // Booleans appear in no field the importer reads
+    Bool,
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn as_object(&self, what: &str) -> Result<&[(String, Json)], String> {
+        match self {
+            Json::Object(fields) => Ok(fields),
+            _ => Err(format!("{what}: expected an object")),
+        }
+    }
+
+    fn as_scalar(&self) -> Option<&str> {
+        match self {
+            Json::Number(text) | Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+// A null field counts as missing, which is how dataframes write empty cells
+fn field<'a>(object: &'a [(String, Json)], name: &str) -> Option<&'a Json> {
+    object
+        .iter()
+        .rev()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !matches!(value, Json::Null))
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn parse(text: &'a str) -> Result<Json, String> {
+        let mut parser = Self { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        format!("invalid JSON at line {line}: {message}")
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // Consumes `byte` if it comes next
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => Ok(self.number()),
+            Some(_) => {
+                for (literal, value) in [("null", Json::Null), ("true", Json::Bool), ("false", Json::Bool)] {
+                    if self.text[self.pos..].starts_with(literal) {
+                        self.pos += literal.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if self.eat(b'}') {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            if !self.eat(b',') {
+                self.expect(b'}')?;
+                return Ok(Json::Object(fields));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.eat(b']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if !self.eat(b',') {
+                self.expect(b']')?;
+                return Ok(Json::Array(items));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error(&format!("unsupported escape \\u{hex}")))?
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    out.push(escaped);
+                }
+                c => out.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn number(&mut self) -> Json {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        Json::Number(self.text[start..self.pos].to_string())
+    }
+}
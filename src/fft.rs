@@ -0,0 +1,169 @@
+use std::f64::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+// Iterative radix-2 FFT for one power-of-two length, with the twiddles and
+// bit-reversal permutation computed once
+pub struct Fft {
+    len: usize,
+    twiddles: Vec<Complex>,
+    bit_reverse: Vec<usize>,
+}
+
+impl Fft {
+    pub fn new(len: usize) -> Self {
+        assert!(len.is_power_of_two(), "FFT length must be a power of two, got {len}");
+        let bits = len.trailing_zeros();
+        let twiddles = (0..len / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f64 / len as f64;
+                Complex::new(angle.cos(), angle.sin())
+            })
+            .collect();
+        let bit_reverse = (0..len)
+            .map(|i| if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) })
+            .collect();
+        Self {
+            len,
+            twiddles,
+            bit_reverse,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // In place. The inverse includes the 1/len scaling.
+    pub fn process(&self, data: &mut [Complex], inverse: bool) {
+        self.process_columns(data, 1, inverse);
+    }
+
+    // Transforms each column of a row-major grid with `len` rows. The
+    // butterflies combine whole rows at a time, which keeps the memory
+    // access sequential instead of striding down every column.
+    pub fn process_columns(&self, data: &mut [Complex], width: usize, inverse: bool) {
+        assert_eq!(data.len(), self.len * width, "FFT input has the wrong length");
+        for i in 0..self.len {
+            let j = self.bit_reverse[i];
+            if i < j {
+                let (low, high) = data.split_at_mut(j * width);
+                low[i * width..(i + 1) * width].swap_with_slice(&mut high[..width]);
+            }
+        }
+
+        let mut half = 1;
+        while half < self.len {
+            let step = self.len / (2 * half);
+            for start in (0..self.len).step_by(2 * half) {
+                for k in 0..half {
+                    let mut w = self.twiddles[k * step];
+                    if inverse {
+                        w.im = -w.im;
+                    }
+                    let (top, bottom) = data.split_at_mut((start + k + half) * width);
+                    let evens = &mut top[(start + k) * width..(start + k + 1) * width];
+                    let odds = &mut bottom[..width];
+                    for (even, odd) in evens.iter_mut().zip(odds.iter_mut()) {
+                        let t = *odd * w;
+                        *odd = *even - t;
+                        *even = *even + t;
+                    }
+                }
+            }
+            half *= 2;
+        }
+
+        if inverse {
+            let scale = 1.0 / self.len as f64;
+            for v in data.iter_mut() {
+                v.re *= scale;
+                v.im *= scale;
+            }
+        }
+    }
+}
+
+// 2D FFT over a row-major width x height grid, both powers of two
+pub struct Fft2d {
+    rows: Fft,
+    cols: Fft,
+}
+
+impl Fft2d {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            rows: Fft::new(width),
+            cols: Fft::new(height),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.cols.len()
+    }
+
+    // Forward transform of a grid whose other rows are all zero, so their
+    // row pass can be skipped
+    pub fn forward_rows(&self, data: &mut [Complex], nonzero_rows: impl IntoIterator<Item = usize>) {
+        let width = self.width();
+        for y in nonzero_rows {
+            self.rows.process(&mut data[y * width..(y + 1) * width], false);
+        }
+        self.columns(data, false);
+    }
+
+    // Inverse transform that only finishes the requested rows; the others
+    // are left half transformed
+    pub fn inverse_rows(&self, data: &mut [Complex], wanted_rows: impl IntoIterator<Item = usize>) {
+        let width = self.width();
+        self.columns(data, true);
+        for y in wanted_rows {
+            self.rows.process(&mut data[y * width..(y + 1) * width], true);
+        }
+    }
+
+    fn columns(&self, data: &mut [Complex], inverse: bool) {
+        self.cols.process_columns(data, self.width(), inverse);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::alpha_beta::BlockSums;
+use crate::block_extractor::{BlockExtractor, BlockPixels, BlockStats, BlockView};
+use crate::encode::{EncodeOptions, EncodedBlock};
+use crate::fft::{Complex, Fft2d};
+use crate::transform::apply_d4_transform;
+
+// Exhaustive search where the dot products of a range with every domain
+// position come from one cross-correlation over the whole image. The image
+// spectrum is computed once; each range then costs a forward and an inverse
+// FFT per pair of isometries (two real kernels share one complex FFT, in
+// the real and imaginary parts). Block sums come from the integral image.
+//
+// The grid is padded to powers of two at least as large as the image, so
+// the circular correlation never wraps for a domain that fits the image.
+pub struct FftSearch {
+    fft: Fft2d,
+    image_spectrum: Vec<Complex>,
+    block_size: usize,
+    stride: usize,
+    domains_per_row: usize,
+    domains_per_col: usize,
+    stats: Vec<BlockStats>,
+    // For each transform, where range pixel p goes in the correlation kernel
+    kernel_positions: Vec<Vec<usize>>,
+}
+
+impl FftSearch {
+    pub fn new(extractor: &BlockExtractor, options: &EncodeOptions) -> Self {
+        let (width, height) = (extractor.width, extractor.height);
+        let block_size = options.block_size;
+        let stride = options.stride;
+        let domains_per_row = (width - block_size) / stride + 1;
+        let domains_per_col = (height - block_size) / stride + 1;
+
+        let fft = Fft2d::new(width.next_power_of_two(), height.next_power_of_two());
+        let mut image_spectrum = vec![Complex::ZERO; fft.width() * fft.height()];
+        for y in 0..height {
+            for x in 0..width {
+                image_spectrum[y * fft.width() + x].re = extractor.image[y * width + x] as f64;
+            }
+        }
+        fft.forward_rows(&mut image_spectrum, 0..height);
+
+        // Transforming a block of pixel indices gives the permutation each
+        // isometry applies
+        let indices: Vec<f32> = (0..block_size * block_size).map(|i| i as f32).collect();
+        let kernel_positions = options
+            .search
+            .transform_ids()
+            .map(|transform_id| {
+                apply_d4_transform(&indices, block_size, transform_id)
+                    .iter()
+                    .map(|&i| i as usize)
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            fft,
+            image_spectrum,
+            block_size,
+            stride,
+            domains_per_row,
+            domains_per_col,
+            stats: extractor.domain_stats(),
+            kernel_positions,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    // Same candidates in the same order as `encode_block`, so ties resolve
+    // to the same domain and transform
+    pub fn encode_range(
+        &self,
+        range_block: &BlockView,
+        range: BlockStats,
+        options: &EncodeOptions,
+    ) -> EncodedBlock {
+        let n = range_block.pixel_count() as f32;
+
+        let transforms = self.kernel_positions.len();
+        let correlations: Vec<Vec<Complex>> = (0..transforms)
+            .step_by(2)
+            .map(|t| self.correlate(range_block, t, (t + 1 < transforms).then_some(t + 1)))
+            .collect();
+
+        let mut best_mse = f32::MAX;
+        let mut best_meta = 0u32;
+        let mut best_alpha = 0.0;
+        let mut best_beta = 0.0;
+
+        for domain_idx in 0..self.len() {
+            let x = domain_idx % self.domains_per_row * self.stride;
+            let y = domain_idx / self.domains_per_row * self.stride;
+            let pos = y * self.fft.width() + x;
+
+            for transform_id in 0..transforms {
+                let c = correlations[transform_id / 2][pos];
+                let sums = BlockSums {
+                    n,
+                    sum_d: self.stats[domain_idx].sum,
+                    sum_r: range.sum,
+                    sum_dr: if transform_id % 2 == 0 { c.re } else { c.im } as f32,
+                    sum_d2: self.stats[domain_idx].sum_sq,
+                    sum_r2: range.sum_sq,
+                };
+                let (alpha, beta, mse) = sums.fit(options.quant_bits, options.luminance);
+                if mse < best_mse {
+                    best_mse = mse;
+                    best_meta = ((transform_id as u32) << 16) | (domain_idx as u32 & 0xFFFF);
+                    best_alpha = alpha;
+                    best_beta = beta;
+                }
+            }
+        }
+
+        EncodedBlock::affine(best_meta, best_alpha, best_beta).with_error(best_mse)
+    }
+
+    // Correlation of the image with the range as seen through transform
+    // `first` (real part) and optionally `second` (imaginary part). Only the
+    // rows holding domain origins are fully inverted.
+    fn correlate(&self, range_block: &BlockView, first: usize, second: Option<usize>) -> Vec<Complex> {
+        let (fft_w, fft_h) = (self.fft.width(), self.fft.height());
+        let b = self.block_size;
+
+        // Kernel k[q] = range[p] where q is the pixel the transform moves p
+        // to, stored flipped so a convolution gives the correlation
+        let flipped = |q: usize| {
+            let (j, i) = (q / b, q % b);
+            ((fft_h - j) % fft_h) * fft_w + (fft_w - i) % fft_w
+        };
+        let mut kernel = vec![Complex::ZERO; fft_w * fft_h];
+        for (&q, r) in self.kernel_positions[first].iter().zip(range_block.pixels()) {
+            kernel[flipped(q)].re = r as f64;
+        }
+        if let Some(second) = second {
+            for (&q, r) in self.kernel_positions[second].iter().zip(range_block.pixels()) {
+                kernel[flipped(q)].im = r as f64;
+            }
+        }
+
+        self.fft.forward_rows(&mut kernel, (0..b).map(|j| (fft_h - j) % fft_h));
+        for (k, s) in kernel.iter_mut().zip(&self.image_spectrum) {
+            *k = *k * *s;
+        }
+        self.fft
+            .inverse_rows(&mut kernel, (0..self.domains_per_col).map(|dy| dy * self.stride));
+        kernel
+    }
+}
@@ -0,0 +1,178 @@
+use crate::encode::EncodedBlock;
+use crate::gpu::encoder::EncodeParams;
+use crate::gpu::shader::{Kernel, REDUCTION_WORKGROUP_SIZE};
+
+// Same bias and early exit as the kernel
+const DENOMINATOR_BIAS: f32 = 0.0001;
+const EARLY_EXIT_MSE: f32 = 0.0001;
+
+// Line-by-line port of the `params.kernel` shader for checking the GPU
+// encoder where there is no GPU: every range, in dispatch order, searched
+// with the kernel's f32 arithmetic, domain order and early exit. The whole
+// image is one tile and one domain band, which tiling mustn't change.
+pub fn transform_and_compare(image: &[f32], params: &EncodeParams) -> Vec<EncodedBlock> {
+    let search = match params.kernel {
+        Kernel::Serial => search_range,
+        Kernel::Reduction => reduce_range,
+    };
+    let block_size = params.range_size;
+    let range_blocks_x = (params.image_width - block_size) / block_size + 1;
+    let range_blocks_y = (params.image_height - block_size) / block_size + 1;
+    let mut output = Vec::with_capacity((range_blocks_x * range_blocks_y) as usize);
+    for y in 0..range_blocks_y {
+        for x in 0..range_blocks_x {
+            output.push(search(image, params, (x * block_size, y * block_size)));
+        }
+    }
+    output
+}
+
+fn pixel(image: &[f32], params: &EncodeParams, (x, y): (u32, u32), index: u32) -> f32 {
+    let block_size = params.range_size;
+    image[((y + index / block_size) * params.image_width + x + index % block_size) as usize]
+}
+
+fn d4_index(index: u32, tid: u32, block_size: u32) -> u32 {
+    let (x, y) = (index % block_size, index / block_size);
+    let last = block_size - 1;
+    let (tx, ty) = match tid {
+        1 => (last - y, x),
+        2 => (last - x, last - y),
+        3 => (y, last - x),
+        4 => (last - x, y),
+        5 => (x, last - y),
+        6 => (y, x),
+        7 => (last - y, last - x),
+        _ => (x, y),
+    };
+    ty * block_size + tx
+}
+
+fn compute_ab_mse(
+    image: &[f32],
+    params: &EncodeParams,
+    range: (u32, u32),
+    domain: (u32, u32),
+    tid: u32,
+    (sum_a, sum_b, sum_bb): (f32, f32, f32),
+) -> (f32, f32, f32) {
+    let block_size = params.range_size;
+    let area = block_size * block_size;
+    let n = area as f32;
+
+    let mut sum_ab = 0.0;
+    for i in 0..area {
+        sum_ab += pixel(image, params, range, d4_index(i, tid, block_size)) * pixel(image, params, domain, i);
+    }
+
+    let alpha = (n * sum_ab - sum_a * sum_b) / (n * sum_bb - sum_b * sum_b + DENOMINATOR_BIAS);
+    let beta = (sum_a - alpha * sum_b) / n;
+
+    let mut mse = 0.0;
+    for i in 0..area {
+        let diff = pixel(image, params, range, d4_index(i, tid, block_size))
+            - (alpha * pixel(image, params, domain, i) + beta);
+        mse += diff * diff;
+    }
+
+    (alpha, beta, mse / n)
+}
+
+fn search_range(image: &[f32], params: &EncodeParams, range: (u32, u32)) -> EncodedBlock {
+    let block_size = params.range_size;
+    let area = block_size * block_size;
+    let sum_a: f32 = (0..area).map(|i| pixel(image, params, range, i)).sum();
+
+    let mut best_mse = 1e10;
+    let mut best_tid = 0;
+    let mut best_did = 0;
+    let mut best_ab = (0.0, 0.0);
+
+    let mut did = 0;
+    'search: for dy in (0..).step_by(params.stride as usize).take_while(|dy| dy + block_size <= params.image_height) {
+        for dx in (0..).step_by(params.stride as usize).take_while(|dx| dx + block_size <= params.image_width) {
+            let domain = (dx, dy);
+            let (mut sum_b, mut sum_bb) = (0.0, 0.0);
+            for i in 0..area {
+                let b = pixel(image, params, domain, i);
+                sum_b += b;
+                sum_bb += b * b;
+            }
+
+            for tid in 0..8 {
+                let (alpha, beta, mse) = compute_ab_mse(image, params, range, domain, tid, (sum_a, sum_b, sum_bb));
+                if mse < best_mse {
+                    best_mse = mse;
+                    best_ab = (alpha, beta);
+                    best_tid = tid;
+                    best_did = did;
+                    if best_mse < EARLY_EXIT_MSE {
+                        break 'search;
+                    }
+                }
+            }
+            did += 1;
+        }
+    }
+
+    EncodedBlock::affine((best_tid << 16) | (best_did & 0xFFFF), best_ab.0, best_ab.1).with_error(best_mse)
+}
+
+// `better` in `transform_and_compare_reduction.wgsl`
+fn better((mse_a, candidate_a): (f32, u32), (mse_b, candidate_b): (f32, u32)) -> bool {
+    let key_a = if mse_a < EARLY_EXIT_MSE { 0.0 } else { mse_a };
+    let key_b = if mse_b < EARLY_EXIT_MSE { 0.0 } else { mse_b };
+    key_a < key_b || (key_a == key_b && candidate_a < candidate_b)
+}
+
+// One workgroup of the reduction kernel: each thread's share of the domains,
+// then the tree reduction over the threads' bests
+fn reduce_range(image: &[f32], params: &EncodeParams, range: (u32, u32)) -> EncodedBlock {
+    let block_size = params.range_size;
+    let area = block_size * block_size;
+    let sum_a: f32 = (0..area).map(|i| pixel(image, params, range, i)).sum();
+    let domains_per_row = (params.image_width - block_size) / params.stride + 1;
+    let domains = ((params.image_height - block_size) / params.stride + 1) * domains_per_row;
+
+    let mut best: Vec<(f32, u32, (f32, f32))> = (0..REDUCTION_WORKGROUP_SIZE)
+        .map(|lid| {
+            let (mut mse, mut candidate, mut ab) = (1e10, u32::MAX, (0.0, 0.0));
+            let mut d = lid;
+            while d < domains && mse >= EARLY_EXIT_MSE {
+                let domain = ((d % domains_per_row) * params.stride, (d / domains_per_row) * params.stride);
+                let (mut sum_b, mut sum_bb) = (0.0, 0.0);
+                for i in 0..area {
+                    let b = pixel(image, params, domain, i);
+                    sum_b += b;
+                    sum_bb += b * b;
+                }
+                for tid in 0..8 {
+                    let (alpha, beta, candidate_mse) =
+                        compute_ab_mse(image, params, range, domain, tid, (sum_a, sum_b, sum_bb));
+                    let c = d * 8 + tid;
+                    if better((candidate_mse, c), (mse, candidate)) {
+                        (mse, candidate, ab) = (candidate_mse, c, (alpha, beta));
+                        if mse < EARLY_EXIT_MSE {
+                            break;
+                        }
+                    }
+                }
+                d += REDUCTION_WORKGROUP_SIZE;
+            }
+            (mse, candidate, ab)
+        })
+        .collect();
+
+    let mut s = REDUCTION_WORKGROUP_SIZE as usize / 2;
+    while s > 0 {
+        for lid in 0..s {
+            if better((best[lid + s].0, best[lid + s].1), (best[lid].0, best[lid].1)) {
+                best[lid] = best[lid + s];
+            }
+        }
+        s /= 2;
+    }
+
+    let (mse, candidate, (alpha, beta)) = best[0];
+    EncodedBlock::affine(((candidate % 8) << 16) | ((candidate / 8) & 0xFFFF), alpha, beta).with_error(mse)
+}
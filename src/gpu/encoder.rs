@@ -1,275 +1,467 @@
+use std::collections::HashMap;
+use std::error::Error;
 use std::path::Path;
 
-use crate::{
-    decode,
-    encode::EncodedBlock,
-    util::{save_debug_txt, save_fic_file_as_txt},
-};
+use crate::gpu::shader::{Kernel, ShaderRegistry, SOFTWARE_LOOP_LIMIT};
+use crate::gpu::tiles::{self, DispatchLimits};
+use crate::encode::{EncodedBlock, Luminance};
+use crate::util::{save_fic_file, FicHeader};
 use bytemuck::{Pod, Zeroable};
-use wgpu::util::DeviceExt;
 
-use crate::{
-    block_extractor::{self, BlockExtractor},
-    encode,
-    util::save_fic_file,
-};
+// One result as the shaders write it, the affine part of `EncodedBlock`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(crate) struct GpuBlock {
+    meta: u32,
+    error: f32,
+    alpha: f32,
+    beta: f32,
+}
 
+#[derive(Clone, Copy, Debug)]
 pub struct EncodeParams {
     pub image_width: u32,
     pub image_height: u32,
     pub range_size: u32,
     pub domain_size: u32,
     pub stride: u32,
+    pub kernel: Kernel,
 }
 
-fn init_wgpu() -> (wgpu::Device, wgpu::Queue) {
+// The adapter wgpu picks, if any. Software adapters such as llvmpipe are
+// only taken with `allow_software`, which also falls back to wgpu's fallback
+// adapter when nothing else turns up.
+pub fn request_adapter(allow_software: bool) -> Option<wgpu::Adapter> {
     let instance = wgpu::Instance::default();
-    let adapter =
-        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
-            .expect("no adapter");
-    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
-        .expect("no device")
+    match pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())) {
+        Ok(adapter) if allow_software || adapter.get_info().device_type != wgpu::DeviceType::Cpu => Some(adapter),
+        _ if allow_software => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter: true,
+            ..Default::default()
+        }))
+        .ok(),
+        _ => None,
+    }
 }
 
-pub fn process_gpu_encode(img_path: &Path, encode_params: EncodeParams, block_dim: u32) {
-    println!("Trying to load: {}", img_path.display());
-    let img = image::open(img_path).expect("Failed to open image!");
-    let gs_image = img.to_luma8();
+// The kernel to search `domains` domains per dispatch with when no loop may
+// run more than `loop_limit` times in one invocation: `kernel` if it fits,
+// else the reduction kernel, whose threads each take a share of the domains
+pub fn kernel_within_loop_limit(
+    kernel: Kernel,
+    block_size: u32,
+    domains: u64,
+    loop_limit: Option<u64>,
+) -> Result<Kernel, String> {
+    let Some(limit) = loop_limit else {
+        return Ok(kernel);
+    };
+    [kernel, Kernel::Reduction]
+        .into_iter()
+        .find(|k| k.loop_iterations(block_size, domains) <= limit)
+        .ok_or_else(|| {
+            format!(
+                "searching {domains} {block_size}x{block_size} domains takes {} loop iterations per invocation, more than the {limit} a software adapter runs",
+                Kernel::Reduction.loop_iterations(block_size, domains)
+            )
+        })
+}
 
-    let (width, height) = gs_image.dimensions();
-    println!(
-        "[gpu] Loaded image: {:?} with width: {} and height: {}",
-        img_path, width, height
-    );
+// A device with everything an encode needs that doesn't depend on the image:
+// the bind group layout, a pipeline per kernel and block size, compiled on
+// first use, and buffers that grow to the largest image seen so far. Encoding
+// a run of images with one `GpuEncoder` sets all of that up only once.
+pub struct GpuEncoder {
+    adapter_name: String,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    limits: DispatchLimits,
+    // Iterations a loop gets per invocation, on software adapters
+    loop_limit: Option<u64>,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    shaders: ShaderRegistry,
+    pipelines: HashMap<(Kernel, u32), wgpu::ComputePipeline>,
+    buffers: Buffers,
+}
 
-    let image_data: Vec<f32> = gs_image.pixels().map(|p| p[0] as f32).collect();
+// Reused between encodes, each only replaced when an image needs a bigger one
+#[derive(Default)]
+struct Buffers {
+    ranges: Option<wgpu::Buffer>,
+    // One per domain band
+    domains: Vec<Option<wgpu::Buffer>>,
+    output: Option<wgpu::Buffer>,
+    staging: Option<wgpu::Buffer>,
+    // The `Params` of every band of a tile, each at its own aligned offset
+    uniforms: Option<wgpu::Buffer>,
+}
 
-    // ---------------- hand over image data, block size, and stride to wgpu compute ----------------
+// Uniforms of one dispatch, laid out as `Params` in the kernel
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Params {
+    img_width: u32,
+    stride: u32,
+    domains_per_row: u32,
+    range_x0: u32,
+    range_y0: u32,
+    range_cols: u32,
+    range_rows: u32,
+    first_domain_row: u32,
+    domain_rows: u32,
+    _pad: [u32; 3],
+}
 
-    let encoded_blocks = encode_on_gpu(
-        image_data,
-        encode_params.image_width,
-        encode_params.image_height,
-        encode_params.range_size,
-        encode_params.domain_size,
-        encode_params.stride,
-    );
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
 
-    let output_path = Path::new("output.fic");
-    println!("[gpu] Saving encoded image to: {:?}", output_path);
-    save_fic_file(
-        output_path,
-        &encoded_blocks,
-        width as u16,
-        height as u16,
-        encode_params.range_size as u8,
-        encode_params.stride as u8,
-    );
-    println!("[gpu] Saving encoded image debug");
-    let debug_path = Path::new("fic_debug.txt");
-    save_fic_file_as_txt(
-        debug_path,
-        &encoded_blocks,
-        width as u16,
-        height as u16,
-        block_dim as u8,
-        encode_params.stride as u8,
-    );
-    let to_decode_path = output_path.with_extension("decoded.png");
+// The buffer in `slot`, replaced first if it is smaller than `size`
+fn reserve<'a>(
+    device: &wgpu::Device,
+    slot: &'a mut Option<wgpu::Buffer>,
+    size: u64,
+    usage: wgpu::BufferUsages,
+    label: &str,
+) -> &'a wgpu::Buffer {
+    if slot.as_ref().is_none_or(|buffer| buffer.size() < size) {
+        *slot = Some(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        }));
+    }
+    slot.as_ref().unwrap()
+}
 
-    println!("[gpu] Decoding .fic into {:?}", to_decode_path);
-    decode::decode_image(output_path.as_ref(), &to_decode_path, 15);
-    println!("[gpu] Done decoding.");
+// Copies image rows `rows` to the start of the buffer in `slot`
+fn upload_rows<'a>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    slot: &'a mut Option<wgpu::Buffer>,
+    image_data: &[f32],
+    width: u32,
+    rows: std::ops::Range<u32>,
+    label: &str,
+) -> &'a wgpu::Buffer {
+    let pixels = &image_data[(rows.start * width) as usize..(rows.end * width) as usize];
+    let buffer = reserve(
+        device,
+        slot,
+        size_of_val(pixels) as u64,
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        label,
+    );
+    queue.write_buffer(buffer, 0, bytemuck::cast_slice(pixels));
+    buffer
 }
 
-fn encode_on_gpu(
-    image_data: Vec<f32>,
-    img_width: u32,
-    img_height: u32,
-    range_size: u32,
-    domain_size: u32,
-    stride: u32,
-) -> Vec<EncodedBlock> {
-    let (device, queue) = init_wgpu();
-
-    let shader_path = match range_size {
-        2 => "src/gpu/transform_and_compare2x2.wgsl",
-        4 => "src/gpu/transform_and_compare4x4.wgsl",
-        8 => "src/gpu/transform_and_compare8x8.wgsl",
-        _ => panic!("Unsupported block size"),
-    };
+impl GpuEncoder {
+    pub fn new(adapter: &wgpu::Adapter) -> Result<Self, Box<dyn Error>> {
+        let info = adapter.get_info();
+        println!("[gpu] Using {} ({:?}, {:?})", info.name, info.device_type, info.backend);
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))?;
 
-    let shader_source = std::fs::read_to_string(shader_path).expect("Failed to load shader");
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("transform_and_compare shader"),
-        source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-    });
-
-    let image_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("image buffer"),
-        contents: bytemuck::cast_slice(&image_data),
-        usage: wgpu::BufferUsages::STORAGE,
-    });
-
-    // Compute number of range blocks
-    let range_blocks_x = (img_width - range_size) / stride + 1;
-    let range_blocks_y = (img_height - range_size) / stride + 1;
-    let total_ranges = (range_blocks_x * range_blocks_y) as usize;
-
-    let output_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("output encoded blocks"),
-        size: (total_ranges * std::mem::size_of::<EncodedBlock>()) as u64,
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    // Uniforms (can expand later)
-    #[repr(C)]
-    #[derive(Clone, Copy, Pod, Zeroable)]
-    struct Params {
-        img_width: u32,
-        img_height: u32,
-        range_size: u32,
-        domain_size: u32,
-        stride: u32,
-        range_blocks_x: u32,
-        range_blocks_y: u32,
-        _pad: u32,
+        /*
+        Binding 0: Range rows of the tile
+        Binding 1: Domain rows of the band
+        Binding 2: Output (encoded blocks of the tile)
+        Binding 3: Dispatch parameters
+         */
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        Ok(Self {
+            adapter_name: info.name,
+            limits: DispatchLimits::of(&device),
+            loop_limit: (info.device_type == wgpu::DeviceType::Cpu).then_some(SOFTWARE_LOOP_LIMIT),
+            device,
+            queue,
+            layout,
+            pipeline_layout,
+            shaders: ShaderRegistry::default(),
+            pipelines: HashMap::new(),
+            buffers: Buffers::default(),
+        })
     }
 
-    let params = Params {
-        img_width,
-        img_height,
-        range_size,
-        domain_size,
-        stride,
-        range_blocks_x,
-        range_blocks_y,
-        _pad: 0,
-    };
+    // Takes kernel sources from `shaders` instead of the embedded ones.
+    // Pipelines are compiled once per encoder, so overrides are read when a
+    // kernel and block size is first used.
+    pub fn with_shaders(mut self, shaders: ShaderRegistry) -> Self {
+        self.shaders = shaders;
+        self
+    }
 
-    let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("uniforms"),
-        contents: bytemuck::bytes_of(&params),
-        usage: wgpu::BufferUsages::UNIFORM,
-    });
-
-    /*
-    Binding 0: Image data
-    Binding 1: Output (encoded blocks)
-    Binding 2: Encode Parameters
-     */
-
-    // Bind group layout and bind group
-    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("layout"),
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-        ],
-    });
-
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: image_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: output_buf.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: uniform_buf.as_entire_binding(),
-            },
-        ],
-        label: Some("bind group"),
-    });
-
-    // Pipeline
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("pipeline layout"),
-        bind_group_layouts: &[&layout],
-        push_constant_ranges: &[],
-    });
-
-    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("compute pipeline"),
-        layout: Some(&pipeline_layout),
-        module: &shader,
-        entry_point: Some("main"),
-        cache: None,
-        compilation_options: wgpu::PipelineCompilationOptions::default(),
-    });
-
-    // Encode + dispatch
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("encoder"),
-    });
-
-    {
-        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("main pass"),
-            timestamp_writes: None,
+    // Plans dispatches for `limits` instead of the device's
+    #[cfg(test)]
+    pub(crate) fn with_limits(mut self, limits: DispatchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter_name
+    }
+
+    fn compile(&mut self, kernel: Kernel, block_size: u32) -> Result<(), String> {
+        if self.pipelines.contains_key(&(kernel, block_size)) {
+            return Ok(());
+        }
+        println!("[gpu] Compiling the {kernel} kernel for {block_size}x{block_size} blocks");
+        let shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("transform_and_compare shader"),
+            source: wgpu::ShaderSource::Wgsl(self.shaders.source(kernel, block_size)?.into()),
+        });
+        let pipeline = self.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("compute pipeline"),
+            layout: Some(&self.pipeline_layout),
+            module: &shader,
+            entry_point: Some("main"),
+            cache: None,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
         });
-        cpass.set_pipeline(&pipeline);
-        cpass.set_bind_group(0, &bind_group, &[]);
-        cpass.dispatch_workgroups(range_blocks_x, range_blocks_y, 1);
+        self.pipelines.insert((kernel, block_size), pipeline);
+        Ok(())
     }
-    // Staging buffer for results
-    let staging = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("staging"),
-        size: (total_ranges * std::mem::size_of::<EncodedBlock>()) as u64,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-    println!(
-        "[gpu] About to map result buffer of size: {}",
-        staging.size()
-    );
 
-    encoder.copy_buffer_to_buffer(&output_buf, 0, &staging, 0, staging.size());
-    queue.submit(Some(encoder.finish()));
+    // Loads `img_path` as grayscale, encodes it and saves the blocks to
+    // `fic_path`
+    pub fn encode_file(
+        &mut self,
+        img_path: &Path,
+        fic_path: &Path,
+        encode_params: EncodeParams,
+    ) -> Result<Vec<EncodedBlock>, Box<dyn Error>> {
+        println!("Trying to load: {}", img_path.display());
+        let img = image::open(img_path)?;
+        let gs_image = img.to_luma8();
+
+        let (width, height) = gs_image.dimensions();
+        println!(
+            "[gpu] Loaded image: {:?} with width: {} and height: {}",
+            img_path, width, height
+        );
 
-    // Wait + map
-    let slice = staging.slice(..);
-    slice.map_async(wgpu::MapMode::Read, |_| {});
-    device.poll(wgpu::PollType::Wait);
+        let image_data: Vec<f32> = gs_image.pixels().map(|p| p[0] as f32).collect();
 
-    let data = slice.get_mapped_range();
-    let vec: Vec<EncodedBlock> = bytemuck::cast_slice(&data).to_vec(); // ✅ clone
-    drop(data); // ✅ unmap range safely
-    staging.unmap(); // ✅ avoid lingering mapping
+        // ---------------- hand over image data, block size, and stride to wgpu compute ----------------
+
+        let encoded_blocks = self.encode(&image_data, &encode_params)?;
+
+        println!("[gpu] Saving encoded image to: {:?}", fic_path);
+        let header = FicHeader {
+            width: width as u16,
+            height: height as u16,
+            block_size: encode_params.range_size as u8,
+            stride: encode_params.stride as u8,
+            num_blocks: encoded_blocks.len() as u32,
+            luminance: Luminance::Affine,
+        };
+        save_fic_file(fic_path, &header, &encoded_blocks)?;
+        Ok(encoded_blocks)
+    }
 
-    vec // ✅ return result cleanly
+    // Searches in tiles of ranges against bands of domain rows, as planned by
+    // `tiles::plan` from the encoder's limits, and stitches the tiles back
+    // together in range order
+    pub fn encode(&mut self, image_data: &[f32], encode_params: &EncodeParams) -> Result<Vec<EncodedBlock>, Box<dyn Error>> {
+        let EncodeParams {
+            image_width: img_width,
+            image_height: img_height,
+            range_size,
+            domain_size,
+            stride,
+            kernel,
+        } = *encode_params;
+        if domain_size != range_size {
+            return Err(format!("GPU encoder needs domains the size of ranges, got {domain_size} and {range_size}").into());
+        }
+        let plan = tiles::plan(img_width, img_height, range_size, stride, &self.limits)?;
+        let domains_per_row = (img_width - range_size) / stride + 1;
+        let band_domains = plan.bands.iter().map(|band| band.rows as u64).max().unwrap_or(0) * domains_per_row as u64;
+        let kernel = kernel_within_loop_limit(kernel, range_size, band_domains, self.loop_limit)?;
+        if kernel != encode_params.kernel {
+            println!(
+                "[gpu] The {} kernel would overrun the software adapter's loop limit, using the {kernel} kernel",
+                encode_params.kernel
+            );
+        }
+        self.compile(kernel, range_size)?;
+        println!(
+            "[gpu] Searching with the {kernel} kernel in {} tile(s) x {} domain band(s)",
+            plan.tiles.len(),
+            plan.bands.len()
+        );
+
+        let Self {
+            device,
+            queue,
+            layout,
+            pipelines,
+            buffers,
+            ..
+        } = self;
+        let pipeline = &pipelines[&(kernel, range_size)];
+
+        // Every tile searches the same bands, so upload them once
+        if buffers.domains.len() < plan.bands.len() {
+            buffers.domains.resize_with(plan.bands.len(), || None);
+        }
+        let domain_bufs: Vec<&wgpu::Buffer> = plan
+            .bands
+            .iter()
+            .zip(&mut buffers.domains)
+            .map(|(band, slot)| {
+                let rows = band.pixel_rows(range_size, stride);
+                upload_rows(device, queue, slot, image_data, img_width, rows, "domain rows")
+            })
+            .collect();
+
+        // Each band's `Params` at an offset a uniform binding can start at
+        let params_size = size_of::<Params>() as u64;
+        let params_stride = params_size.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
+        let uniform_buf = reserve(
+            device,
+            &mut buffers.uniforms,
+            params_stride * plan.bands.len() as u64,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            "uniforms",
+        );
+
+        let range_blocks_x = (img_width - range_size) / range_size + 1;
+        let range_blocks_y = (img_height - range_size) / range_size + 1;
+        let mut blocks = vec![EncodedBlock::zeroed(); (range_blocks_x * range_blocks_y) as usize];
+
+        for tile in &plan.tiles {
+            let range_rows = tile.y0 * range_size..(tile.y0 + tile.rows) * range_size;
+            let range_buf = upload_rows(device, queue, &mut buffers.ranges, image_data, img_width, range_rows, "range rows");
+            let output_size = (tile.cols * tile.rows) as u64 * size_of::<GpuBlock>() as u64;
+            let output_buf = reserve(
+                device,
+                &mut buffers.output,
+                output_size,
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                "output encoded blocks",
+            );
+            // Staging buffer for results
+            let staging = reserve(
+                device,
+                &mut buffers.staging,
+                output_size,
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                "staging",
+            );
+
+            let mut uniforms = vec![0u8; (params_stride * plan.bands.len() as u64) as usize];
+            for (band, slot) in plan.bands.iter().zip(uniforms.chunks_mut(params_stride as usize)) {
+                let params = Params {
+                    img_width,
+                    stride,
+                    domains_per_row,
+                    range_x0: tile.x0,
+                    range_y0: tile.y0,
+                    range_cols: tile.cols,
+                    range_rows: tile.rows,
+                    first_domain_row: band.first_row,
+                    domain_rows: band.rows,
+                    _pad: [0; 3],
+                };
+                slot[..params_size as usize].copy_from_slice(bytemuck::bytes_of(&params));
+            }
+            queue.write_buffer(uniform_buf, 0, &uniforms);
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("encoder"),
+            });
+            // One pass per band, so each sees the previous band's results
+            for (i, domain_buf) in domain_bufs.iter().enumerate() {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: range_buf.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: domain_buf.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: output_buf.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: uniform_buf,
+                                offset: i as u64 * params_stride,
+                                size: wgpu::BufferSize::new(params_size),
+                            }),
+                        },
+                    ],
+                    label: Some("bind group"),
+                });
+
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("main pass"),
+                    timestamp_writes: None,
+                });
+                cpass.set_pipeline(pipeline);
+                cpass.set_bind_group(0, &bind_group, &[]);
+                cpass.dispatch_workgroups(tile.cols, tile.rows, 1);
+            }
+
+            encoder.copy_buffer_to_buffer(output_buf, 0, staging, 0, output_size);
+            queue.submit(Some(encoder.finish()));
+
+            // Wait + map
+            let slice = staging.slice(..output_size);
+            slice.map_async(wgpu::MapMode::Read, |_| {});
+            device.poll(wgpu::PollType::Wait)?;
+
+            let data = slice.get_mapped_range();
+            for (i, b) in bytemuck::cast_slice::<u8, GpuBlock>(&data).iter().enumerate() {
+                let (x, y) = (tile.x0 + i as u32 % tile.cols, tile.y0 + i as u32 / tile.cols);
+                blocks[(y * range_blocks_x + x) as usize] = EncodedBlock::affine(b.meta, b.alpha, b.beta).with_error(b.error);
+            }
+            drop(data);
+            staging.unmap();
+        }
+
+        Ok(blocks)
+    }
 }
@@ -1 +1,5 @@
-pub(crate) mod encoder;
\ No newline at end of file
+#[cfg(test)]
+pub(crate) mod emulator;
+pub(crate) mod encoder;
+pub(crate) mod shader;
+pub(crate) mod tiles;
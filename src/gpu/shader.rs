@@ -0,0 +1,119 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+// Block sizes the kernels are generated for
+pub const MIN_BLOCK_SIZE: u32 = 2;
+pub const MAX_BLOCK_SIZE: u32 = 16;
+// Threads sharing one range in the reduction kernel, a power of two for the
+// tree reduction
+pub const REDUCTION_WORKGROUP_SIZE: u32 = 64;
+// Software adapters such as llvmpipe end a loop after this many iterations
+// in one invocation, so longer searches come back wrong
+pub const SOFTWARE_LOOP_LIMIT: u64 = 65535;
+
+const TRANSFORM_AND_COMPARE: &str = include_str!("transform_and_compare.wgsl");
+const TRANSFORM_AND_COMPARE_REDUCTION: &str = include_str!("transform_and_compare_reduction.wgsl");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kernel {
+    // One thread searches every domain for its range
+    Serial,
+    // A workgroup shares a range, splitting the domains between its threads
+    Reduction,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 2] = [Kernel::Serial, Kernel::Reduction];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Kernel::ALL.into_iter().find(|k| k.to_string() == name)
+    }
+
+    // Name of the template, in `src/gpu` and in override directories
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Kernel::Serial => "transform_and_compare.wgsl",
+            Kernel::Reduction => "transform_and_compare_reduction.wgsl",
+        }
+    }
+
+    // Iterations of the busiest loops of one invocation in a dispatch that
+    // searches `domains` domains: the pixel loops of the fit, which run for
+    // every domain and transform the thread covers
+    pub fn loop_iterations(self, block_size: u32, domains: u64) -> u64 {
+        let per_thread = match self {
+            Kernel::Serial => domains,
+            Kernel::Reduction => domains.div_ceil(REDUCTION_WORKGROUP_SIZE as u64),
+        };
+        per_thread * 8 * (block_size * block_size) as u64
+    }
+
+    fn embedded(self) -> &'static str {
+        match self {
+            Kernel::Serial => TRANSFORM_AND_COMPARE,
+            Kernel::Reduction => TRANSFORM_AND_COMPARE_REDUCTION,
+        }
+    }
+}
+
+impl fmt::Display for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kernel::Serial => write!(f, "serial"),
+            Kernel::Reduction => write!(f, "reduction"),
+        }
+    }
+}
+
+// Kernel sources by kind and block size. The templates are compiled into
+// the binary, so it runs from anywhere; for working on the kernels, an
+// override directory is searched first for `<template stem>_<block size>.wgsl`
+// and then the template itself, and read on every lookup, so edits are picked
+// up without rebuilding.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderRegistry {
+    override_dir: Option<PathBuf>,
+}
+
+impl ShaderRegistry {
+    pub fn new(override_dir: Option<PathBuf>) -> Self {
+        Self { override_dir }
+    }
+
+    // Every kernel and block size there is a source for
+    #[cfg(test)]
+    pub(crate) fn keys() -> impl Iterator<Item = (Kernel, u32)> {
+        Kernel::ALL
+            .into_iter()
+            .flat_map(|kernel| (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).map(move |block_size| (kernel, block_size)))
+    }
+
+    // WGSL source of `kernel` for `block_size` x `block_size` blocks
+    pub fn source(&self, kernel: Kernel, block_size: u32) -> Result<String, String> {
+        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+            return Err(format!(
+                "GPU encoder supports block sizes {MIN_BLOCK_SIZE} to {MAX_BLOCK_SIZE}, got {block_size}"
+            ));
+        }
+        let template = match self.override_path(kernel, block_size) {
+            Some(path) => {
+                println!("[gpu] Using shader override {}", path.display());
+                fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?
+            }
+            None => kernel.embedded().to_string(),
+        };
+        Ok(template
+            .replace("{{BLOCK_SIZE}}", &block_size.to_string())
+            .replace("{{WORKGROUP_SIZE}}", &REDUCTION_WORKGROUP_SIZE.to_string()))
+    }
+
+    fn override_path(&self, kernel: Kernel, block_size: u32) -> Option<PathBuf> {
+        let dir = self.override_dir.as_ref()?;
+        let file_name = kernel.file_name();
+        let stem = file_name.trim_end_matches(".wgsl");
+        [dir.join(format!("{stem}_{block_size}.wgsl")), dir.join(file_name)]
+            .into_iter()
+            .find(|path| path.is_file())
+    }
+}
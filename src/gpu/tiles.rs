@@ -0,0 +1,104 @@
+use std::mem::size_of;
+
+use crate::gpu::encoder::GpuBlock;
+
+// What a single dispatch has to fit in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DispatchLimits {
+    // Bytes in one storage buffer binding
+    pub max_buffer_size: u64,
+    pub max_workgroups_per_dimension: u32,
+}
+
+impl DispatchLimits {
+    pub fn of(device: &wgpu::Device) -> Self {
+        let limits = device.limits();
+        Self {
+            max_buffer_size: (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size),
+            max_workgroups_per_dimension: limits.max_compute_workgroups_per_dimension,
+        }
+    }
+}
+
+// Ranges dispatched together, in blocks. Their pixels are uploaded as whole
+// image rows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RangeTile {
+    pub x0: u32,
+    pub y0: u32,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+// Domain rows searched by one dispatch. Bands are searched in order and the
+// best match so far carries over, so the result is the same as searching
+// every domain at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DomainBand {
+    pub first_row: u32,
+    pub rows: u32,
+}
+
+impl DomainBand {
+    // Image rows the band's domains cover
+    pub fn pixel_rows(&self, block_size: u32, stride: u32) -> std::ops::Range<u32> {
+        let start = self.first_row * stride;
+        start..start + (self.rows - 1) * stride + block_size
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DispatchPlan {
+    pub tiles: Vec<RangeTile>,
+    pub bands: Vec<DomainBand>,
+}
+
+// Splits the search so every buffer and dispatch stays within `limits`:
+// ranges into tiles of at most `max_workgroups_per_dimension` blocks a side,
+// and domains into bands of rows small enough to bind
+pub fn plan(width: u32, height: u32, block_size: u32, stride: u32, limits: &DispatchLimits) -> Result<DispatchPlan, String> {
+    let row_bytes = width as u64 * size_of::<f32>() as u64;
+    let rows_per_buffer = limits.max_buffer_size / row_bytes.max(1);
+    if rows_per_buffer < block_size as u64 {
+        return Err(format!(
+            "a {block_size}-pixel row of blocks of a {width} pixel wide image needs {} bytes, more than the device's {} byte buffers",
+            row_bytes * block_size as u64,
+            limits.max_buffer_size
+        ));
+    }
+
+    let range_blocks_x = (width - block_size) / block_size + 1;
+    let range_blocks_y = (height - block_size) / block_size + 1;
+    let max_cols = range_blocks_x.min(limits.max_workgroups_per_dimension);
+    let output_rows = limits.max_buffer_size / (max_cols as u64 * size_of::<GpuBlock>() as u64);
+    let max_rows = (rows_per_buffer / block_size as u64)
+        .min(output_rows)
+        .min(limits.max_workgroups_per_dimension as u64) as u32;
+    if max_rows == 0 {
+        return Err(format!("the device's {} byte buffers can't hold a row of results", limits.max_buffer_size));
+    }
+
+    let mut tiles = Vec::new();
+    for y0 in (0..range_blocks_y).step_by(max_rows as usize) {
+        for x0 in (0..range_blocks_x).step_by(max_cols as usize) {
+            tiles.push(RangeTile {
+                x0,
+                y0,
+                cols: max_cols.min(range_blocks_x - x0),
+                rows: max_rows.min(range_blocks_y - y0),
+            });
+        }
+    }
+
+    let domain_rows = (height - block_size) / stride + 1;
+    let max_band_rows = ((rows_per_buffer - block_size as u64) / stride as u64 + 1).min(domain_rows as u64) as u32;
+    let bands = (0..domain_rows)
+        .step_by(max_band_rows as usize)
+        .map(|first_row| DomainBand {
+            first_row,
+            rows: max_band_rows.min(domain_rows - first_row),
+        })
+        .collect();
+
+    Ok(DispatchPlan { tiles, bands })
+}
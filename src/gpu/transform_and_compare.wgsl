@@ -0,0 +1,178 @@
+// One invocation per range block: searches a band of domain rows under the 8
+// D4 transforms for the best affine match, carrying on from the previous
+// band's result. `{{BLOCK_SIZE}}` is filled in by
+// `shader::source` before the module is compiled.
+
+const BLOCK_SIZE: u32 = {{BLOCK_SIZE}}u;
+const BLOCK_AREA: u32 = BLOCK_SIZE * BLOCK_SIZE;
+const N: f32 = f32(BLOCK_AREA);
+
+struct EncodedBlock {
+    meta_data: u32,
+    error: f32,
+    alpha: f32,
+    beta: f32,
+};
+
+struct Params {
+    img_width: u32,
+    stride: u32,
+    domains_per_row: u32,
+    // This dispatch's ranges, in blocks
+    range_x0: u32,
+    range_y0: u32,
+    range_cols: u32,
+    range_rows: u32,
+    // This dispatch's domain rows
+    first_domain_row: u32,
+    domain_rows: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// Whole image rows: from the tile's first range row, and from the band's
+// first domain row
+@group(0) @binding(0) var<storage, read> ranges: array<f32>;
+@group(0) @binding(1) var<storage, read> domains: array<f32>;
+@group(0) @binding(2) var<storage, read_write> output: array<EncodedBlock>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+// Pixel `index` of the block whose top-left corner is `origin`, relative to
+// the start of its buffer. Blocks are read straight from the buffers rather
+// than copied into arrays, so a 16x16 variant doesn't need kilobytes of
+// private memory per invocation.
+fn range_pixel(origin: vec2<u32>, index: u32) -> f32 {
+    let x = origin.x + index % BLOCK_SIZE;
+    let y = origin.y + index / BLOCK_SIZE;
+    return ranges[y * params.img_width + x];
+}
+
+fn domain_pixel(origin: vec2<u32>, index: u32) -> f32 {
+    let x = origin.x + index % BLOCK_SIZE;
+    let y = origin.y + index / BLOCK_SIZE;
+    return domains[y * params.img_width + x];
+}
+
+// Where pixel `index` of a block lands under transform `tid`, in the same
+// order as `apply_d4_transform`
+fn d4_index(index: u32, tid: u32) -> u32 {
+    let x = index % BLOCK_SIZE;
+    let y = index / BLOCK_SIZE;
+    let last = BLOCK_SIZE - 1u;
+    var t = vec2<u32>(x, y);
+    switch tid {
+        case 1u: { t = vec2<u32>(last - y, x); }
+        case 2u: { t = vec2<u32>(last - x, last - y); }
+        case 3u: { t = vec2<u32>(y, last - x); }
+        case 4u: { t = vec2<u32>(last - x, y); }
+        case 5u: { t = vec2<u32>(x, last - y); }
+        case 6u: { t = vec2<u32>(y, x); }
+        case 7u: { t = vec2<u32>(last - y, last - x); }
+        default: {}
+    }
+    return t.y * BLOCK_SIZE + t.x;
+}
+
+// Least-squares alpha and beta mapping the transformed domain onto the range,
+// and the MSE of that fit. The block sums don't depend on the transform, so
+// the caller passes them in.
+fn compute_ab_mse(
+    range: vec2<u32>,
+    domain: vec2<u32>,
+    tid: u32,
+    sum_a: f32,
+    sum_b: f32,
+    sum_bb: f32,
+) -> vec3<f32> {
+    var sum_ab = 0.0;
+    for (var i = 0u; i < BLOCK_AREA; i++) {
+        sum_ab += range_pixel(range, d4_index(i, tid)) * domain_pixel(domain, i);
+    }
+
+    let alpha = (N * sum_ab - sum_a * sum_b) / (N * sum_bb - sum_b * sum_b + 0.0001);
+    let beta = (sum_a - alpha * sum_b) / N;
+
+    var mse = 0.0;
+    for (var i = 0u; i < BLOCK_AREA; i++) {
+        let diff = range_pixel(range, d4_index(i, tid)) - (alpha * domain_pixel(domain, i) + beta);
+        mse += diff * diff;
+    }
+
+    return vec3<f32>(alpha, beta, mse / N);
+}
+
+@compute @workgroup_size(1)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let x = gid.x;
+    let y = gid.y;
+
+    if (x >= params.range_cols || y >= params.range_rows) {
+        return;
+    }
+    let out_index = y * params.range_cols + x;
+
+    var best_mse = 1e10;
+    var best_tid = 0u;
+    var best_did = 0u;
+    var best_ab = vec2<f32>(0.0, 0.0);
+    if (params.first_domain_row > 0u) {
+        let previous = output[out_index];
+        best_mse = previous.error;
+        best_tid = previous.meta_data >> 16u;
+        best_did = previous.meta_data & 0xFFFFu;
+        best_ab = vec2<f32>(previous.alpha, previous.beta);
+    }
+    // An earlier band already ended the search
+    if (best_mse < 0.0001) {
+        return;
+    }
+
+    let range = vec2<u32>((params.range_x0 + x) * BLOCK_SIZE, y * BLOCK_SIZE);
+    var sum_a = 0.0;
+    for (var i = 0u; i < BLOCK_AREA; i++) {
+        sum_a += range_pixel(range, i);
+    }
+
+    var did = params.first_domain_row * params.domains_per_row;
+    for (var row = 0u; row < params.domain_rows; row++) {
+        let dy = row * params.stride;
+        for (var dx = 0u; dx + BLOCK_SIZE <= params.img_width; dx += params.stride) {
+            let domain = vec2<u32>(dx, dy);
+            var sum_b = 0.0;
+            var sum_bb = 0.0;
+            for (var i = 0u; i < BLOCK_AREA; i++) {
+                let b = domain_pixel(domain, i);
+                sum_b += b;
+                sum_bb += b * b;
+            }
+
+            for (var tid = 0u; tid < 8u; tid++) {
+                let abmse = compute_ab_mse(range, domain, tid, sum_a, sum_b, sum_bb);
+                if (abmse.z < best_mse) {
+                    best_mse = abmse.z;
+                    best_ab = abmse.xy;
+                    best_tid = tid;
+                    best_did = did;
+                    if (best_mse < 0.0001) {
+                        break;
+                    }
+                }
+            }
+            if (best_mse < 0.0001) {
+                break;
+            }
+            did += 1u;
+        }
+        if (best_mse < 0.0001) {
+            break;
+        }
+    }
+
+    output[out_index] = EncodedBlock(
+        (best_tid << 16u) | (best_did & 0xFFFFu),
+        best_mse,
+        best_ab.x,
+        best_ab.y
+    );
+}
@@ -0,0 +1,192 @@
+// One workgroup per range block: the range is loaded into workgroup memory,
+// each thread searches every WORKGROUP_SIZE-th domain of the band under the
+// 8 D4 transforms, and a tree reduction picks the workgroup's best match,
+// carrying on from the previous band's result. Picks the same candidate as
+// the serial kernel: the lowest MSE, except that the first candidate below
+// EXACT_MSE wins outright, and ties go to the earlier candidate.
+// `{{BLOCK_SIZE}}` and `{{WORKGROUP_SIZE}}` are filled in by
+// `shader::source` before the module is compiled.
+
+const BLOCK_SIZE: u32 = {{BLOCK_SIZE}}u;
+const BLOCK_AREA: u32 = BLOCK_SIZE * BLOCK_SIZE;
+const N: f32 = f32(BLOCK_AREA);
+const WORKGROUP_SIZE: u32 = {{WORKGROUP_SIZE}}u;
+const EXACT_MSE: f32 = 0.0001;
+const NO_CANDIDATE: u32 = 0xFFFFFFFFu;
+
+struct EncodedBlock {
+    meta_data: u32,
+    error: f32,
+    alpha: f32,
+    beta: f32,
+};
+
+struct Params {
+    img_width: u32,
+    stride: u32,
+    domains_per_row: u32,
+    // This dispatch's ranges, in blocks
+    range_x0: u32,
+    range_y0: u32,
+    range_cols: u32,
+    range_rows: u32,
+    // This dispatch's domain rows
+    first_domain_row: u32,
+    domain_rows: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// Whole image rows: from the tile's first range row, and from the band's
+// first domain row
+@group(0) @binding(0) var<storage, read> ranges: array<f32>;
+@group(0) @binding(1) var<storage, read> domains: array<f32>;
+@group(0) @binding(2) var<storage, read_write> output: array<EncodedBlock>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+var<workgroup> range_block: array<f32, BLOCK_AREA>;
+// Each thread's best match, then the reduction's
+var<workgroup> best_mse: array<f32, WORKGROUP_SIZE>;
+var<workgroup> best_candidate: array<u32, WORKGROUP_SIZE>;
+var<workgroup> best_ab: array<vec2<f32>, WORKGROUP_SIZE>;
+
+fn domain_pixel(origin: vec2<u32>, index: u32) -> f32 {
+    let x = origin.x + index % BLOCK_SIZE;
+    let y = origin.y + index / BLOCK_SIZE;
+    return domains[y * params.img_width + x];
+}
+
+// Where pixel `index` of a block lands under transform `tid`, in the same
+// order as `apply_d4_transform`
+fn d4_index(index: u32, tid: u32) -> u32 {
+    let x = index % BLOCK_SIZE;
+    let y = index / BLOCK_SIZE;
+    let last = BLOCK_SIZE - 1u;
+    var t = vec2<u32>(x, y);
+    switch tid {
+        case 1u: { t = vec2<u32>(last - y, x); }
+        case 2u: { t = vec2<u32>(last - x, last - y); }
+        case 3u: { t = vec2<u32>(y, last - x); }
+        case 4u: { t = vec2<u32>(last - x, y); }
+        case 5u: { t = vec2<u32>(x, last - y); }
+        case 6u: { t = vec2<u32>(y, x); }
+        case 7u: { t = vec2<u32>(last - y, last - x); }
+        default: {}
+    }
+    return t.y * BLOCK_SIZE + t.x;
+}
+
+// Same arithmetic as the serial kernel, with the range from workgroup memory
+fn compute_ab_mse(domain: vec2<u32>, tid: u32, sum_a: f32, sum_b: f32, sum_bb: f32) -> vec3<f32> {
+    var sum_ab = 0.0;
+    for (var i = 0u; i < BLOCK_AREA; i++) {
+        sum_ab += range_block[d4_index(i, tid)] * domain_pixel(domain, i);
+    }
+
+    let alpha = (N * sum_ab - sum_a * sum_b) / (N * sum_bb - sum_b * sum_b + 0.0001);
+    let beta = (sum_a - alpha * sum_b) / N;
+
+    var mse = 0.0;
+    for (var i = 0u; i < BLOCK_AREA; i++) {
+        let diff = range_block[d4_index(i, tid)] - (alpha * domain_pixel(domain, i) + beta);
+        mse += diff * diff;
+    }
+
+    return vec3<f32>(alpha, beta, mse / N);
+}
+
+// Whether candidate `a` beats candidate `b`. Candidates are numbered
+// `domain * 8 + transform`, in the order the serial kernel visits them.
+fn better(mse_a: f32, candidate_a: u32, mse_b: f32, candidate_b: u32) -> bool {
+    let key_a = select(mse_a, 0.0, mse_a < EXACT_MSE);
+    let key_b = select(mse_b, 0.0, mse_b < EXACT_MSE);
+    return key_a < key_b || (key_a == key_b && candidate_a < candidate_b);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(
+    @builtin(workgroup_id) wid: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+) {
+    // One workgroup per range of the tile, so no bounds check is needed
+    let out_index = wid.y * params.range_cols + wid.x;
+    let range = vec2<u32>((params.range_x0 + wid.x) * BLOCK_SIZE, wid.y * BLOCK_SIZE);
+
+    for (var i = lid; i < BLOCK_AREA; i += WORKGROUP_SIZE) {
+        range_block[i] = ranges[(range.y + i / BLOCK_SIZE) * params.img_width + range.x + i % BLOCK_SIZE];
+    }
+    workgroupBarrier();
+
+    var sum_a = 0.0;
+    for (var i = 0u; i < BLOCK_AREA; i++) {
+        sum_a += range_block[i];
+    }
+
+    var mse = 1e10;
+    var candidate = NO_CANDIDATE;
+    var ab = vec2<f32>(0.0, 0.0);
+
+    let first_did = params.first_domain_row * params.domains_per_row;
+    var band_domains = params.domain_rows * params.domains_per_row;
+    // An earlier band already ended the search
+    if (params.first_domain_row > 0u && output[out_index].error < EXACT_MSE) {
+        band_domains = 0u;
+    }
+    // A thread's candidates only grow, so it can stop at its first exact one
+    for (var d = lid; d < band_domains && mse >= EXACT_MSE; d += WORKGROUP_SIZE) {
+        let domain = vec2<u32>((d % params.domains_per_row) * params.stride, (d / params.domains_per_row) * params.stride);
+        var sum_b = 0.0;
+        var sum_bb = 0.0;
+        for (var i = 0u; i < BLOCK_AREA; i++) {
+            let b = domain_pixel(domain, i);
+            sum_b += b;
+            sum_bb += b * b;
+        }
+
+        for (var tid = 0u; tid < 8u; tid++) {
+            let abmse = compute_ab_mse(domain, tid, sum_a, sum_b, sum_bb);
+            let c = (first_did + d) * 8u + tid;
+            if (better(abmse.z, c, mse, candidate)) {
+                mse = abmse.z;
+                candidate = c;
+                ab = abmse.xy;
+                if (mse < EXACT_MSE) {
+                    break;
+                }
+            }
+        }
+    }
+
+    best_mse[lid] = mse;
+    best_candidate[lid] = candidate;
+    best_ab[lid] = ab;
+    workgroupBarrier();
+
+    for (var s = WORKGROUP_SIZE / 2u; s > 0u; s >>= 1u) {
+        if (lid < s && better(best_mse[lid + s], best_candidate[lid + s], best_mse[lid], best_candidate[lid])) {
+            best_mse[lid] = best_mse[lid + s];
+            best_candidate[lid] = best_candidate[lid + s];
+            best_ab[lid] = best_ab[lid + s];
+        }
+        workgroupBarrier();
+    }
+
+    if (lid == 0u) {
+        var result = EncodedBlock(
+            ((best_candidate[0] % 8u) << 16u) | ((best_candidate[0] / 8u) & 0xFFFFu),
+            best_mse[0],
+            best_ab[0].x,
+            best_ab[0].y
+        );
+        // Earlier bands hold earlier candidates
+        if (params.first_domain_row > 0u) {
+            let previous = output[out_index];
+            let previous_candidate = (previous.meta_data & 0xFFFFu) * 8u + (previous.meta_data >> 16u);
+            if (!better(best_mse[0], best_candidate[0], previous.error, previous_candidate)) {
+                result = previous;
+            }
+        }
+        output[out_index] = result;
+    }
+}
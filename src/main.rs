@@ -1,15 +1,44 @@
+use std::io;
 use std::path::Path;
+use std::process::ExitCode;
 use std::time;
-use std::{fs, io};
 mod alpha_beta;
+mod animation;
+mod batch;
+mod bench;
 mod block_extractor;
+mod cli;
 mod decode;
+mod domain_pool;
 mod encode;
+mod export;
+mod fft;
+mod fft_search;
 mod gpu;
+mod metrics;
+mod polynomial;
+mod report;
+mod simd;
+mod sweep;
+#[cfg(test)]
+mod tests;
 mod transform;
 mod util;
+mod video;
+mod visualize;
 
-fn main() {
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
+
+    interactive();
+    ExitCode::SUCCESS
+}
+
+// Fallback used when no arguments are given
+fn interactive() {
     println!("[main] Starting program...");
 
     println!("[main] Enter the block size:");
@@ -33,8 +62,12 @@ fn main() {
     io::stdin().read_line(&mut input).unwrap();
     let mode = input.trim();
 
+    if mode == "2" {
+        interactive_batch(block_size, stride);
+        return;
+    }
     if mode != "1" {
-        println!("[main] Batch mode not implemented");
+        println!("[main] Invalid mode selected.");
         return;
     }
 
@@ -68,7 +101,8 @@ fn main() {
 
     if method == "1" {
         println!("[main] CPU encoding selected.");
-        encode::encode_image(to_encode_path, block_size, stride);
+        encode::encode_image(to_encode_path, fic_path, &encode::EncodeOptions::new(block_size, stride))
+            .expect("Failed to encode image");
         println!("[main] Preparing decode step...");
         let output_path = to_encode_path.with_extension("decoded.png");
         println!(" → Source fic: {}", fic_path.display());
@@ -81,7 +115,7 @@ fn main() {
         );
 
         println!("[main] Calling decode_image...");
-        decode::decode_image(fic_path, &output_path, 10);
+        decode::decode_image(fic_path, &output_path, 10).expect("Failed to decode image");
         println!("[main] Finished decode.");
     } else if method == "2" {
         println!("[main] GPU encoding selected.");
@@ -105,9 +139,31 @@ fn main() {
                 range_size: block_size as u32,
                 domain_size: block_size as u32,
                 stride: stride as u32,
+                kernel: gpu::shader::Kernel::Reduction,
+            };
+            let Some(adapter) = gpu::encoder::request_adapter(false) else {
+                println!("[gpu] No hardware GPU adapter found, choose the CPU instead.");
+                return;
             };
             let gpu_encode_start = time::Instant::now();
-            gpu::encoder::process_gpu_encode(to_encode_path, encode_params, block_size as u32);
+            let encoded_blocks = gpu::encoder::GpuEncoder::new(&adapter)
+                .and_then(|mut encoder| encoder.encode_file(to_encode_path, fic_path, encode_params))
+                .expect("GPU encode failed");
+
+            println!("[gpu] Saving encoded image debug");
+            util::save_fic_file_as_txt(
+                Path::new("fic_debug.txt"),
+                &encoded_blocks,
+                width as u16,
+                height as u16,
+                block_size as u8,
+                stride as u8,
+            );
+            let to_decode_path = fic_path.with_extension("decoded.png");
+
+            println!("[gpu] Decoding .fic into {:?}", to_decode_path);
+            decode::decode_image(fic_path, &to_decode_path, 15).expect("Failed to decode image");
+            println!("[gpu] Done decoding.");
 
             println!("[gpu] Finished Processing image...");
             println!(
@@ -117,6 +173,49 @@ fn main() {
         }
     } else {
         println!("Invalid method selected.");
-        return;
+    }
+}
+
+fn interactive_batch(block_size: usize, stride: usize) {
+    let default_pattern = "test_imgs";
+    println!("[main] Batch mode selected.");
+    println!("Enter a directory or glob (e.g. test_imgs/*.png), or press enter to use default: {default_pattern}");
+    print!("> ");
+    io::Write::flush(&mut io::stdout()).unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    let pattern = match input.trim() {
+        "" => default_pattern.to_string(),
+        trimmed => trimmed.to_string(),
+    };
+
+    println!("[main] Choose encoding method:");
+    println!("1. CPU");
+    println!("2. GPU");
+
+    input.clear();
+    io::stdin().read_line(&mut input).unwrap();
+    let backend = match input.trim() {
+        "1" => encode::Backend::Cpu,
+        "2" => encode::Backend::Gpu,
+        _ => {
+            println!("Invalid method selected.");
+            return;
+        }
+    };
+
+    let options = batch::BatchOptions {
+        mode: batch::BatchMode::Encode,
+        encode: encode::EncodeOptions::new(block_size, stride),
+        backend,
+        iterations: 10,
+        jobs: cli::default_jobs(),
+        output_dir: None,
+        originals_dir: None,
+        shaders: gpu::shader::ShaderRegistry::default(),
+    };
+    if let Err(e) = cli::run_batch(&pattern, &options) {
+        println!("[main] Batch finished with errors: {e}");
     }
 }
@@ -0,0 +1,236 @@
+use std::fmt;
+
+use image::GrayImage;
+
+use crate::util::FicHeader;
+
+const PEAK: f64 = 255.0;
+
+// SSIM constants from Wang et al. 2004
+const SSIM_K1: f64 = 0.01;
+const SSIM_K2: f64 = 0.03;
+const SSIM_WINDOW: usize = 11;
+const SSIM_SIGMA: f64 = 1.5;
+
+// Per-scale exponents from Wang et al. 2003, finest scale first
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+#[derive(Clone, Copy, Debug)]
+pub struct QualityReport {
+    pub mse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    // None for images smaller than the SSIM window
+    pub ms_ssim: Option<f64>,
+}
+
+impl QualityReport {
+    pub fn compare(original: &GrayImage, decoded: &GrayImage) -> Self {
+        Self {
+            mse: mse(original, decoded),
+            psnr: psnr(original, decoded),
+            ssim: ssim(original, decoded),
+            ms_ssim: ms_ssim(original, decoded),
+        }
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MSE {:.2}, PSNR {:.2} dB, SSIM {:.4}, ", self.mse, self.psnr, self.ssim)?;
+        match self.ms_ssim {
+            Some(ms_ssim) => write!(f, "MS-SSIM {ms_ssim:.4}"),
+            None => write!(f, "MS-SSIM n/a (image smaller than the {SSIM_WINDOW}x{SSIM_WINDOW} window)"),
+        }
+    }
+}
+
+pub fn mse(original: &GrayImage, decoded: &GrayImage) -> f64 {
+    assert_same_size(original, decoded);
+
+    let mut sum = 0.0;
+    for (a, b) in original.pixels().zip(decoded.pixels()) {
+        let diff = a[0] as f64 - b[0] as f64;
+        sum += diff * diff;
+    }
+    sum / (original.width() as f64 * original.height() as f64)
+}
+
+// Peak signal-to-noise ratio in dB for 8-bit images (infinite for identical images)
+pub fn psnr(original: &GrayImage, decoded: &GrayImage) -> f64 {
+    let mse = mse(original, decoded);
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (PEAK * PEAK / mse).log10()
+    }
+}
+
+// Mean SSIM over every position of an 11x11 Gaussian window (sigma 1.5).
+// Images smaller than the window use a window as large as the image.
+pub fn ssim(original: &GrayImage, decoded: &GrayImage) -> f64 {
+    assert_same_size(original, decoded);
+    let a = Plane::from_image(original);
+    let b = Plane::from_image(decoded);
+    ssim_terms(&a, &b).0
+}
+
+// Multi-scale SSIM with the standard 5 scales. Scales that would shrink the
+// image below the SSIM window are dropped and the remaining weights
+// renormalised, so small test images still get a score; None when even the
+// full-size image is smaller than the window.
+pub fn ms_ssim(original: &GrayImage, decoded: &GrayImage) -> Option<f64> {
+    assert_same_size(original, decoded);
+    let mut a = Plane::from_image(original);
+    let mut b = Plane::from_image(decoded);
+
+    let min_side = a.width.min(a.height);
+    if min_side < SSIM_WINDOW {
+        return None;
+    }
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && (min_side >> scales) >= SSIM_WINDOW {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let weight_sum: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_terms(&a, &b);
+        let exponent = weight / weight_sum;
+        if scale + 1 == scales {
+            result *= ssim.max(0.0).powf(exponent);
+        } else {
+            result *= cs.max(0.0).powf(exponent);
+            a = a.downsample();
+            b = b.downsample();
+        }
+    }
+    Some(result)
+}
+
+// Raw 8-bit grayscale size over encoded size
+pub fn compression_ratio(header: &FicHeader, fic_bytes: u64) -> f64 {
+    header.width as f64 * header.height as f64 / fic_bytes as f64
+}
+
+pub fn bits_per_pixel(header: &FicHeader, fic_bytes: u64) -> f64 {
+    fic_bytes as f64 * 8.0 / (header.width as f64 * header.height as f64)
+}
+
+fn assert_same_size(original: &GrayImage, decoded: &GrayImage) {
+    assert_eq!(
+        original.dimensions(),
+        decoded.dimensions(),
+        "images must have the same dimensions"
+    );
+}
+
+struct Plane {
+    data: Vec<f64>,
+    width: usize,
+    height: usize,
+}
+
+impl Plane {
+    fn from_image(img: &GrayImage) -> Self {
+        Self {
+            data: img.pixels().map(|p| p[0] as f64).collect(),
+            width: img.width() as usize,
+            height: img.height() as usize,
+        }
+    }
+
+    // 2x2 box average, dropping an odd last row/column
+    fn downsample(&self) -> Self {
+        let width = self.width / 2;
+        let height = self.height / 2;
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let i = 2 * y * self.width + 2 * x;
+                let sum = self.data[i] + self.data[i + 1] + self.data[i + self.width] + self.data[i + self.width + 1];
+                data.push(sum / 4.0);
+            }
+        }
+        Self { data, width, height }
+    }
+
+    fn map2(&self, other: &Plane, f: impl Fn(f64, f64) -> f64) -> Plane {
+        Plane {
+            data: self.data.iter().zip(&other.data).map(|(&a, &b)| f(a, b)).collect(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Separable Gaussian filter keeping only positions where the whole
+    // window fits ("valid" convolution)
+    fn gaussian_filter(&self, kernel: &[f64]) -> Plane {
+        let k = kernel.len();
+        let out_w = self.width + 1 - k;
+        let out_h = self.height + 1 - k;
+
+        let mut rows = vec![0.0; out_w * self.height];
+        for y in 0..self.height {
+            for x in 0..out_w {
+                let src = &self.data[y * self.width + x..y * self.width + x + k];
+                rows[y * out_w + x] = src.iter().zip(kernel).map(|(v, w)| v * w).sum();
+            }
+        }
+
+        let mut data = vec![0.0; out_w * out_h];
+        for y in 0..out_h {
+            for x in 0..out_w {
+                data[y * out_w + x] = (0..k).map(|i| rows[(y + i) * out_w + x] * kernel[i]).sum();
+            }
+        }
+        Plane {
+            data,
+            width: out_w,
+            height: out_h,
+        }
+    }
+}
+
+fn gaussian_kernel(size: usize, sigma: f64) -> Vec<f64> {
+    let center = (size as f64 - 1.0) / 2.0;
+    let kernel: Vec<f64> = (0..size)
+        .map(|i| {
+            let d = i as f64 - center;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.into_iter().map(|v| v / sum).collect()
+}
+
+// Mean SSIM and mean contrast-structure term over every window position
+fn ssim_terms(a: &Plane, b: &Plane) -> (f64, f64) {
+    let window = SSIM_WINDOW.min(a.width).min(a.height);
+    let kernel = gaussian_kernel(window, SSIM_SIGMA);
+    let c1 = (SSIM_K1 * PEAK).powi(2);
+    let c2 = (SSIM_K2 * PEAK).powi(2);
+
+    let mu_a = a.gaussian_filter(&kernel);
+    let mu_b = b.gaussian_filter(&kernel);
+    let aa = a.map2(a, |x, _| x * x).gaussian_filter(&kernel);
+    let bb = b.map2(b, |x, _| x * x).gaussian_filter(&kernel);
+    let ab = a.map2(b, |x, y| x * y).gaussian_filter(&kernel);
+
+    let n = mu_a.data.len() as f64;
+    let mut ssim_sum = 0.0;
+    let mut cs_sum = 0.0;
+    for i in 0..mu_a.data.len() {
+        let (ma, mb) = (mu_a.data[i], mu_b.data[i]);
+        let var_a = aa.data[i] - ma * ma;
+        let var_b = bb.data[i] - mb * mb;
+        let cov = ab.data[i] - ma * mb;
+        let l = (2.0 * ma * mb + c1) / (ma * ma + mb * mb + c1);
+        let cs = (2.0 * cov + c2) / (var_a + var_b + c2);
+        ssim_sum += l * cs;
+        cs_sum += cs;
+    }
+    (ssim_sum / n, cs_sum / n)
+}
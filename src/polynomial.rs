@@ -0,0 +1,231 @@
+use crate::block_extractor::{BlockPixels, BlockStats};
+
+// Coefficients of the polynomial luminance model. Like the orthogonal
+// model it is stored about the domain's mean m and variance s2:
+//   r = alpha * (d - m) + gamma * ((d - m)^2 - s2) + beta + ramp_x * u + ramp_y * v
+// where (u, v) is the pixel's offset from the block centre. Every term but
+// beta sums to zero over the block, so beta is the range mean and the
+// decoder can't drift away from it however the domain changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Polynomial {
+    pub alpha: f32,
+    pub gamma: f32,
+    pub beta: f32,
+    pub ramp_x: f32,
+    pub ramp_y: f32,
+}
+
+impl Polynomial {
+    // The range these coefficients map a (transformed) domain block to,
+    // given the domain's mean and variance
+    pub fn apply(&self, domain: &[f32], mean: f32, variance: f32, block_size: usize) -> Vec<f32> {
+        domain
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let (u, v) = centred(i, block_size);
+                let centred_d = d - mean;
+                self.alpha * centred_d
+                    + self.gamma * (centred_d * centred_d - variance)
+                    + self.beta
+                    + self.ramp_x * u
+                    + self.ramp_y * v
+            })
+            .collect()
+    }
+}
+
+// Offset of pixel `i` from the centre of the block
+pub fn centred(i: usize, block_size: usize) -> (f32, f32) {
+    let centre = (block_size as f32 - 1.0) / 2.0;
+    ((i % block_size) as f32 - centre, (i / block_size) as f32 - centre)
+}
+
+// The sums over a (transformed) domain the fit needs, independent of the
+// range, so a pool can compute them once per candidate. The plain sums come
+// from the domain's `BlockStats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DomainMoments {
+    pub sum_d: f64,
+    pub sum_d2: f64,
+    pub sum_d3: f64,
+    pub sum_d4: f64,
+    pub sum_du: f64,
+    pub sum_dv: f64,
+    pub sum_d2u: f64,
+    pub sum_d2v: f64,
+}
+
+impl DomainMoments {
+    pub fn new<D: BlockPixels + ?Sized>(domain: &D, stats: BlockStats, block_size: usize) -> Self {
+        let mut m = Self {
+            sum_d: stats.sum as f64,
+            sum_d2: stats.sum_sq as f64,
+            ..Self::default()
+        };
+        for (i, d) in domain.pixels().enumerate() {
+            let (u, v) = centred(i, block_size);
+            let d = d as f64;
+            let d2 = d * d;
+            m.sum_d3 += d2 * d;
+            m.sum_d4 += d2 * d2;
+            m.sum_du += d * u as f64;
+            m.sum_dv += d * v as f64;
+            m.sum_d2u += d2 * u as f64;
+            m.sum_d2v += d2 * v as f64;
+        }
+        m
+    }
+
+    // The moments of `apply_d4_transform(domain, block_size, transform_id)`.
+    // Every isometry moves the pixel at (u, v) to a signed permutation of
+    // it, so only the ramp sums change.
+    pub fn transformed(&self, transform_id: u8) -> Self {
+        let ramp = |u: f64, v: f64| match transform_id {
+            0 => (u, v),
+            1 => (-v, u),
+            2 => (-u, -v),
+            3 => (v, -u),
+            4 => (-u, v),
+            5 => (u, -v),
+            6 => (v, u),
+            7 => (-v, -u),
+            _ => panic!("Invalid transform ID: {}", transform_id),
+        };
+        let (sum_du, sum_dv) = ramp(self.sum_du, self.sum_dv);
+        let (sum_d2u, sum_d2v) = ramp(self.sum_d2u, self.sum_d2v);
+        Self {
+            sum_du,
+            sum_dv,
+            sum_d2u,
+            sum_d2v,
+            ..*self
+        }
+    }
+}
+
+// The range half: its sums against the constant and ramp terms, with the
+// plain sums from the range's `BlockStats`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RangeMoments {
+    pub n: f64,
+    pub sum_r: f64,
+    pub sum_r2: f64,
+    pub sum_ur: f64,
+    pub sum_vr: f64,
+    pub sum_u2: f64,
+    pub sum_v2: f64,
+}
+
+impl RangeMoments {
+    pub fn new<R: BlockPixels + ?Sized>(range: &R, stats: BlockStats, block_size: usize) -> Self {
+        let mut m = Self {
+            n: range.pixel_count() as f64,
+            sum_r: stats.sum as f64,
+            sum_r2: stats.sum_sq as f64,
+            ..Self::default()
+        };
+        for (i, r) in range.pixels().enumerate() {
+            let (u, v) = centred(i, block_size);
+            let (r, u, v) = (r as f64, u as f64, v as f64);
+            m.sum_ur += u * r;
+            m.sum_vr += v * r;
+            m.sum_u2 += u * u;
+            m.sum_v2 += v * v;
+        }
+        m
+    }
+}
+
+// sum(d * r) and sum(d^2 * r), the only sums that need both blocks
+pub fn cross_sums<D, R>(domain: &D, range: &R) -> (f64, f64)
+where
+    D: BlockPixels + ?Sized,
+    R: BlockPixels + ?Sized,
+{
+    domain.pixels().zip(range.pixels()).fold((0.0, 0.0), |(dr, d2r), (d, r)| {
+        let (d, r) = (d as f64, r as f64);
+        (dr + d * r, d2r + d * d * r)
+    })
+}
+
+const TERMS: usize = 5;
+const QUADRATIC: usize = 4;
+
+// A term is dropped when less than this fraction of its energy is left once
+// the earlier terms are projected out. The quadratic gets a much looser
+// bound: when d^2 is nearly a line in d its coefficient is huge, and it
+// blows up as soon as the decoder's domain differs from the encoder's.
+const DEPENDENT: f64 = 1e-9;
+const DEPENDENT_QUADRATIC: f64 = 1e-2;
+
+// Least-squares fit from the normal equations G c = h over the basis
+// [1, u, v, d, d^2], along with its MSE. The sums of u, v and u*v over a
+// square block are zero, so those entries are left out. The solution is
+// then rewritten in the mean-removed form `Polynomial` stores, which spans
+// the same space.
+//
+// The system is solved by a Cholesky factorisation that drops any term
+// which is (nearly) a combination of the ones before it, so a flat domain
+// gives alpha = gamma = 0 rather than large coefficients that cancel.
+pub fn fit(domain: &DomainMoments, range: &RangeMoments, sum_dr: f64, sum_d2r: f64) -> (Polynomial, f32) {
+    let d = domain;
+    let gram = [
+        [range.n, 0.0, 0.0, d.sum_d, d.sum_d2],
+        [0.0, range.sum_u2, 0.0, d.sum_du, d.sum_d2u],
+        [0.0, 0.0, range.sum_v2, d.sum_dv, d.sum_d2v],
+        [d.sum_d, d.sum_du, d.sum_dv, d.sum_d2, d.sum_d3],
+        [d.sum_d2, d.sum_d2u, d.sum_d2v, d.sum_d3, d.sum_d4],
+    ];
+    let rhs = [range.sum_r, range.sum_ur, range.sum_vr, sum_dr, sum_d2r];
+
+    let mut lower = [[0.0f64; TERMS]; TERMS];
+    let mut active = [false; TERMS];
+    for j in 0..TERMS {
+        let pivot = gram[j][j] - (0..j).map(|k| lower[j][k] * lower[j][k]).sum::<f64>();
+        let tolerance = if j == QUADRATIC { DEPENDENT_QUADRATIC } else { DEPENDENT };
+        if pivot <= tolerance * gram[j][j] || gram[j][j] <= 0.0 {
+            continue;
+        }
+        active[j] = true;
+        lower[j][j] = pivot.sqrt();
+        for i in j + 1..TERMS {
+            let dot: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+            lower[i][j] = (gram[i][j] - dot) / lower[j][j];
+        }
+    }
+
+    // Dropped terms have an all-zero column in `lower`, so skipping them in
+    // both substitutions solves the reduced system
+    let mut y = [0.0f64; TERMS];
+    for i in (0..TERMS).filter(|&i| active[i]) {
+        let dot: f64 = (0..i).map(|k| lower[i][k] * y[k]).sum();
+        y[i] = (rhs[i] - dot) / lower[i][i];
+    }
+    let mut c = [0.0f64; TERMS];
+    for i in (0..TERMS).rev().filter(|&i| active[i]) {
+        let dot: f64 = (i + 1..TERMS).map(|k| lower[k][i] * c[k]).sum();
+        c[i] = (y[i] - dot) / lower[i][i];
+    }
+
+    // sum((r - c.basis)^2) = sum_r2 - 2 c.h + c' G c
+    let mut sse = range.sum_r2;
+    for i in 0..TERMS {
+        sse -= 2.0 * c[i] * rhs[i];
+        for j in 0..TERMS {
+            sse += c[i] * gram[i][j] * c[j];
+        }
+    }
+
+    // gamma d^2 + alpha' d = gamma (d - m)^2 + (alpha' + 2 gamma m)(d - m) + const,
+    // and the constants all fold into the range mean
+    let domain_mean = d.sum_d / range.n;
+    let coefficients = Polynomial {
+        alpha: (c[3] + 2.0 * c[4] * domain_mean) as f32,
+        gamma: c[4] as f32,
+        beta: (range.sum_r / range.n) as f32,
+        ramp_x: c[1] as f32,
+        ramp_y: c[2] as f32,
+    };
+    (coefficients, (sse.max(0.0) / range.n) as f32)
+}
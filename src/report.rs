@@ -0,0 +1,234 @@
+use std::fmt;
+
+use image::{Rgb, RgbImage};
+
+use crate::encode::EncodedBlock;
+use crate::util::FicHeader;
+use crate::visualize::TRANSFORM_NAMES;
+
+pub const DEFAULT_WORST: usize = 10;
+pub const DEFAULT_BINS: usize = 16;
+const BAR_WIDTH: usize = 40;
+// Domains reused this often or more share the last row of the reuse table
+const REUSE_ROWS: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct ReportOptions {
+    pub worst: usize,
+    pub bins: usize,
+    // Heatmap scale, defaults to the visualizer's
+    pub scale: Option<usize>,
+    // Error drawn white, defaults to the largest
+    pub max_error: Option<f32>,
+}
+
+// Equal-width bins spanning the values
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn new(values: &[f32], bins: usize) -> Self {
+        let bins = bins.max(1);
+        let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mut counts = vec![0; bins];
+        if values.is_empty() {
+            return Self { min: 0.0, max: 0.0, counts };
+        }
+        let width = (max - min) / bins as f32;
+        for &v in values {
+            // The maximum lands in the last bin, and without any spread
+            // everything lands in the first
+            let bin = if width > 0.0 { ((v - min) / width) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Self { min, max, counts }
+    }
+
+    pub fn bin_start(&self, bin: usize) -> f32 {
+        self.min + (self.max - self.min) * bin as f32 / self.counts.len() as f32
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RankedBlock {
+    pub index: usize,
+    pub block: EncodedBlock,
+    pub error: f32,
+}
+
+// Where the encoder struggles and what it reaches for: coefficient and
+// transform histograms, how often each domain is reused, and the blocks with
+// the largest collage error
+#[derive(Clone, Debug)]
+pub struct BlockReport {
+    pub header: FicHeader,
+    pub mean_error: f32,
+    pub alpha: Histogram,
+    pub beta: Histogram,
+    pub transforms: [usize; 8],
+    // Uses of each domain, by domain index
+    pub domain_uses: Vec<usize>,
+    pub worst: Vec<RankedBlock>,
+}
+
+impl BlockReport {
+    pub fn new(header: &FicHeader, blocks: &[EncodedBlock], errors: &[f32], bins: usize, worst: usize) -> Self {
+        let alphas: Vec<f32> = blocks.iter().map(|b| b.alpha).collect();
+        let betas: Vec<f32> = blocks.iter().map(|b| b.beta).collect();
+
+        let mut transforms = [0; 8];
+        let mut domain_uses = vec![0; header.domains_per_row() * header.domains_per_col()];
+        for block in blocks {
+            transforms[(block.transform_id() as usize).min(7)] += 1;
+            if let Some(uses) = domain_uses.get_mut(block.domain_index()) {
+                *uses += 1;
+            }
+        }
+
+        // Ties keep range order, so the report is stable
+        let mut ranked: Vec<RankedBlock> = blocks
+            .iter()
+            .zip(errors)
+            .enumerate()
+            .map(|(index, (&block, &error))| RankedBlock { index, block, error })
+            .collect();
+        ranked.sort_by(|a, b| b.error.total_cmp(&a.error));
+        ranked.truncate(worst);
+
+        Self {
+            header: *header,
+            mean_error: errors.iter().sum::<f32>() / errors.len().max(1) as f32,
+            alpha: Histogram::new(&alphas, bins),
+            beta: Histogram::new(&betas, bins),
+            transforms,
+            domain_uses,
+            worst: ranked,
+        }
+    }
+
+    // The `n` most reused domains as (domain index, uses)
+    pub fn most_reused(&self, n: usize) -> Vec<(usize, usize)> {
+        let mut reused: Vec<(usize, usize)> = self
+            .domain_uses
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, uses)| uses > 0)
+            .collect();
+        reused.sort_by_key(|&(_, uses)| std::cmp::Reverse(uses));
+        reused.truncate(n);
+        reused
+    }
+}
+
+fn bar(count: usize, largest: usize) -> String {
+    "#".repeat((count * BAR_WIDTH).div_ceil(largest.max(1)))
+}
+
+fn write_histogram(f: &mut fmt::Formatter<'_>, name: &str, histogram: &Histogram) -> fmt::Result {
+    writeln!(f, "{name} ({:.3} to {:.3}):", histogram.min, histogram.max)?;
+    let largest = histogram.counts.iter().copied().max().unwrap_or(0);
+    for (bin, &count) in histogram.counts.iter().enumerate() {
+        writeln!(f, "  {:>10.3}  {:>6}  {}", histogram.bin_start(bin), count, bar(count, largest))?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for BlockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let blocks: usize = self.transforms.iter().sum();
+        writeln!(f, "Blocks:      {blocks}, mean collage MSE {:.2}", self.mean_error)?;
+        writeln!(f)?;
+        write_histogram(f, "Alpha", &self.alpha)?;
+        writeln!(f)?;
+        write_histogram(f, "Beta", &self.beta)?;
+        writeln!(f)?;
+
+        writeln!(f, "Transforms:")?;
+        let largest = self.transforms.iter().copied().max().unwrap_or(0);
+        for (transform, &count) in self.transforms.iter().enumerate() {
+            writeln!(f, "  {transform} {:<18}  {count:>6}  {}", TRANSFORM_NAMES[transform], bar(count, largest))?;
+        }
+        writeln!(f)?;
+
+        let used = self.domain_uses.iter().filter(|&&uses| uses > 0).count();
+        writeln!(
+            f,
+            "Domains:     {used} of {} used ({:.1}%)",
+            self.domain_uses.len(),
+            100.0 * used as f64 / self.domain_uses.len().max(1) as f64
+        )?;
+        let mut by_uses = [0; REUSE_ROWS];
+        for &uses in self.domain_uses.iter().filter(|&&uses| uses > 0) {
+            by_uses[uses.min(REUSE_ROWS) - 1] += 1;
+        }
+        let largest = by_uses.iter().copied().max().unwrap_or(0);
+        for (row, &count) in by_uses.iter().enumerate() {
+            let label = if row + 1 == REUSE_ROWS { format!("{REUSE_ROWS}+") } else { (row + 1).to_string() };
+            writeln!(f, "  used {label:>2}x  {count:>6}  {}", bar(count, largest))?;
+        }
+        let most_reused: Vec<String> = self
+            .most_reused(5)
+            .into_iter()
+            .map(|(domain, uses)| {
+                let (x, y) = self.header.domain_origin(domain);
+                format!("#{domain} at ({x}, {y}) x{uses}")
+            })
+            .collect();
+        writeln!(f, "  most reused: {}", most_reused.join(", "))?;
+        writeln!(f)?;
+
+        writeln!(f, "Worst {} blocks:", self.worst.len())?;
+        writeln!(
+            f,
+            "  {:>6}  {:>11}  {:>6}  {:>9}  {:>9}  {:>9}  {:>9}",
+            "range", "at", "domain", "transform", "alpha", "beta", "MSE"
+        )?;
+        for ranked in &self.worst {
+            let (x, y) = self.header.range_origin(ranked.index);
+            writeln!(
+                f,
+                "  {:>6}  {:>11}  {:>6}  {:>9}  {:>9.3}  {:>9.3}  {:>9.2}",
+                ranked.index,
+                format!("({x}, {y})"),
+                ranked.block.domain_index(),
+                ranked.block.transform_id(),
+                ranked.block.alpha,
+                ranked.block.beta,
+                ranked.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Black through red and yellow to white
+fn hot(t: f32) -> Rgb<u8> {
+    let channel = |offset: f32| ((3.0 * t - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgb([channel(0.0), channel(1.0), channel(2.0)])
+}
+
+// Each range filled by its collage error, scaled up by `scale`. The colour
+// follows the RMSE, so white is sqrt(max_error) grey levels off; pass a fixed
+// `max_error` to compare heatmaps of different files.
+pub fn error_heatmap(header: &FicHeader, errors: &[f32], scale: usize, max_error: f32) -> RgbImage {
+    let scale = scale.max(1);
+    let bs = header.block_size as usize;
+    let mut canvas = RgbImage::new(header.width as u32 * scale as u32, header.height as u32 * scale as u32);
+    for (i, &error) in errors.iter().enumerate() {
+        let t = if max_error > 0.0 { (error / max_error).sqrt() } else { 0.0 };
+        let colour = hot(t);
+        let (bx, by) = header.range_origin(i);
+        for y in by * scale..((by + bs) * scale).min(canvas.height() as usize) {
+            for x in bx * scale..((bx + bs) * scale).min(canvas.width() as usize) {
+                canvas.put_pixel(x as u32, y as u32, colour);
+            }
+        }
+    }
+    canvas
+}
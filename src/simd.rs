@@ -0,0 +1,351 @@
+use std::sync::OnceLock;
+
+// Vectorised block kernels. The implementation is picked once at runtime
+// from what the CPU supports (AVX, then SSE2 on x86_64, NEON on aarch64),
+// with the scalar loops as the fallback everywhere else. Any length works;
+// 4x4, 8x8 and 16x16 blocks (16, 64, 256 floats) fill whole vectors.
+pub struct Kernels {
+    pub name: &'static str,
+    dot: unsafe fn(&[f32], &[f32]) -> f32,
+    dot_rows: unsafe fn(&[f32], usize, usize, &[f32]) -> f32,
+    sum: unsafe fn(&[f32]) -> f32,
+    sse: unsafe fn(&[f32], &[f32], f32, f32) -> f32,
+}
+
+impl Kernels {
+    // sum(a[i] * b[i])
+    pub fn dot(&self, a: &[f32], b: &[f32]) -> f32 {
+        assert_eq!(a.len(), b.len(), "blocks must have the same length");
+        // SAFETY: only kernels supported by this CPU are ever constructed
+        unsafe { (self.dot)(a, b) }
+    }
+
+    // `dot` of a contiguous size x size block with one whose rows start
+    // `pitch` floats apart in `rows`, such as a block borrowed from the image
+    pub fn dot_rows(&self, rows: &[f32], pitch: usize, size: usize, block: &[f32]) -> f32 {
+        assert_eq!(block.len(), size * size, "block must be size x size");
+        if size == 0 {
+            return 0.0;
+        }
+        assert!(pitch >= size && rows.len() >= (size - 1) * pitch + size, "rows must hold the whole block");
+        // SAFETY: see `dot`
+        unsafe { (self.dot_rows)(rows, pitch, size, block) }
+    }
+
+    pub fn sum(&self, a: &[f32]) -> f32 {
+        // SAFETY: see `dot`
+        unsafe { (self.sum)(a) }
+    }
+
+    // sum((range[i] - (alpha * domain[i] + beta))^2), i.e. `compute_mse` * n
+    pub fn sse(&self, domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
+        assert_eq!(domain.len(), range.len(), "blocks must have the same length");
+        // SAFETY: see `dot`
+        unsafe { (self.sse)(domain, range, alpha, beta) }
+    }
+}
+
+pub const SCALAR: Kernels = Kernels {
+    name: "scalar",
+    dot: scalar::dot,
+    dot_rows: scalar::dot_rows,
+    sum: scalar::sum,
+    sse: scalar::sse,
+};
+
+static ACTIVE: OnceLock<&'static Kernels> = OnceLock::new();
+
+pub fn kernels() -> &'static Kernels {
+    ACTIVE.get_or_init(|| available_kernels()[0])
+}
+
+// Every implementation this CPU can run, best first
+pub fn available_kernels() -> Vec<&'static Kernels> {
+    #[allow(unused_mut)]
+    let mut available: Vec<&'static Kernels> = Vec::new();
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            available.push(&x86::AVX);
+        }
+        // SSE2 is part of the x86_64 baseline
+        available.push(&x86::SSE2);
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            available.push(&neon::NEON);
+        }
+    }
+
+    available.push(&SCALAR);
+    available
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    kernels().dot(a, b)
+}
+
+pub fn dot_rows(rows: &[f32], pitch: usize, size: usize, block: &[f32]) -> f32 {
+    kernels().dot_rows(rows, pitch, size, block)
+}
+
+pub fn sum(a: &[f32]) -> f32 {
+    kernels().sum(a)
+}
+
+pub fn sse(domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
+    kernels().sse(domain, range, alpha, beta)
+}
+
+mod scalar {
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    pub fn dot_rows(rows: &[f32], pitch: usize, size: usize, block: &[f32]) -> f32 {
+        block
+            .chunks_exact(size)
+            .enumerate()
+            .map(|(y, b)| dot(&rows[y * pitch..y * pitch + size], b))
+            .sum()
+    }
+
+    pub fn sum(a: &[f32]) -> f32 {
+        a.iter().sum()
+    }
+
+    pub fn sse(domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
+        domain
+            .iter()
+            .zip(range)
+            .map(|(d, r)| {
+                let error = r - (alpha * d + beta);
+                error * error
+            })
+            .sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{scalar, Kernels};
+    use std::arch::x86_64::*;
+
+    pub const AVX: Kernels = Kernels {
+        name: "avx",
+        dot: dot_avx,
+        dot_rows: dot_rows_avx,
+        sum: sum_avx,
+        sse: sse_avx,
+    };
+
+    pub const SSE2: Kernels = Kernels {
+        name: "sse2",
+        dot: dot_sse2,
+        dot_rows: dot_rows_sse2,
+        sum: sum_sse2,
+        sse: sse_sse2,
+    };
+
+    #[target_feature(enable = "sse2")]
+    fn hsum128(v: __m128) -> f32 {
+        let high = _mm_movehl_ps(v, v);
+        let pair = _mm_add_ps(v, high);
+        let odd = _mm_shuffle_ps(pair, pair, 0b01);
+        _mm_cvtss_f32(_mm_add_ss(pair, odd))
+    }
+
+    #[target_feature(enable = "avx")]
+    fn hsum256(v: __m256) -> f32 {
+        let low = _mm256_castps256_ps128(v);
+        let high = _mm256_extractf128_ps(v, 1);
+        hsum128(_mm_add_ps(low, high))
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn dot_avx(a: &[f32], b: &[f32]) -> f32 {
+        let body = a.len() / 8 * 8;
+        let mut acc = _mm256_setzero_ps();
+        for i in (0..body).step_by(8) {
+            // SAFETY: i + 8 <= body <= len of both slices
+            let (va, vb) = unsafe { (_mm256_loadu_ps(a.as_ptr().add(i)), _mm256_loadu_ps(b.as_ptr().add(i))) };
+            acc = _mm256_add_ps(acc, _mm256_mul_ps(va, vb));
+        }
+        hsum256(acc) + scalar::dot(&a[body..], &b[body..])
+    }
+
+    // One accumulator across all rows, so there is a single horizontal sum
+    #[target_feature(enable = "avx")]
+    unsafe fn dot_rows_avx(rows: &[f32], pitch: usize, size: usize, block: &[f32]) -> f32 {
+        let body = size / 8 * 8;
+        let mut acc = _mm256_setzero_ps();
+        let mut tail = 0.0;
+        for (y, b) in block.chunks_exact(size).enumerate() {
+            let a = &rows[y * pitch..y * pitch + size];
+            for i in (0..body).step_by(8) {
+                // SAFETY: i + 8 <= body <= size, the length of both rows
+                let (va, vb) = unsafe { (_mm256_loadu_ps(a.as_ptr().add(i)), _mm256_loadu_ps(b.as_ptr().add(i))) };
+                acc = _mm256_add_ps(acc, _mm256_mul_ps(va, vb));
+            }
+            tail += scalar::dot(&a[body..], &b[body..]);
+        }
+        hsum256(acc) + tail
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn sum_avx(a: &[f32]) -> f32 {
+        let body = a.len() / 8 * 8;
+        let mut acc = _mm256_setzero_ps();
+        for i in (0..body).step_by(8) {
+            // SAFETY: i + 8 <= body <= a.len()
+            acc = _mm256_add_ps(acc, unsafe { _mm256_loadu_ps(a.as_ptr().add(i)) });
+        }
+        hsum256(acc) + scalar::sum(&a[body..])
+    }
+
+    #[target_feature(enable = "avx")]
+    unsafe fn sse_avx(domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
+        let body = domain.len() / 8 * 8;
+        let va = _mm256_set1_ps(alpha);
+        let vb = _mm256_set1_ps(beta);
+        let mut acc = _mm256_setzero_ps();
+        for i in (0..body).step_by(8) {
+            // SAFETY: i + 8 <= body <= len of both slices
+            let (d, r) = unsafe {
+                (
+                    _mm256_loadu_ps(domain.as_ptr().add(i)),
+                    _mm256_loadu_ps(range.as_ptr().add(i)),
+                )
+            };
+            let error = _mm256_sub_ps(r, _mm256_add_ps(_mm256_mul_ps(va, d), vb));
+            acc = _mm256_add_ps(acc, _mm256_mul_ps(error, error));
+        }
+        hsum256(acc) + scalar::sse(&domain[body..], &range[body..], alpha, beta)
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn dot_sse2(a: &[f32], b: &[f32]) -> f32 {
+        let body = a.len() / 4 * 4;
+        let mut acc = _mm_setzero_ps();
+        for i in (0..body).step_by(4) {
+            // SAFETY: i + 4 <= body <= len of both slices
+            let (va, vb) = unsafe { (_mm_loadu_ps(a.as_ptr().add(i)), _mm_loadu_ps(b.as_ptr().add(i))) };
+            acc = _mm_add_ps(acc, _mm_mul_ps(va, vb));
+        }
+        hsum128(acc) + scalar::dot(&a[body..], &b[body..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn dot_rows_sse2(rows: &[f32], pitch: usize, size: usize, block: &[f32]) -> f32 {
+        let body = size / 4 * 4;
+        let mut acc = _mm_setzero_ps();
+        let mut tail = 0.0;
+        for (y, b) in block.chunks_exact(size).enumerate() {
+            let a = &rows[y * pitch..y * pitch + size];
+            for i in (0..body).step_by(4) {
+                // SAFETY: i + 4 <= body <= size, the length of both rows
+                let (va, vb) = unsafe { (_mm_loadu_ps(a.as_ptr().add(i)), _mm_loadu_ps(b.as_ptr().add(i))) };
+                acc = _mm_add_ps(acc, _mm_mul_ps(va, vb));
+            }
+            tail += scalar::dot(&a[body..], &b[body..]);
+        }
+        hsum128(acc) + tail
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn sum_sse2(a: &[f32]) -> f32 {
+        let body = a.len() / 4 * 4;
+        let mut acc = _mm_setzero_ps();
+        for i in (0..body).step_by(4) {
+            // SAFETY: i + 4 <= body <= a.len()
+            acc = _mm_add_ps(acc, unsafe { _mm_loadu_ps(a.as_ptr().add(i)) });
+        }
+        hsum128(acc) + scalar::sum(&a[body..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn sse_sse2(domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
+        let body = domain.len() / 4 * 4;
+        let va = _mm_set1_ps(alpha);
+        let vb = _mm_set1_ps(beta);
+        let mut acc = _mm_setzero_ps();
+        for i in (0..body).step_by(4) {
+            // SAFETY: i + 4 <= body <= len of both slices
+            let (d, r) = unsafe { (_mm_loadu_ps(domain.as_ptr().add(i)), _mm_loadu_ps(range.as_ptr().add(i))) };
+            let error = _mm_sub_ps(r, _mm_add_ps(_mm_mul_ps(va, d), vb));
+            acc = _mm_add_ps(acc, _mm_mul_ps(error, error));
+        }
+        hsum128(acc) + scalar::sse(&domain[body..], &range[body..], alpha, beta)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::{scalar, Kernels};
+    use std::arch::aarch64::*;
+
+    pub const NEON: Kernels = Kernels {
+        name: "neon",
+        dot: dot_neon,
+        dot_rows: dot_rows_neon,
+        sum: sum_neon,
+        sse: sse_neon,
+    };
+
+    #[target_feature(enable = "neon")]
+    unsafe fn dot_neon(a: &[f32], b: &[f32]) -> f32 {
+        let body = a.len() / 4 * 4;
+        let mut acc = vdupq_n_f32(0.0);
+        for i in (0..body).step_by(4) {
+            // SAFETY: i + 4 <= body <= len of both slices
+            let (va, vb) = unsafe { (vld1q_f32(a.as_ptr().add(i)), vld1q_f32(b.as_ptr().add(i))) };
+            acc = vaddq_f32(acc, vmulq_f32(va, vb));
+        }
+        vaddvq_f32(acc) + scalar::dot(&a[body..], &b[body..])
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn dot_rows_neon(rows: &[f32], pitch: usize, size: usize, block: &[f32]) -> f32 {
+        let body = size / 4 * 4;
+        let mut acc = vdupq_n_f32(0.0);
+        let mut tail = 0.0;
+        for (y, b) in block.chunks_exact(size).enumerate() {
+            let a = &rows[y * pitch..y * pitch + size];
+            for i in (0..body).step_by(4) {
+                // SAFETY: i + 4 <= body <= size, the length of both rows
+                let (va, vb) = unsafe { (vld1q_f32(a.as_ptr().add(i)), vld1q_f32(b.as_ptr().add(i))) };
+                acc = vaddq_f32(acc, vmulq_f32(va, vb));
+            }
+            tail += scalar::dot(&a[body..], &b[body..]);
+        }
+        vaddvq_f32(acc) + tail
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn sum_neon(a: &[f32]) -> f32 {
+        let body = a.len() / 4 * 4;
+        let mut acc = vdupq_n_f32(0.0);
+        for i in (0..body).step_by(4) {
+            // SAFETY: i + 4 <= body <= a.len()
+            acc = vaddq_f32(acc, unsafe { vld1q_f32(a.as_ptr().add(i)) });
+        }
+        vaddvq_f32(acc) + scalar::sum(&a[body..])
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn sse_neon(domain: &[f32], range: &[f32], alpha: f32, beta: f32) -> f32 {
+        let body = domain.len() / 4 * 4;
+        let va = vdupq_n_f32(alpha);
+        let vb = vdupq_n_f32(beta);
+        let mut acc = vdupq_n_f32(0.0);
+        for i in (0..body).step_by(4) {
+            // SAFETY: i + 4 <= body <= len of both slices
+            let (d, r) = unsafe { (vld1q_f32(domain.as_ptr().add(i)), vld1q_f32(range.as_ptr().add(i))) };
+            let error = vsubq_f32(r, vaddq_f32(vmulq_f32(va, d), vb));
+            acc = vaddq_f32(acc, vmulq_f32(error, error));
+        }
+        vaddvq_f32(acc) + scalar::sse(&domain[body..], &range[body..], alpha, beta)
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, Write};
+use std::time;
+
+use image::GrayImage;
+
+use crate::decode;
+use crate::encode::{self, EncodeOptions, Luminance, SearchMode};
+use crate::metrics;
+use crate::util::FicHeader;
+
+pub const CSV_HEADER: &str =
+    "block_size,stride,quant_bits,search,luminance,ranges,domains,file_bpp,coded_bpp,psnr,ssim,encode_ms,decode_ms";
+
+#[derive(Clone, Debug)]
+pub struct SweepGrid {
+    pub block_sizes: Vec<usize>,
+    pub strides: Vec<usize>,
+    pub quant_bits: Vec<Option<u8>>,
+    pub searches: Vec<SearchMode>,
+    pub luminances: Vec<Luminance>,
+}
+
+impl SweepGrid {
+    pub fn points(&self) -> Vec<EncodeOptions> {
+        let mut points = Vec::new();
+        for &block_size in &self.block_sizes {
+            for &stride in &self.strides {
+                for &quant_bits in &self.quant_bits {
+                    for &search in &self.searches {
+                        for &luminance in &self.luminances {
+                            points.push(EncodeOptions {
+                                search,
+                                quant_bits,
+                                luminance,
+                                ..EncodeOptions::new(block_size, stride)
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        points
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SweepPoint {
+    pub options: EncodeOptions,
+    pub ranges: usize,
+    pub domains: usize,
+    pub file_bpp: f64,
+    pub coded_bpp: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    pub encode_time: time::Duration,
+    pub decode_time: time::Duration,
+}
+
+impl SweepPoint {
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{:.4},{:.4},{:.4},{:.6},{:.3},{:.3}",
+            self.options.block_size,
+            self.options.stride,
+            self.options.quant_bits.map_or("none".to_string(), |b| b.to_string()),
+            self.options.search,
+            self.options.luminance,
+            self.ranges,
+            self.domains,
+            self.file_bpp,
+            self.coded_bpp,
+            self.psnr,
+            self.ssim,
+            self.encode_time.as_secs_f64() * 1000.0,
+            self.decode_time.as_secs_f64() * 1000.0,
+        )
+    }
+}
+
+// Bits per pixel if each block were packed tightly: a domain index just wide
+// enough for the domain pool, 3 transform bits (none for identity search)
+// and either two quantized coefficients or two raw f32s, plus three more
+// f32s for the polynomial model
+pub fn coded_bits_per_pixel(header: &FicHeader, options: &EncodeOptions) -> f64 {
+    let domains = header.domains_per_row() * header.domains_per_col();
+    let index_bits = usize::BITS - (domains.max(2) - 1).leading_zeros();
+    let transform_bits = match options.search {
+        SearchMode::Full => 3,
+        SearchMode::Identity => 0,
+    };
+    let mut coefficient_bits = options.quant_bits.map_or(64, |b| 2 * b as u32);
+    if options.luminance == Luminance::Polynomial {
+        coefficient_bits += 96;
+    }
+    let block_bits = (index_bits + transform_bits + coefficient_bits) as f64;
+    let total_bits = header.header_bytes() as f64 * 8.0 + header.num_blocks as f64 * block_bits;
+    total_bits / (header.width as f64 * header.height as f64)
+}
+
+pub fn run_point(image: &GrayImage, options: &EncodeOptions, iterations: usize) -> Result<SweepPoint, String> {
+    let start = time::Instant::now();
+    let blocks = encode::encode_luma(image, options)?;
+    let encode_time = start.elapsed();
+
+    let header = FicHeader::for_encode(image.width(), image.height(), options, blocks.len());
+
+    let start = time::Instant::now();
+    let decoded = decode::reconstruct(&header, &blocks, iterations);
+    let decode_time = start.elapsed();
+
+    Ok(SweepPoint {
+        options: *options,
+        ranges: blocks.len(),
+        domains: header.domains_per_row() * header.domains_per_col(),
+        file_bpp: metrics::bits_per_pixel(&header, header.file_size() as u64),
+        coded_bpp: coded_bits_per_pixel(&header, options),
+        psnr: metrics::psnr(image, &decoded),
+        ssim: metrics::ssim(image, &decoded),
+        encode_time,
+        decode_time,
+    })
+}
+
+// Runs every grid point and streams a CSV row per point, so partial results
+// survive an interrupted sweep. Points that can't be encoded are skipped.
+pub fn run_sweep<W: Write>(
+    image: &GrayImage,
+    grid: &SweepGrid,
+    iterations: usize,
+    csv: &mut W,
+) -> io::Result<Vec<SweepPoint>> {
+    writeln!(csv, "{CSV_HEADER}")?;
+
+    let points = grid.points();
+    let mut results = Vec::with_capacity(points.len());
+    for (i, options) in points.iter().enumerate() {
+        println!(
+            "[sweep] ({}/{}) block size {}, stride {}, quant bits {}, search {}, luminance {}",
+            i + 1,
+            points.len(),
+            options.block_size,
+            options.stride,
+            options.quant_bits.map_or("none".to_string(), |b| b.to_string()),
+            options.search,
+            options.luminance
+        );
+        match run_point(image, options, iterations) {
+            Ok(point) => {
+                writeln!(csv, "{}", point.csv_row())?;
+                csv.flush()?;
+                println!(
+                    "[sweep] -> {:.3} bpp, PSNR {:.2} dB, SSIM {:.4}",
+                    point.coded_bpp, point.psnr, point.ssim
+                );
+                results.push(point);
+            }
+            Err(e) => eprintln!("[sweep] skipped: {e}"),
+        }
+    }
+    Ok(results)
+}
@@ -0,0 +1,51 @@
+use std::fs;
+
+use image::{Delay, GrayImage};
+
+use crate::animation::{load_frames, save_gif};
+use crate::tests::temp_path;
+use crate::video::Frame;
+
+fn frames() -> Vec<Frame> {
+    (0..3u32)
+        .map(|k| Frame {
+            image: GrayImage::from_fn(12, 8, |x, y| image::Luma([(x * 20 + y * 7 + k * 30) as u8])),
+            delay: Delay::from_numer_denom_ms(40 + 30 * k, 1),
+        })
+        .collect()
+}
+
+#[test]
+fn gif_round_trips_frames_and_delays() {
+    let path = temp_path("clip.gif");
+    save_gif(&path, &frames()).unwrap();
+    let read = load_frames(&path, Delay::from_numer_denom_ms(1, 1)).unwrap();
+    assert_eq!(read, frames());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn directories_load_in_name_order_with_the_default_delay() {
+    let dir = temp_path("frames");
+    fs::create_dir_all(&dir).unwrap();
+    for (i, frame) in frames().iter().enumerate().rev() {
+        frame.image.save(dir.join(format!("frame_{i:02}.png"))).unwrap();
+    }
+    fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+
+    let delay = Delay::from_numer_denom_ms(1000, 12);
+    let read = load_frames(&dir, delay).unwrap();
+    let expected: Vec<Frame> = frames().into_iter().map(|f| Frame { delay, ..f }).collect();
+    assert_eq!(read, expected);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn still_images_are_one_frame() {
+    let path = temp_path("still.png");
+    frames()[1].image.save(&path).unwrap();
+    let delay = Delay::from_numer_denom_ms(100, 1);
+    let read = load_frames(&path, delay).unwrap();
+    assert_eq!(read, vec![Frame { delay, ..frames().swap_remove(1) }]);
+    fs::remove_file(path).unwrap();
+}
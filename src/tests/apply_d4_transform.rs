@@ -19,12 +19,12 @@ fn main() {
     let (device, queue) = init_wgpu();
 
     let test_cases = vec![
-        (2, "src/tests/transforms2x2.wgsl"),
-        (4, "src/tests/transforms4x4.wgsl"),
-        (8, "src/tests/transforms8x8.wgsl"),
+        (2, include_str!("transforms2x2.wgsl")),
+        (4, include_str!("transforms4x4.wgsl")),
+        (8, include_str!("transforms8x8.wgsl")),
     ];
 
-    for (block_dim, shader_path) in test_cases {
+    for (block_dim, shader_source) in test_cases {
         println!("\n===============================");
         println!("Testing {}x{} transforms", block_dim, block_dim);
 
@@ -33,7 +33,6 @@ fn main() {
 
         let block: Vec<f32> = (1..=block_len).map(|x| x as f32).collect();
 
-        let shader_source = std::fs::read_to_string(shader_path).expect("Failed to read WGSL");
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("transform shader"),
             source: wgpu::ShaderSource::Wgsl(shader_source.into()),
@@ -159,7 +158,7 @@ fn main() {
 
             let slice = staging.slice(..);
             slice.map_async(wgpu::MapMode::Read, |_| {});
-            device.poll(wgpu::PollType::Wait);
+            device.poll(wgpu::PollType::Wait).expect("poll failed");
             let data = slice.get_mapped_range();
             let result: &[f32] = bytemuck::cast_slice(&data);
 
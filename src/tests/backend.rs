@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::PathBuf;
+
+use image::GrayImage;
+
+use crate::encode::{encode_with_backend, Backend, BackendUsed, EncodeOptions, GpuSession};
+use crate::tests::temp_path;
+use crate::util::read_fic_file;
+
+fn test_image(name: &str) -> PathBuf {
+    let path = temp_path(&format!("backend_{name}"));
+    GrayImage::from_fn(32, 16, |x, y| image::Luma([(x * 7 + y * 11 + (x * y) % 17) as u8]))
+        .save(&path)
+        .unwrap();
+    path
+}
+
+// Options only the CPU encoder supports send `auto` there, with the same
+// output as asking for the CPU
+#[test]
+fn auto_falls_back_to_the_cpu() {
+    let input = test_image("fallback.png");
+    let (auto, cpu) = (temp_path("backend_fallback_auto.fic"), temp_path("backend_fallback_cpu.fic"));
+    let mut options = EncodeOptions::new(4, 4);
+    options.quant_bits = Some(8);
+    options.threads = 2;
+    options.allow_software_gpu = true;
+
+    let used = encode_with_backend(&input, &auto, &options, Backend::Auto, &GpuSession::default()).unwrap();
+    assert_eq!(used, BackendUsed::Cpu { threads: 2 });
+    assert_eq!(encode_with_backend(&input, &cpu, &options, Backend::Cpu, &GpuSession::default()).unwrap(), used);
+    assert_eq!(fs::read(&auto).unwrap(), fs::read(&cpu).unwrap());
+    assert!(encode_with_backend(&input, &temp_path("backend_fallback_gpu.fic"), &options, Backend::Gpu, &GpuSession::default()).is_err());
+
+    for path in [input, auto, cpu] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+// Whichever backend `auto` picks, the file reads back like a CPU encode
+#[test]
+fn auto_writes_the_cpu_format() {
+    let input = test_image("format.png");
+    let (auto, cpu) = (temp_path("backend_format_auto.fic"), temp_path("backend_format_cpu.fic"));
+    let mut options = EncodeOptions::new(4, 2);
+    options.allow_software_gpu = true;
+
+    let used = encode_with_backend(&input, &auto, &options, Backend::Auto, &GpuSession::default()).unwrap();
+    println!("auto encoded on the {used}");
+    encode_with_backend(&input, &cpu, &options, Backend::Cpu, &GpuSession::default()).unwrap();
+    assert_eq!(fs::metadata(&auto).unwrap().len(), fs::metadata(&cpu).unwrap().len());
+    let (auto_header, auto_blocks) = read_fic_file(&auto).unwrap();
+    let (cpu_header, cpu_blocks) = read_fic_file(&cpu).unwrap();
+    assert_eq!(
+        (auto_header.width, auto_header.height, auto_header.block_size, auto_header.stride, auto_header.num_blocks),
+        (cpu_header.width, cpu_header.height, cpu_header.block_size, cpu_header.stride, cpu_header.num_blocks)
+    );
+    assert_eq!(auto_header.luminance, cpu_header.luminance);
+    assert_eq!(auto_blocks.len(), cpu_blocks.len());
+
+    for path in [input, auto, cpu] {
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+
+use image::GrayImage;
+
+use crate::batch::{collect_inputs, run_batch, wildcard_match, BatchMode, BatchOptions, IMAGE_EXTENSIONS};
+use crate::encode::{Backend, EncodeOptions};
+use crate::gpu::shader::ShaderRegistry;
+use crate::tests::temp_path;
+
+#[test]
+fn wildcards_match_file_names() {
+    assert!(wildcard_match("*.png", "lena.png"));
+    assert!(wildcard_match("lena*.png", "lena256.png"));
+    assert!(wildcard_match("lena*.png", "lena.png"));
+    assert!(wildcard_match("*a*b*", "xaybz"));
+    assert!(wildcard_match("lena???.png", "lena128.png"));
+    assert!(wildcard_match("*", ""));
+    assert!(!wildcard_match("lena???.png", "lena64.png"));
+    assert!(!wildcard_match("*.png", "lena.jpg"));
+    assert!(!wildcard_match("*.png", "lena.png.bak"));
+    assert!(!wildcard_match("lena", "lena256"));
+    assert!(!wildcard_match("?", ""));
+}
+
+#[test]
+fn inputs_come_from_a_directory_or_a_glob() {
+    let dir = temp_path("batch_inputs");
+    fs::create_dir_all(dir.join("nested.png")).unwrap();
+    for name in ["b.png", "a.JPG", "c.fic", "notes.txt", "a2.png"] {
+        fs::write(dir.join(name), b"").unwrap();
+    }
+
+    // A directory takes every file with a known extension, in name order,
+    // and skips subdirectories
+    let names = |inputs: Vec<std::path::PathBuf>| -> Vec<String> {
+        inputs.iter().map(|p| p.file_name().unwrap().to_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(names(collect_inputs(dir.to_str().unwrap(), IMAGE_EXTENSIONS).unwrap()), ["a.JPG", "a2.png", "b.png"]);
+    assert_eq!(names(collect_inputs(dir.to_str().unwrap(), &["fic"]).unwrap()), ["c.fic"]);
+
+    let glob = |pattern: &str| names(collect_inputs(dir.join(pattern).to_str().unwrap(), IMAGE_EXTENSIONS).unwrap());
+    assert_eq!(glob("*.png"), ["a2.png", "b.png"]);
+    assert_eq!(glob("a?.png"), ["a2.png"]);
+    assert!(glob("*.gif").is_empty());
+    assert!(collect_inputs(dir.join("missing").join("*.png").to_str().unwrap(), IMAGE_EXTENSIONS).is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn inputs_sharing_a_stem_fail_instead_of_overwriting_each_other() {
+    let dir = temp_path("batch_clash");
+    fs::create_dir_all(&dir).unwrap();
+    let image = GrayImage::from_fn(16, 16, |x, y| image::Luma([(x * 13 + y * 7) as u8]));
+    for name in ["a.png", "a.jpg", "b.png"] {
+        image.save(dir.join(name)).unwrap();
+    }
+    let inputs = collect_inputs(dir.to_str().unwrap(), IMAGE_EXTENSIONS).unwrap();
+    let options = BatchOptions {
+        mode: BatchMode::Encode,
+        encode: EncodeOptions::new(4, 4),
+        backend: Backend::Cpu,
+        iterations: 4,
+        jobs: 2,
+        output_dir: None,
+        originals_dir: None,
+        shaders: ShaderRegistry::default(),
+    };
+
+    let results = run_batch(&inputs, &options);
+    let outcome: Vec<(&str, bool)> = results
+        .iter()
+        .map(|r| (r.input.file_name().unwrap().to_str().unwrap(), r.error.is_none()))
+        .collect();
+    assert_eq!(outcome, [("a.jpg", false), ("a.png", false), ("b.png", true)]);
+    let error = results[0].error.as_deref().unwrap();
+    assert!(error.contains("a.jpg") && error.contains("a.png"), "{error}");
+    assert!(!dir.join("a.fic").exists());
+    assert!(dir.join("b.fic").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,80 @@
+use crate::alpha_beta::{compute_alpha_beta, compute_mse};
+use crate::block_extractor::{BlockExtractor, BlockPixels, BlockView};
+use crate::encode::{encode_block, EncodeOptions, Luminance};
+use crate::tests::block_stats;
+use crate::transform::apply_d4_transform;
+
+fn image(width: usize, height: usize) -> Vec<f32> {
+    (0..width * height).map(|i| ((i * 53 + i / width * 7) % 256) as f32).collect()
+}
+
+// The copy the extractor used to make for every block
+fn copy_block(pixels: &[f32], width: usize, x: usize, y: usize, size: usize) -> Vec<f32> {
+    (0..size)
+        .flat_map(|dy| (0..size).map(move |dx| pixels[(y + dy) * width + x + dx]))
+        .collect()
+}
+
+#[test]
+fn views_read_the_same_pixels_as_copies() {
+    let (width, height) = (11, 7);
+    let pixels = image(width, height);
+    let view = BlockView::new(&pixels, width, 6, 2, 5);
+    let copy = copy_block(&pixels, width, 6, 2, 5);
+
+    assert_eq!(view.pixel_count(), copy.len());
+    assert_eq!(view.to_vec(), copy);
+    assert_eq!(view.pixels().collect::<Vec<_>>(), copy);
+    for (i, &v) in copy.iter().enumerate() {
+        assert_eq!(view.pixel(i), v);
+    }
+    assert_eq!(view.row(4), &copy[20..25]);
+}
+
+#[test]
+fn positions_cover_the_grid_in_row_order() {
+    let extractor = BlockExtractor::new(image(10, 9), 10, 9, 4, 3);
+    let domains: Vec<_> = extractor.domain_positions().collect();
+    assert_eq!(domains, [(0, 0), (3, 0), (6, 0), (0, 3), (3, 3), (6, 3)]);
+    let ranges: Vec<_> = extractor.range_positions().collect();
+    assert_eq!(ranges, [(0, 0), (4, 0), (0, 4), (4, 4)]);
+}
+
+#[test]
+fn generic_functions_agree_on_views_and_copies() {
+    let (width, height) = (16, 12);
+    let pixels = image(width, height);
+    let extractor = BlockExtractor::new(pixels.clone(), width, height, 4, 2);
+    let domain = extractor.view(5, 3);
+    let range = extractor.view(8, 4);
+    let domain_copy = copy_block(&pixels, width, 5, 3, 4);
+    let range_copy = copy_block(&pixels, width, 8, 4, 4);
+
+    for transform_id in 0..8 {
+        assert_eq!(
+            apply_d4_transform(&domain, 4, transform_id),
+            apply_d4_transform(&domain_copy, 4, transform_id)
+        );
+    }
+
+    let (alpha, beta) = compute_alpha_beta(&domain, &range, Luminance::Affine);
+    assert_eq!((alpha, beta), compute_alpha_beta(&domain_copy, &range_copy, Luminance::Affine));
+    assert_eq!(
+        compute_mse(&domain, &range, alpha, beta),
+        compute_mse(&domain_copy, &range_copy, alpha, beta)
+    );
+
+    let options = EncodeOptions::new(4, 2);
+    let domain_views: Vec<_> = extractor.domain_views().collect();
+    let domain_copies: Vec<_> = extractor
+        .domain_positions()
+        .map(|(x, y)| copy_block(&pixels, width, x, y, 4))
+        .collect();
+    let range_stats = block_stats(&range);
+    assert_eq!(range_stats, block_stats(&range_copy));
+    let domain_stats = extractor.domain_stats();
+    assert_eq!(
+        encode_block(&range, range_stats, &domain_views, &domain_stats, &options),
+        encode_block(&range_copy, range_stats, &domain_copies, &domain_stats, &options)
+    );
+}
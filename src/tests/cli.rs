@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::cli::{parse_args, run, Command, EXIT_FAILURE, EXIT_USAGE};
+use crate::tests::temp_path;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+fn parse(line: &str) -> Result<Command, String> {
+    parse_args(&args(line))
+}
+
+#[test]
+fn bad_arguments_are_rejected() {
+    let error = parse("encode a.png --blocksize 8").unwrap_err();
+    assert!(error.contains("unknown option '--blocksize'"), "{error}");
+    let error = parse("encode a.png --stride").unwrap_err();
+    assert!(error.contains("'--stride' needs a value"), "{error}");
+    let error = parse("encode a.png --stride four").unwrap_err();
+    assert!(error.contains("expects a number"), "{error}");
+    let error = parse("encode").unwrap_err();
+    assert!(error.contains("missing input file"), "{error}");
+    let error = parse("info a.fic b.fic").unwrap_err();
+    assert!(error.contains("expected one input file"), "{error}");
+    assert!(parse("frobnicate").unwrap_err().contains("unknown command"));
+}
+
+#[test]
+fn inline_and_separate_values_parse_the_same() {
+    let inline = parse("encode a.png --stride=3 --block-size=4 -o=b.fic").unwrap();
+    let separate = parse("encode a.png --stride 3 --block-size 4 -o b.fic").unwrap();
+    assert_eq!(format!("{inline:?}"), format!("{separate:?}"));
+
+    // The last occurrence of a flag wins
+    let repeated = parse("encode a.png --stride 5 --block-size 4 --stride 3 --output b.fic").unwrap();
+    assert_eq!(format!("{repeated:?}"), format!("{separate:?}"));
+}
+
+#[test]
+fn defaults_fill_in_missing_options() {
+    let Command::Encode {
+        input, output, options, ..
+    } = parse("encode dir/a.png").unwrap()
+    else {
+        panic!("not an encode");
+    };
+    assert_eq!(input, PathBuf::from("dir/a.png"));
+    assert_eq!(output, PathBuf::from("dir/a.fic"));
+    assert_eq!((options.block_size, options.stride), (8, 4));
+
+    let Command::Decode { output, iterations, .. } = parse("decode a.fic").unwrap() else {
+        panic!("not a decode");
+    };
+    assert_eq!(output, PathBuf::from("a.decoded.png"));
+    assert_eq!(iterations, 10);
+
+    assert!(matches!(parse("").unwrap(), Command::Help));
+    assert!(matches!(parse("--help").unwrap(), Command::Help));
+}
+
+#[test]
+fn exit_codes_follow_the_outcome() {
+    assert_eq!(run(&args("help")), ExitCode::SUCCESS);
+    assert_eq!(run(&args("encode a.png --stride")), ExitCode::from(EXIT_USAGE));
+    assert_eq!(run(&args("frobnicate")), ExitCode::from(EXIT_USAGE));
+
+    let missing = temp_path("missing.fic");
+    let missing = missing.to_str().unwrap();
+    assert_eq!(run(&args(&format!("info {missing}"))), ExitCode::from(EXIT_FAILURE));
+    assert_eq!(run(&args(&format!("decode {missing}"))), ExitCode::from(EXIT_FAILURE));
+}
@@ -0,0 +1,84 @@
+use std::mem::size_of;
+
+use image::GrayImage;
+
+use crate::block_extractor::{BlockExtractor, BlockStats, BlockView};
+use crate::domain_pool::DomainPool;
+use crate::encode::{encode_luma, EncodeOptions, Luminance, Matcher, SearchMode};
+use crate::polynomial::DomainMoments;
+use crate::transform::{apply_d4_transform, inverse_d4_transform};
+
+// The pool only precomputes what the naive search recomputes per range, so
+// it must pick the same domain, transform and coefficients. The naive search
+// measures the error from the pixels and the pool from the block sums, so
+// that may differ by rounding.
+#[test]
+fn pool_matches_the_naive_search() {
+    let image = GrayImage::from_fn(24, 24, |x, y| {
+        let h = (x * 73856093) ^ (y * 19349663);
+        image::Luma([((h ^ (h >> 7)) % 256) as u8])
+    });
+    for search in [SearchMode::Full, SearchMode::Identity] {
+        for quant_bits in [None, Some(6)] {
+            for luminance in [Luminance::Affine, Luminance::Orthogonal] {
+                for (block_size, stride) in [(4, 2), (8, 4)] {
+                    let options = |matcher| EncodeOptions {
+                        matcher,
+                        search,
+                        quant_bits,
+                        luminance,
+                        ..EncodeOptions::new(block_size, stride)
+                    };
+                    let naive = encode_luma(&image, &options(Matcher::Naive)).unwrap();
+                    let pool = encode_luma(&image, &options(Matcher::Pool)).unwrap();
+                    let case = format!("{search} search, {quant_bits:?} bits, {luminance}, block size {block_size}");
+                    assert_eq!(naive.len(), pool.len(), "{case}");
+                    for (i, (n, p)) in naive.iter().zip(&pool).enumerate() {
+                        assert_eq!(
+                            (n.meta, n.alpha.to_bits(), n.beta.to_bits()),
+                            (p.meta, p.alpha.to_bits(), p.beta.to_bits()),
+                            "{case}, range {i}"
+                        );
+                        assert!((n.error - p.error).abs() <= 1e-5 * n.error.max(1.0), "{case}, range {i}: {n:?} vs {p:?}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn inverse_transforms_undo_every_isometry() {
+    let block: Vec<f32> = (0..16).map(|v| v as f32).collect();
+    for transform_id in 0..8 {
+        let transformed = apply_d4_transform(&block, 4, transform_id);
+        assert_eq!(apply_d4_transform(&transformed, 4, inverse_d4_transform(transform_id)), block);
+    }
+}
+
+// The pool borrows the domains, so what it keeps per domain must not grow
+// with the block size (up to the slack of a growing Vec), and a 16x16
+// search must stay well under one copy of every domain, let alone eight
+#[test]
+fn pool_memory_does_not_grow_with_the_block_size() {
+    let (width, height) = (64, 64);
+    let pixels: Vec<f32> = (0..width * height).map(|i| (i % 251) as f32).collect();
+    for luminance in [Luminance::Affine, Luminance::Polynomial] {
+        let mut per_domain = size_of::<BlockView>() + size_of::<BlockStats>();
+        if luminance == Luminance::Polynomial {
+            per_domain += size_of::<DomainMoments>();
+        }
+        for block_size in [4, 8, 16] {
+            let extractor = BlockExtractor::new(pixels.clone(), width, height, block_size, 1);
+            let options = EncodeOptions {
+                luminance,
+                ..EncodeOptions::new(block_size, 1)
+            };
+            let pool = DomainPool::new(&extractor, &options);
+            let case = format!("{luminance}, block size {block_size}");
+            assert!(pool.heap_bytes() <= 2 * pool.len() * per_domain, "{case}");
+            let copies = pool.len() * block_size * block_size * size_of::<f32>();
+            assert!(block_size < 16 || pool.heap_bytes() < copies / 4, "{case}");
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use image::GrayImage;
+
+use crate::encode::{encode_in_parallel, encode_luma, EncodeOptions, EncodedBlock, Luminance, SearchMode};
+
+fn bits(blocks: &[EncodedBlock]) -> Vec<u32> {
+    bytemuck::cast_slice(blocks).to_vec()
+}
+
+// 35 ranges, which 4 threads can't split evenly
+#[test]
+fn thread_count_does_not_change_the_output() {
+    let image = GrayImage::from_fn(28, 20, |x, y| image::Luma([((x * 29 + y * 53 + x * y * 7) % 256) as u8]));
+    for search in [SearchMode::Full, SearchMode::Identity] {
+        for luminance in [Luminance::Affine, Luminance::Orthogonal] {
+            let options = |threads| EncodeOptions {
+                search,
+                luminance,
+                threads,
+                ..EncodeOptions::new(4, 2)
+            };
+            let single = encode_luma(&image, &options(1)).unwrap();
+            let parallel = encode_luma(&image, &options(4)).unwrap();
+            assert_eq!(single.len(), 35);
+            assert_eq!(bits(&single), bits(&parallel), "{search} search, {luminance} luminance");
+        }
+    }
+}
+
+#[test]
+fn parallel_results_land_at_their_range_index() {
+    for threads in [1, 2, 3, 8, 64] {
+        let blocks = encode_in_parallel(10, threads, |i| EncodedBlock::affine(i as u32, 0.0, 0.0));
+        let metas: Vec<u32> = blocks.iter().map(|b| b.meta).collect();
+        assert_eq!(metas, (0..10).collect::<Vec<_>>(), "{threads} threads");
+    }
+    assert!(encode_in_parallel(0, 4, |_| unreachable!()).is_empty());
+}
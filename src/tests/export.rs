@@ -0,0 +1,156 @@
+use image::GrayImage;
+
+use crate::decode::collage_errors;
+use crate::encode::{encode_luma, EncodeOptions, EncodedBlock, Luminance};
+use crate::export::{parse_csv, parse_json, write_csv, write_json};
+use crate::util::FicHeader;
+
+fn header(luminance: Luminance) -> FicHeader {
+    FicHeader {
+        width: 16,
+        height: 8,
+        block_size: 4,
+        stride: 4,
+        num_blocks: 8,
+        luminance,
+    }
+}
+
+// Values whose decimal forms need care: thirds, tiny, huge, subnormal, -0
+fn blocks() -> Vec<EncodedBlock> {
+    let awkward = [1.0 / 3.0, -0.0, 1e-30, f32::MAX, f32::MIN_POSITIVE / 8.0, -7.25, 0.1, 255.0];
+    (0..8)
+        .map(|i| EncodedBlock {
+            gamma: awkward[(i + 1) % 8],
+            ramp_x: awkward[(i + 2) % 8],
+            ramp_y: awkward[(i + 3) % 8],
+            ..EncodedBlock::affine((i as u32 % 8) << 16 | (7 - i as u32), awkward[i], awkward[(i + 4) % 8])
+                .with_error(awkward[(i + 5) % 8].abs())
+        })
+        .collect()
+}
+
+fn affine_blocks() -> Vec<EncodedBlock> {
+    blocks()
+        .iter()
+        .map(|b| EncodedBlock::affine(b.meta, b.alpha, b.beta).with_error(b.error))
+        .collect()
+}
+
+fn bits(blocks: &[EncodedBlock]) -> Vec<[u32; 6]> {
+    blocks
+        .iter()
+        .map(|b| [b.alpha, b.beta, b.gamma, b.ramp_x, b.ramp_y, b.error].map(f32::to_bits))
+        .collect()
+}
+
+fn json(header: &FicHeader, blocks: &[EncodedBlock]) -> String {
+    let mut out = Vec::new();
+    write_json(&mut out, header, blocks).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn csv(header: &FicHeader, blocks: &[EncodedBlock]) -> String {
+    let mut out = Vec::new();
+    write_csv(&mut out, header, blocks).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn exports_round_trip_bit_for_bit() {
+    for (luminance, blocks) in [(Luminance::Polynomial, blocks()), (Luminance::Orthogonal, affine_blocks())] {
+        let header = header(luminance);
+        for (read_header, read) in [
+            parse_json(&json(&header, &blocks)).unwrap(),
+            parse_csv(&csv(&header, &blocks), None).unwrap(),
+        ] {
+            assert_eq!(read, blocks);
+            assert_eq!(bits(&read), bits(&blocks));
+            assert_eq!(read_header.luminance, luminance);
+            assert_eq!(read_header.num_blocks, 8);
+            assert_eq!((read_header.width, read_header.height), (16, 8));
+        }
+    }
+}
+
+#[test]
+fn imports_accept_rewritten_files() {
+    // Reordered keys, whole numbers as floats, nulls, extra fields and columns
+    let json = r#"{"blocks": [
+        {"transform": 2.0, "domain": 3, "beta": 1e1, "alpha": -0.5, "error": null, "note": "edited"}
+    ], "luminance": "orthogonal", "stride": 4, "block_size": 4, "height": 4, "width": 16, "extra": [true, {}]}"#;
+    let (header, blocks) = parse_json(json).unwrap();
+    assert_eq!(header.luminance, Luminance::Orthogonal);
+    assert_eq!(blocks, vec![EncodedBlock::affine(2 << 16 | 3, -0.5, 10.0)]);
+
+    let csv = "\n,beta,alpha,transform,domain\n0,10,-0.5,2.0,3\n";
+    let (_, from_csv) = parse_csv(csv, Some(header)).unwrap();
+    assert_eq!(from_csv, blocks);
+}
+
+#[test]
+fn imports_reject_bad_blocks() {
+    let header = header(Luminance::Affine);
+    let text = csv(&header, &affine_blocks());
+    let lines: Vec<&str> = text.lines().collect();
+    let with_row = |row: &str| [lines[0], lines[1], row].join("\n");
+
+    // The 16x8 image with stride 4 has 4 x 2 domains
+    assert!(parse_csv(&with_row("0,0,0,7,7,1,0,0"), None).is_ok());
+    assert!(parse_csv(&with_row("0,0,0,8,0,1,0,0"), None).is_err());
+    assert!(parse_csv(&with_row("0,0,0,0,8,1,0,0"), None).is_err());
+    assert!(parse_csv(&with_row("1,0,0,0,0,1,0,0"), None).is_err());
+    assert!(parse_csv(&with_row("0,0,0,0,0,nan,0,0"), None).is_err());
+    assert!(parse_csv(&with_row("0,0,0,0.5,0,1,0,0"), None).is_err());
+    assert!(parse_csv(&with_row("0,0,0,0,0,1,0"), None).is_err());
+    assert!(parse_csv(&lines[1..].join("\n"), None).is_err());
+
+    // Polynomial files need their extra terms
+    let polynomial = text.replace("luminance=affine", "luminance=polynomial");
+    assert!(parse_csv(&polynomial, None).is_err());
+
+    assert!(parse_json(r#"{"width": 16, "height": 8, "block_size": 9, "stride": 4, "luminance": "affine", "blocks": []}"#).is_err());
+    assert!(parse_json(r#"{"width": 16, "height": 8, "block_size": 4, "stride": 4, "luminance": "affine", "blocks": [}"#).is_err());
+}
+
+#[test]
+fn exports_reject_what_they_cannot_represent() {
+    let header = header(Luminance::Affine);
+    let mut out = Vec::new();
+
+    let mut blocks = affine_blocks();
+    blocks[3].alpha = f32::NAN;
+    assert!(write_json(&mut out, &header, &blocks).is_err());
+
+    let mut blocks = affine_blocks();
+    blocks[3].error = f32::INFINITY;
+    assert!(write_json(&mut out, &header, &blocks).is_err());
+
+    let mut blocks = affine_blocks();
+    blocks[3].meta |= 1 << 24;
+    assert!(write_csv(&mut out, &header, &blocks).is_err());
+}
+
+#[test]
+fn collage_errors_measure_each_block_against_the_image() {
+    let image = GrayImage::from_fn(16, 16, |x, y| image::Luma([((x / 4 * 7 + y / 4 * 13) % 5 * 40 + 20) as u8]));
+    let options = EncodeOptions {
+        luminance: Luminance::Orthogonal,
+        ..EncodeOptions::new(4, 2)
+    };
+    let blocks = encode_luma(&image, &options).unwrap();
+    let header = FicHeader::for_encode(16, 16, &options, blocks.len());
+
+    // Flat cells are matched exactly
+    let errors = collage_errors(&header, &blocks, &image).unwrap();
+    assert_eq!(errors, vec![0.0; blocks.len()]);
+
+    // Flattening a range to its cell value + 8 costs 64 on that block only
+    let mut brighter = blocks.clone();
+    brighter[5].beta += 8.0;
+    let errors = collage_errors(&header, &brighter, &image).unwrap();
+    assert!((errors[5] - 64.0).abs() < 1e-3, "{}", errors[5]);
+    assert_eq!(errors.iter().filter(|&&e| e != 0.0).count(), 1);
+
+    assert!(collage_errors(&header, &blocks, &GrayImage::new(8, 16)).is_err());
+}
@@ -0,0 +1,96 @@
+use crate::block_extractor::BlockExtractor;
+use crate::encode::{encode_block, EncodeOptions, Matcher, SearchMode};
+use crate::fft::{Complex, Fft, Fft2d};
+use crate::fft_search::FftSearch;
+
+fn signal(len: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as f32
+        })
+        .collect()
+}
+
+fn assert_close(a: Complex, b: Complex) {
+    assert!(
+        (a.re - b.re).abs() < 1e-6 && (a.im - b.im).abs() < 1e-6,
+        "{a:?} vs {b:?}"
+    );
+}
+
+#[test]
+fn fft_matches_direct_dft() {
+    for len in [1, 2, 8, 64] {
+        let input: Vec<Complex> = signal(2 * len, 7)
+            .chunks(2)
+            .map(|p| Complex::new(p[0] as f64, p[1] as f64))
+            .collect();
+        let mut output = input.clone();
+        Fft::new(len).process(&mut output, false);
+
+        for (k, &actual) in output.iter().enumerate() {
+            let mut expected = Complex::ZERO;
+            for (n, &x) in input.iter().enumerate() {
+                let angle = -2.0 * std::f64::consts::PI * (k * n) as f64 / len as f64;
+                expected = expected + x * Complex::new(angle.cos(), angle.sin());
+            }
+            assert_close(actual, expected);
+        }
+
+        Fft::new(len).process(&mut output, true);
+        for (&a, &b) in output.iter().zip(&input) {
+            assert_close(a, b);
+        }
+    }
+}
+
+#[test]
+fn fft_2d_round_trips() {
+    let (width, height) = (16, 8);
+    let input: Vec<Complex> = signal(width * height, 11)
+        .into_iter()
+        .map(|v| Complex::new(v as f64, 0.0))
+        .collect();
+    let fft = Fft2d::new(width, height);
+    let mut data = input.clone();
+    fft.forward_rows(&mut data, 0..height);
+    fft.inverse_rows(&mut data, 0..height);
+    for (&a, &b) in data.iter().zip(&input) {
+        assert_close(a, b);
+    }
+}
+
+// Odd image sizes exercise the zero padding up to the FFT size
+#[test]
+fn fft_search_finds_the_same_matches_as_encode_block() {
+    let (width, height) = (23, 18);
+    let image = signal(width * height, 3);
+
+    for (block_size, stride, search, quant_bits) in [
+        (4, 2, SearchMode::Full, None),
+        (4, 3, SearchMode::Identity, None),
+        (5, 1, SearchMode::Full, Some(6)),
+    ] {
+        let options = EncodeOptions {
+            search,
+            quant_bits,
+            matcher: Matcher::Fft,
+            ..EncodeOptions::new(block_size, stride)
+        };
+        let extractor = BlockExtractor::new(image.clone(), width, height, block_size, stride);
+        let domain_blocks: Vec<_> = extractor.domain_views().collect();
+        let fft_search = FftSearch::new(&extractor, &options);
+        assert_eq!(fft_search.len(), domain_blocks.len());
+
+        let domain_stats = extractor.domain_stats();
+        for (range, stats) in extractor.range_views().zip(extractor.range_stats()) {
+            let expected = encode_block(&range, stats, &domain_blocks, &domain_stats, &options);
+            let actual = fft_search.encode_range(&range, stats, &options);
+            assert_eq!(actual.meta, expected.meta, "{options:?}");
+            assert!((actual.alpha - expected.alpha).abs() < 1e-4, "{options:?}");
+            assert!((actual.beta - expected.beta).abs() < 1e-2, "{options:?}");
+        }
+    }
+}
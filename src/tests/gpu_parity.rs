@@ -0,0 +1,160 @@
+use crate::block_extractor::BlockExtractor;
+use crate::encode::{encode_block, EncodeOptions, EncodedBlock};
+use crate::gpu::emulator::transform_and_compare;
+use crate::gpu::encoder::{request_adapter, EncodeParams, GpuEncoder};
+use crate::gpu::shader::{Kernel, SOFTWARE_LOOP_LIMIT};
+
+// Whole grey levels, like a decoded image. Block sums of those stay exact in
+// f32 up to 16x16, so summation order can't tell the encoders apart.
+fn signal(width: u32, height: u32, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+    (0..width * height)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as f32
+        })
+        .collect()
+}
+
+fn params(width: u32, height: u32, block_size: u32, stride: u32, kernel: Kernel) -> EncodeParams {
+    EncodeParams {
+        image_width: width,
+        image_height: height,
+        range_size: block_size,
+        domain_size: block_size,
+        stride,
+        kernel,
+    }
+}
+
+// Small enough that software adapters don't hit their loop limits
+const CASES: [(u32, u32); 5] = [(2, 1), (3, 2), (4, 2), (8, 4), (16, 4)];
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 16;
+
+fn assert_same_match(actual: &[EncodedBlock], expected: &[EncodedBlock], context: &str) {
+    assert_eq!(actual.len(), expected.len(), "{context}");
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert_eq!(a.meta, e.meta, "{context}, range {i}");
+        assert!((a.alpha - e.alpha).abs() < 1e-3, "{context}, range {i}: {a:?} vs {e:?}");
+        assert!((a.beta - e.beta).abs() < 0.1, "{context}, range {i}: {a:?} vs {e:?}");
+        assert!((a.error - e.error).abs() <= 1e-3 * e.error.max(1.0), "{context}, range {i}: {a:?} vs {e:?}");
+    }
+}
+
+// The kernel only differs from the CPU search by its denominator bias and
+// early exit, neither of which changes a match on textured blocks
+#[test]
+fn emulator_matches_encode_block() {
+    for (block_size, stride) in CASES {
+        let image = signal(WIDTH, HEIGHT, block_size * 31 + stride);
+        let options = EncodeOptions::new(block_size as usize, stride as usize);
+        let extractor = BlockExtractor::new(image.clone(), WIDTH as usize, HEIGHT as usize, options.block_size, options.stride);
+        let domain_blocks: Vec<_> = extractor.domain_views().collect();
+        let domain_stats = extractor.domain_stats();
+        let expected: Vec<EncodedBlock> = extractor
+            .range_views()
+            .zip(extractor.range_stats())
+            .map(|(range, stats)| encode_block(&range, stats, &domain_blocks, &domain_stats, &options))
+            .collect();
+
+        let actual = transform_and_compare(&image, &params(WIDTH, HEIGHT, block_size, stride, Kernel::Serial));
+        assert_same_match(&actual, &expected, &format!("block size {block_size}, stride {stride}"));
+    }
+}
+
+// The emulator has no loop limit, so it shows what the serial kernel computes
+// on a search longer than a software adapter runs, which the encoder moves to
+// the reduction kernel there
+#[test]
+fn serial_emulator_matches_encode_block_past_the_software_loop_limit() {
+    let (width, height, block_size, stride) = (64, 48, 4, 2);
+    let domains = (((width - block_size) / stride + 1) * ((height - block_size) / stride + 1)) as u64;
+    assert!(Kernel::Serial.loop_iterations(block_size, domains) > SOFTWARE_LOOP_LIMIT);
+
+    let image = signal(width, height, 11);
+    let options = EncodeOptions::new(block_size as usize, stride as usize);
+    let extractor = BlockExtractor::new(image.clone(), width as usize, height as usize, options.block_size, options.stride);
+    let domain_blocks: Vec<_> = extractor.domain_views().collect();
+    assert_eq!(domain_blocks.len() as u64, domains);
+    let domain_stats = extractor.domain_stats();
+    let expected: Vec<EncodedBlock> = extractor
+        .range_views()
+        .zip(extractor.range_stats())
+        .map(|(range, stats)| encode_block(&range, stats, &domain_blocks, &domain_stats, &options))
+        .collect();
+
+    let actual = transform_and_compare(&image, &params(width, height, block_size, stride, Kernel::Serial));
+    assert_same_match(&actual, &expected, "serial kernel past the loop limit");
+}
+
+// Splitting the domains between threads mustn't change the arithmetic or
+// which candidate wins, early exits included
+#[test]
+fn reduction_kernel_emulator_matches_the_serial_one_exactly() {
+    let mut image = signal(WIDTH, HEIGHT, 5);
+    // Repeat a block so some ranges find an exact match partway through
+    for y in 0..4 {
+        for x in 0..4 {
+            image[((y + 8) * WIDTH + x + 20) as usize] = image[(y * WIDTH + x + 6) as usize];
+        }
+    }
+    for (block_size, stride) in CASES.into_iter().chain([(4, 1), (2, 2)]) {
+        let serial = transform_and_compare(&image, &params(WIDTH, HEIGHT, block_size, stride, Kernel::Serial));
+        let reduction = transform_and_compare(&image, &params(WIDTH, HEIGHT, block_size, stride, Kernel::Reduction));
+        assert_eq!(reduction, serial, "block size {block_size}, stride {stride}");
+    }
+}
+
+#[test]
+fn emulator_stops_at_the_first_exact_match() {
+    // Every block of a flat image fits every domain exactly, so the search
+    // ends on the first candidate
+    let image = vec![90.0; (WIDTH * HEIGHT) as usize];
+    for kernel in Kernel::ALL {
+        for block in transform_and_compare(&image, &params(WIDTH, HEIGHT, 4, 2, kernel)) {
+            assert_eq!(block.meta, 0);
+            assert_eq!((block.alpha, block.beta, block.error), (0.0, 90.0, 0.0));
+        }
+    }
+}
+
+// Runs on whatever adapter wgpu finds, software ones included, and passes
+// without one so machines without a GPU still run the rest
+#[test]
+fn gpu_matches_emulator() {
+    let Some(adapter) = request_adapter(true) else {
+        println!("no GPU adapter, skipping the GPU parity check");
+        return;
+    };
+    let mut encoder = GpuEncoder::new(&adapter).unwrap();
+    for kernel in Kernel::ALL {
+        for (block_size, stride) in CASES {
+            let image = signal(WIDTH, HEIGHT, block_size * 31 + stride);
+            let params = params(WIDTH, HEIGHT, block_size, stride, kernel);
+            let expected = transform_and_compare(&image, &params);
+            let actual = encoder.encode(&image, &params).unwrap();
+            assert_same_match(&actual, &expected, &format!("GPU {kernel} kernel, block size {block_size}, stride {stride}"));
+        }
+    }
+}
+
+// One context encoding images of different sizes in turn, so its buffers
+// grow and are reused with stale contents, must give the same blocks as a
+// fresh one
+#[test]
+fn gpu_encoder_reuses_its_context_across_images() {
+    let Some(adapter) = request_adapter(true) else {
+        println!("no GPU adapter, skipping the GPU reuse check");
+        return;
+    };
+    let mut encoder = GpuEncoder::new(&adapter).unwrap();
+    let images = [(WIDTH, HEIGHT, 4, 2), (16, 8, 2, 1), (WIDTH, HEIGHT * 2, 8, 4), (WIDTH, HEIGHT, 4, 2)];
+    for (i, (width, height, block_size, stride)) in images.into_iter().enumerate() {
+        let image = signal(width, height, i as u32 * 7 + 3);
+        let params = params(width, height, block_size, stride, Kernel::Reduction);
+        let expected = GpuEncoder::new(&adapter).unwrap().encode(&image, &params).unwrap();
+        let actual = encoder.encode(&image, &params).unwrap();
+        assert_eq!(actual, expected, "image {i}: {width}x{height}, block size {block_size}");
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::PathBuf;
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::gpu::shader::{Kernel, ShaderRegistry, MAX_BLOCK_SIZE, MIN_BLOCK_SIZE};
+use crate::tests::temp_path;
+
+// Parses and validates the kernel the way wgpu would before handing it to a
+// driver, so a broken variant fails here rather than on someone's GPU
+fn validate(source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+    Validator::new(ValidationFlags::all(), Capabilities::default())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string(source))?;
+    Ok(())
+}
+
+#[test]
+fn every_embedded_shader_validates() {
+    let shaders = ShaderRegistry::default();
+    let keys: Vec<(Kernel, u32)> = ShaderRegistry::keys().collect();
+    assert_eq!(keys.len(), Kernel::ALL.len() * (MAX_BLOCK_SIZE - MIN_BLOCK_SIZE + 1) as usize);
+    for (kernel, block_size) in keys {
+        let source = shaders.source(kernel, block_size).unwrap();
+        assert!(!source.contains("{{"), "{kernel} kernel, block size {block_size} left a placeholder");
+        if let Err(error) = validate(&source) {
+            panic!("{kernel} kernel, block size {block_size}:\n{error}");
+        }
+    }
+}
+
+#[test]
+fn unsupported_block_sizes_are_rejected() {
+    let shaders = ShaderRegistry::default();
+    for kernel in Kernel::ALL {
+        assert!(shaders.source(kernel, MIN_BLOCK_SIZE - 1).is_err());
+        assert!(shaders.source(kernel, MAX_BLOCK_SIZE + 1).is_err());
+    }
+}
+
+// A file for one block size beats a template, which beats the embedded
+// kernel, and anything without an override is still served
+#[test]
+fn override_directory_is_searched_first() {
+    let dir = temp_path("shaders");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("transform_and_compare_reduction_8.wgsl"), "// reduction for 8").unwrap();
+    fs::write(dir.join(Kernel::Serial.file_name()), "// serial for {{BLOCK_SIZE}}").unwrap();
+
+    let embedded = ShaderRegistry::default();
+    let shaders = ShaderRegistry::new(Some(dir.clone()));
+    assert_eq!(shaders.source(Kernel::Reduction, 8).unwrap(), "// reduction for 8");
+    assert_eq!(shaders.source(Kernel::Serial, 4).unwrap(), "// serial for 4");
+    assert_eq!(shaders.source(Kernel::Reduction, 4).unwrap(), embedded.source(Kernel::Reduction, 4).unwrap());
+    assert!(shaders.source(Kernel::Serial, MAX_BLOCK_SIZE + 1).is_err());
+
+    let missing = ShaderRegistry::new(Some(PathBuf::from("no/such/shader/dir")));
+    assert_eq!(missing.source(Kernel::Serial, 8).unwrap(), embedded.source(Kernel::Serial, 8).unwrap());
+    fs::remove_dir_all(dir).unwrap();
+}
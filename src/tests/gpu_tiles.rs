@@ -0,0 +1,105 @@
+use crate::gpu::emulator::transform_and_compare;
+use crate::gpu::encoder::{kernel_within_loop_limit, request_adapter, EncodeParams, GpuEncoder};
+use crate::gpu::shader::{Kernel, SOFTWARE_LOOP_LIMIT};
+use crate::gpu::tiles::{plan, DispatchLimits, DomainBand, RangeTile};
+
+fn limits(max_buffer_size: u64, max_workgroups_per_dimension: u32) -> DispatchLimits {
+    DispatchLimits {
+        max_buffer_size,
+        max_workgroups_per_dimension,
+    }
+}
+
+#[test]
+fn roomy_limits_give_a_single_dispatch() {
+    let plan = plan(64, 48, 8, 4, &limits(1 << 27, 65535)).unwrap();
+    assert_eq!(plan.tiles, [RangeTile { x0: 0, y0: 0, cols: 8, rows: 6 }]);
+    assert_eq!(plan.bands, [DomainBand { first_row: 0, rows: 11 }]);
+}
+
+#[test]
+fn tiles_and_bands_cover_everything_once_within_limits() {
+    let (width, height, block_size, stride) = (100, 70, 4, 3);
+    let limits = limits(100 * 4 * 10, 7);
+    let plan = plan(width, height, block_size, stride, &limits).unwrap();
+
+    let (blocks_x, blocks_y) = (width / block_size, height / block_size);
+    let mut covered = vec![0; (blocks_x * blocks_y) as usize];
+    for tile in &plan.tiles {
+        assert!(tile.cols <= 7 && tile.rows <= 7, "{tile:?}");
+        assert!((tile.rows * block_size * width * 4) as u64 <= limits.max_buffer_size, "{tile:?}");
+        for y in tile.y0..tile.y0 + tile.rows {
+            for x in tile.x0..tile.x0 + tile.cols {
+                covered[(y * blocks_x + x) as usize] += 1;
+            }
+        }
+    }
+    assert!(covered.iter().all(|&c| c == 1));
+
+    let mut next_row = 0;
+    for band in &plan.bands {
+        assert_eq!(band.first_row, next_row);
+        let rows = band.pixel_rows(block_size, stride);
+        assert!(rows.end <= height);
+        assert!(((rows.end - rows.start) * width * 4) as u64 <= limits.max_buffer_size, "{band:?}");
+        next_row += band.rows;
+    }
+    assert_eq!(next_row, (height - block_size) / stride + 1);
+}
+
+#[test]
+fn rows_too_wide_for_a_buffer_are_rejected() {
+    assert!(plan(1000, 64, 8, 4, &limits(1000 * 4 * 7, 65535)).is_err());
+}
+
+// Limits small enough to split a small image into several tiles and bands
+// must give the same blocks as one big dispatch
+#[test]
+fn tiled_gpu_encode_matches_emulator() {
+    let Some(adapter) = request_adapter(true) else {
+        println!("no GPU adapter, skipping the tiled GPU check");
+        return;
+    };
+    let (width, height, block_size, stride) = (40, 28, 4, 2);
+    let image: Vec<f32> = (0..width * height).map(|i| ((i * 37 + i / 5 * 11) % 251) as f32).collect();
+    let limits = limits((width * 4 * 8) as u64, 3);
+    let plan = plan(width, height, block_size, stride, &limits).unwrap();
+    assert!(plan.tiles.len() > 1 && plan.bands.len() > 1, "{plan:?}");
+
+    let mut encoder = GpuEncoder::new(&adapter).unwrap().with_limits(limits);
+    for kernel in Kernel::ALL {
+        let params = EncodeParams {
+            image_width: width,
+            image_height: height,
+            range_size: block_size,
+            domain_size: block_size,
+            stride,
+            kernel,
+        };
+        let expected = transform_and_compare(&image, &params);
+        let actual = encoder.encode(&image, &params).unwrap();
+        for (i, (a, e)) in actual.iter().zip(&expected).enumerate() {
+            assert_eq!(a.meta, e.meta, "{kernel} kernel, range {i}");
+            assert!(
+                (a.alpha - e.alpha).abs() < 1e-3 && (a.beta - e.beta).abs() < 0.1,
+                "{kernel} kernel, range {i}: {a:?} vs {e:?}"
+            );
+        }
+    }
+}
+
+// 8x8 blocks take 8 * 64 iterations per domain, so a serial thread fits 127
+// domains under the software limit and a reduction thread 127 of its share
+#[test]
+fn software_loop_limit_picks_a_kernel_that_fits() {
+    let limit = Some(SOFTWARE_LOOP_LIMIT);
+    assert_eq!(Kernel::Serial.loop_iterations(8, 127), 65024);
+    assert_eq!(Kernel::Reduction.loop_iterations(8, 127 * 64 + 1), 65536);
+
+    assert_eq!(kernel_within_loop_limit(Kernel::Serial, 8, 127, limit), Ok(Kernel::Serial));
+    assert_eq!(kernel_within_loop_limit(Kernel::Serial, 8, 128, limit), Ok(Kernel::Reduction));
+    assert_eq!(kernel_within_loop_limit(Kernel::Reduction, 8, 127 * 64, limit), Ok(Kernel::Reduction));
+    assert!(kernel_within_loop_limit(Kernel::Serial, 8, 127 * 64 + 1, limit).is_err());
+    // Hardware adapters have no such limit
+    assert_eq!(kernel_within_loop_limit(Kernel::Serial, 8, 1 << 20, None), Ok(Kernel::Serial));
+}
@@ -0,0 +1,53 @@
+use crate::block_extractor::{BlockExtractor, BlockPixels, IntegralImage};
+
+fn image(width: usize, height: usize) -> Vec<f32> {
+    (0..width * height).map(|i| ((i * 37 + i / width * 11) % 256) as f32).collect()
+}
+
+#[test]
+fn rectangles_match_direct_sums() {
+    let (width, height) = (13, 9);
+    let pixels = image(width, height);
+    let integral = IntegralImage::new(&pixels, width, height);
+
+    for (x, y, w, h) in [(0, 0, width, height), (0, 0, 1, 1), (3, 2, 5, 4), (12, 8, 1, 1), (4, 0, 9, 9)] {
+        let values: Vec<f64> = (y..y + h)
+            .flat_map(|py| (x..x + w).map(move |px| (py, px)))
+            .map(|(py, px)| pixels[py * width + px] as f64)
+            .collect();
+        let n = values.len() as f64;
+        let sum: f64 = values.iter().sum();
+        let sum_sq: f64 = values.iter().map(|v| v * v).sum();
+        let mean = sum / n;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n;
+
+        assert_eq!(integral.rect_sum(x, y, w, h), sum);
+        assert_eq!(integral.rect_sum_sq(x, y, w, h), sum_sq);
+        assert!((integral.rect_mean(x, y, w, h) - mean).abs() < 1e-9);
+        assert!((integral.rect_variance(x, y, w, h) - variance).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn flat_areas_have_zero_variance() {
+    let integral = IntegralImage::new(&[200.0; 64], 8, 8);
+    assert_eq!(integral.rect_variance(1, 1, 5, 5), 0.0);
+    assert_eq!(integral.rect_mean(1, 1, 5, 5), 200.0);
+}
+
+#[test]
+fn block_stats_follow_extraction_order() {
+    let (width, height) = (20, 12);
+    let extractor = BlockExtractor::new(image(width, height), width, height, 4, 3);
+
+    for (blocks, stats) in [
+        (extractor.range_views().collect::<Vec<_>>(), extractor.range_stats()),
+        (extractor.domain_views().collect(), extractor.domain_stats()),
+    ] {
+        assert_eq!(blocks.len(), stats.len());
+        for (block, stats) in blocks.iter().zip(stats) {
+            assert_eq!(stats.sum, block.pixels().sum::<f32>());
+            assert_eq!(stats.sum_sq, block.pixels().map(|v| v * v).sum::<f32>());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+
+use image::GrayImage;
+
+use crate::decode::reconstruct;
+use crate::encode::{encode_luma, EncodeOptions, EncodedBlock, Luminance, Matcher};
+use crate::tests::temp_path;
+use crate::util::{read_fic_file, save_fic_file, FicHeader, FIC_HEADER_BYTES, FIC_VERSIONED_HEADER_BYTES};
+
+fn gradient_image() -> GrayImage {
+    GrayImage::from_fn(24, 16, |x, y| image::Luma([(x * 9 + y * 5 + (x * y) % 13) as u8]))
+}
+
+fn header(luminance: Luminance) -> FicHeader {
+    FicHeader {
+        width: 16,
+        height: 8,
+        block_size: 4,
+        stride: 4,
+        num_blocks: 0,
+        luminance,
+    }
+}
+
+fn blocks() -> Vec<EncodedBlock> {
+    (0..8)
+        .map(|i| EncodedBlock::affine((i % 8) << 16 | i, 0.25 * i as f32 - 1.0, 10.0 * i as f32))
+        .collect()
+}
+
+#[test]
+fn affine_files_keep_the_legacy_header() {
+    let path = temp_path("affine.fic");
+    save_fic_file(&path, &header(Luminance::Affine), &blocks()).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(bytes.len(), FIC_HEADER_BYTES + 8 * 16);
+    assert_eq!(&bytes[..2], &16u16.to_le_bytes());
+
+    let (read, read_blocks) = read_fic_file(&path).unwrap();
+    assert_eq!(read.luminance, Luminance::Affine);
+    assert_eq!(read_blocks, blocks());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn orthogonal_flag_round_trips() {
+    let path = temp_path("orthogonal.fic");
+    save_fic_file(&path, &header(Luminance::Orthogonal), &blocks()).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(bytes.len(), FIC_VERSIONED_HEADER_BYTES + 8 * 16);
+    assert_eq!(&bytes[..3], b"FIC");
+
+    let (read, read_blocks) = read_fic_file(&path).unwrap();
+    assert_eq!(read.luminance, Luminance::Orthogonal);
+    assert_eq!((read.width, read.height, read.num_blocks), (16, 8, 8));
+    assert_eq!(read.file_size(), bytes.len());
+    assert_eq!(read_blocks, blocks());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn unknown_versions_and_flags_are_rejected() {
+    let path = temp_path("future.fic");
+    save_fic_file(&path, &header(Luminance::Orthogonal), &blocks()).unwrap();
+    let original = fs::read(&path).unwrap();
+
+    for (offset, value) in [(3, 3u8), (4, 0x80u8)] {
+        let mut bytes = original.clone();
+        bytes[offset] = value;
+        fs::write(&path, &bytes).unwrap();
+        assert!(read_fic_file(&path).is_err(), "byte {offset} = {value} was accepted");
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn orthogonal_offsets_are_range_means() {
+    let image = gradient_image();
+    let options = EncodeOptions {
+        luminance: Luminance::Orthogonal,
+        ..EncodeOptions::new(4, 2)
+    };
+    let blocks = encode_luma(&image, &options).unwrap();
+
+    let header = FicHeader::for_encode(image.width(), image.height(), &options, blocks.len());
+    for (i, block) in blocks.iter().enumerate() {
+        let (x, y) = header.range_origin(i);
+        let mut sum = 0.0;
+        for dy in 0..4 {
+            for dx in 0..4 {
+                sum += image.get_pixel((x + dx) as u32, (y + dy) as u32)[0] as f32;
+            }
+        }
+        assert!((block.beta - sum / 16.0).abs() < 1e-3, "block {i}");
+    }
+}
+
+// Same fit as the affine model, only the stored offset changes. Decoding
+// from a flat start then gets every block mean right after one iteration.
+#[test]
+fn orthogonal_keeps_the_affine_fit_and_block_means() {
+    let image = gradient_image();
+    let affine_options = EncodeOptions::new(4, 2);
+    let orthogonal_options = EncodeOptions {
+        luminance: Luminance::Orthogonal,
+        ..affine_options
+    };
+    let affine = encode_luma(&image, &affine_options).unwrap();
+    let orthogonal = encode_luma(&image, &orthogonal_options).unwrap();
+    for (a, o) in affine.iter().zip(&orthogonal) {
+        assert!((a.alpha - o.alpha).abs() < 1e-4);
+    }
+
+    let header = FicHeader::for_encode(image.width(), image.height(), &orthogonal_options, orthogonal.len());
+    let decoded = reconstruct(&header, &orthogonal, 1);
+    for (i, block) in orthogonal.iter().enumerate() {
+        let (x, y) = header.range_origin(i);
+        for dy in 0..4 {
+            for dx in 0..4 {
+                let value = decoded.get_pixel((x + dx) as u32, (y + dy) as u32)[0] as f32;
+                assert!((value - block.beta).abs() <= 1.0, "block {i}");
+            }
+        }
+    }
+}
+
+#[test]
+fn matchers_agree_on_orthogonal_quantized_encodes() {
+    let image = gradient_image();
+    let encodes: Vec<Vec<EncodedBlock>> = Matcher::ALL
+        .into_iter()
+        .map(|matcher| {
+            let options = EncodeOptions {
+                luminance: Luminance::Orthogonal,
+                quant_bits: Some(6),
+                matcher,
+                ..EncodeOptions::new(4, 2)
+            };
+            encode_luma(&image, &options).unwrap()
+        })
+        .collect();
+
+    for other in &encodes[1..] {
+        for (a, b) in encodes[0].iter().zip(other) {
+            assert_eq!(a.meta, b.meta);
+            assert_eq!((a.alpha, a.beta), (b.alpha, b.beta));
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use image::GrayImage;
+
+use crate::metrics::{ms_ssim, mse, psnr, ssim, QualityReport};
+
+fn texture(width: u32, height: u32) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, y| image::Luma([((x * 37 + y * 91 + x * y * 13) % 240) as u8]))
+}
+
+#[test]
+fn identical_images_score_perfectly() {
+    let image = texture(64, 48);
+    assert_eq!(mse(&image, &image), 0.0);
+    assert_eq!(psnr(&image, &image), f64::INFINITY);
+    assert!((ssim(&image, &image) - 1.0).abs() < 1e-12);
+    assert!((ms_ssim(&image, &image).unwrap() - 1.0).abs() < 1e-12);
+}
+
+// Every pixel 10 levels brighter: MSE 100, PSNR 10 log10(255^2 / 100)
+#[test]
+fn constant_offset_gives_the_hand_computed_psnr() {
+    let image = texture(32, 32);
+    let brighter = GrayImage::from_fn(32, 32, |x, y| image::Luma([image.get_pixel(x, y)[0] + 10]));
+    assert_eq!(mse(&image, &brighter), 100.0);
+    assert!((psnr(&image, &brighter) - 28.130803608679106).abs() < 1e-9);
+
+    // Structure is untouched, only the luminance term drops below 1
+    let score = ssim(&image, &brighter);
+    assert!(score > 0.95 && score < 1.0, "{score}");
+}
+
+#[test]
+fn ms_ssim_rejects_images_smaller_than_its_window() {
+    assert_eq!(ms_ssim(&texture(8, 8), &texture(8, 8)), None);
+    assert_eq!(ms_ssim(&texture(64, 10), &texture(64, 10)), None);
+    assert!(ms_ssim(&texture(11, 11), &texture(11, 11)).is_some());
+
+    let report = QualityReport::compare(&texture(8, 8), &texture(8, 8));
+    assert_eq!(report.ms_ssim, None);
+    assert!(report.to_string().contains("MS-SSIM n/a"));
+}
+
+#[test]
+#[should_panic(expected = "same dimensions")]
+fn mismatched_sizes_are_rejected() {
+    mse(&texture(8, 8), &texture(8, 9));
+}
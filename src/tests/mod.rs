@@ -0,0 +1,37 @@
+mod cli;
+mod batch;
+mod domain_pool;
+mod encode;
+mod metrics;
+mod sweep;
+mod simd;
+mod fft;
+mod integral;
+mod block_view;
+mod luminance;
+mod polynomial;
+mod video;
+mod animation;
+mod stream;
+mod export;
+mod visualize;
+mod report;
+mod gpu_shader;
+mod gpu_parity;
+mod gpu_tiles;
+mod backend;
+
+use crate::block_extractor::{BlockPixels, BlockStats};
+
+// A path in the temp dir unique to this test run
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("fractal_comp_{}_{name}", std::process::id()))
+}
+
+// Sums of a block added up from its pixels, for blocks off any extractor's grid
+pub(crate) fn block_stats<B: BlockPixels + ?Sized>(block: &B) -> BlockStats {
+    BlockStats {
+        sum: block.pixels().sum(),
+        sum_sq: block.pixels().map(|v| v * v).sum(),
+    }
+}
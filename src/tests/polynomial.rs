@@ -0,0 +1,174 @@
+use std::fs;
+
+use image::GrayImage;
+
+use crate::alpha_beta::compute_mse;
+use crate::encode::{encode_block, encode_luma, EncodeOptions, EncodedBlock, Luminance, Matcher};
+use crate::polynomial::{cross_sums, fit, DomainMoments, Polynomial, RangeMoments};
+use crate::tests::{block_stats, temp_path};
+use crate::transform::apply_d4_transform;
+use crate::util::{read_fic_file, save_fic_file, FicHeader, FIC_POLYNOMIAL_BLOCK_BYTES, FIC_VERSIONED_HEADER_BYTES};
+
+fn textured_image() -> GrayImage {
+    GrayImage::from_fn(24, 16, |x, y| image::Luma([((x * 37 + y * 91 + x * x * y) % 200 + 20) as u8]))
+}
+
+// `BlockStats` sums are f32, exact for pixel values but not for the
+// synthetic ranges here, so the range's plain sums are redone in f64
+fn fit_pixels(domain: &[f32], range: &[f32], block_size: usize) -> (Polynomial, f32) {
+    let (sum_dr, sum_d2r) = cross_sums(domain, range);
+    let range_moments = RangeMoments {
+        sum_r: range.iter().map(|&r| r as f64).sum(),
+        sum_r2: range.iter().map(|&r| r as f64 * r as f64).sum(),
+        ..RangeMoments::new(range, block_stats(range), block_size)
+    };
+    fit(
+        &DomainMoments::new(domain, block_stats(domain), block_size),
+        &range_moments,
+        sum_dr,
+        sum_d2r,
+    )
+}
+
+// `Polynomial::apply` with the domain's mean and variance taken directly
+fn apply(coefficients: &Polynomial, domain: &[f32], block_size: usize) -> Vec<f32> {
+    let n = domain.len() as f32;
+    let mean = domain.iter().sum::<f32>() / n;
+    let variance = domain.iter().map(|d| (d - mean) * (d - mean)).sum::<f32>() / n;
+    coefficients.apply(domain, mean, variance, block_size)
+}
+
+#[test]
+fn fit_recovers_an_exact_polynomial() {
+    let domain: Vec<f32> = (0..64).map(|i| ((i * 53 + 7) % 64) as f32 * 3.0).collect();
+    let expected = Polynomial {
+        alpha: 0.6,
+        gamma: 0.004,
+        beta: 120.0,
+        ramp_x: 1.5,
+        ramp_y: -2.0,
+    };
+    let range = apply(&expected, &domain, 8);
+
+    let (fitted, mse) = fit_pixels(&domain, &range, 8);
+    assert!(mse < 1e-4, "mse {mse}");
+    for (a, b) in [
+        (fitted.alpha, expected.alpha),
+        (fitted.gamma, expected.gamma),
+        (fitted.beta, expected.beta),
+        (fitted.ramp_x, expected.ramp_x),
+        (fitted.ramp_y, expected.ramp_y),
+    ] {
+        assert!((a - b).abs() < 1e-3 * b.abs().max(1.0), "{fitted:?}");
+    }
+}
+
+#[test]
+fn flat_domains_fit_the_mean_and_ramps_only() {
+    let domain = vec![90.0; 16];
+    let range: Vec<f32> = (0..16).map(|i| 50.0 + 4.0 * (i % 4) as f32 + (i / 4) as f32).collect();
+
+    let (fitted, mse) = fit_pixels(&domain, &range, 4);
+    assert_eq!((fitted.alpha, fitted.gamma), (0.0, 0.0));
+    assert!((fitted.beta - 57.5).abs() < 1e-4);
+    assert!((fitted.ramp_x - 4.0).abs() < 1e-4 && (fitted.ramp_y - 1.0).abs() < 1e-4);
+    assert!(mse < 1e-6);
+}
+
+// The model contains the affine one, so the best polynomial match is never
+// worse than the best affine match, and its reported error is what the
+// stored coefficients actually give
+#[test]
+fn polynomial_fit_is_no_worse_than_affine() {
+    let image = textured_image();
+    let pixels: Vec<f32> = image.pixels().map(|p| p[0] as f32).collect();
+    let block = |x: usize, y: usize| -> Vec<f32> {
+        (0..16).map(|i| pixels[(y + i / 4) * 24 + x + i % 4]).collect()
+    };
+    let domains: Vec<Vec<f32>> = (0..=12)
+        .step_by(3)
+        .flat_map(|y| (0..=20).step_by(3).map(move |x| (x, y)))
+        .map(|(x, y)| block(x, y))
+        .collect();
+    let stats: Vec<_> = domains.iter().map(block_stats).collect();
+    let affine_options = EncodeOptions::new(4, 3);
+    let options = EncodeOptions {
+        luminance: Luminance::Polynomial,
+        ..affine_options
+    };
+
+    for (x, y) in [(0, 0), (8, 4), (20, 12), (12, 8)] {
+        let range = block(x, y);
+        let error = |encoded: &EncodedBlock, luminance: Luminance| {
+            let domain = apply_d4_transform(&domains[encoded.domain_index()], 4, encoded.transform_id());
+            match luminance {
+                Luminance::Polynomial => compute_mse(&apply(&encoded.coefficients(), &domain, 4), &range, 1.0, 0.0),
+                _ => compute_mse(&domain, &range, encoded.alpha, encoded.beta),
+            }
+        };
+        let affine = encode_block(&range, block_stats(&range), &domains, &stats, &affine_options);
+        let polynomial = encode_block(&range, block_stats(&range), &domains, &stats, &options);
+        assert!(error(&polynomial, Luminance::Polynomial) <= error(&affine, Luminance::Affine) + 1e-2);
+    }
+}
+
+#[test]
+fn naive_and_pool_agree_and_fft_is_rejected() {
+    let image = textured_image();
+    let encode = |matcher| {
+        let options = EncodeOptions {
+            luminance: Luminance::Polynomial,
+            matcher,
+            ..EncodeOptions::new(4, 2)
+        };
+        encode_luma(&image, &options)
+    };
+    assert_eq!(encode(Matcher::Naive).unwrap(), encode(Matcher::Pool).unwrap());
+    assert!(encode(Matcher::Fft).is_err());
+
+    let quantized = EncodeOptions {
+        luminance: Luminance::Polynomial,
+        quant_bits: Some(8),
+        ..EncodeOptions::new(4, 2)
+    };
+    assert!(encode_luma(&image, &quantized).is_err());
+}
+
+#[test]
+fn polynomial_files_carry_the_extra_coefficients() {
+    let image = textured_image();
+    let options = EncodeOptions {
+        luminance: Luminance::Polynomial,
+        ..EncodeOptions::new(4, 3)
+    };
+    let blocks = encode_luma(&image, &options).unwrap();
+    assert!(blocks.iter().any(|b| b.gamma != 0.0) && blocks.iter().any(|b| b.ramp_x != 0.0));
+
+    let path = temp_path("polynomial.fic");
+    let header = FicHeader::for_encode(image.width(), image.height(), &options, blocks.len());
+    save_fic_file(&path, &header, &blocks).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(bytes.len(), FIC_VERSIONED_HEADER_BYTES + blocks.len() * FIC_POLYNOMIAL_BLOCK_BYTES);
+
+    let (read, read_blocks) = read_fic_file(&path).unwrap();
+    assert_eq!(read.luminance, Luminance::Polynomial);
+    assert_eq!(read_blocks, blocks);
+
+    // Both model flags at once make no sense
+    let mut both = bytes.clone();
+    both[4] = 0b11;
+    fs::write(&path, &both).unwrap();
+    assert!(read_fic_file(&path).is_err());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn transformed_moments_match_the_transformed_pixels() {
+    let domain: Vec<f32> = (0..64).map(|i| ((i * 37 + i / 8 * 11) % 256) as f32).collect();
+    let stats = block_stats(&domain);
+    let moments = DomainMoments::new(&domain, stats, 8);
+    for transform_id in 0..8 {
+        let transformed = apply_d4_transform(&domain, 8, transform_id);
+        assert_eq!(moments.transformed(transform_id), DomainMoments::new(&transformed, stats, 8), "transform {transform_id}");
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs;
+
+use image::{GrayImage, Rgb};
+
+use crate::decode::collage_errors;
+use crate::encode::{encode_luma, EncodeOptions, EncodedBlock, Luminance, Matcher};
+use crate::report::{error_heatmap, BlockReport, Histogram};
+use crate::tests::temp_path;
+use crate::util::{read_fic_file, save_fic_file, FicHeader};
+
+fn textured_image() -> GrayImage {
+    GrayImage::from_fn(24, 16, |x, y| image::Luma([(x * 9 + y * 5 + (x * y) % 13) as u8]))
+}
+
+// 16x8 with stride 4: eight ranges and eight domains
+fn header() -> FicHeader {
+    FicHeader {
+        width: 16,
+        height: 8,
+        block_size: 4,
+        stride: 4,
+        num_blocks: 8,
+        luminance: Luminance::Affine,
+    }
+}
+
+#[test]
+fn encoders_record_the_collage_error() {
+    let image = textured_image();
+    for (luminance, matcher, quant_bits) in [
+        (Luminance::Affine, Matcher::Naive, None),
+        (Luminance::Orthogonal, Matcher::Pool, Some(6)),
+        (Luminance::Orthogonal, Matcher::Fft, None),
+        (Luminance::Polynomial, Matcher::Pool, None),
+    ] {
+        let options = EncodeOptions {
+            luminance,
+            matcher,
+            quant_bits,
+            ..EncodeOptions::new(4, 3)
+        };
+        let blocks = encode_luma(&image, &options).unwrap();
+        let header = FicHeader::for_encode(24, 16, &options, blocks.len());
+        let measured = collage_errors(&header, &blocks, &image).unwrap();
+        assert!(blocks.iter().any(|b| b.error > 1.0), "{matcher} {luminance}");
+        for (block, measured) in blocks.iter().zip(measured) {
+            assert!(block.error >= 0.0);
+            assert!(
+                (block.error - measured).abs() <= 1e-2 * measured.max(1.0),
+                "{matcher} {luminance}: recorded {} measured {measured}",
+                block.error
+            );
+        }
+
+        // The error is part of the file
+        let path = temp_path(&format!("report_{matcher}_{luminance}.fic"));
+        save_fic_file(&path, &header, &blocks).unwrap();
+        assert_eq!(read_fic_file(&path).unwrap().1, blocks);
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn histograms_span_the_values() {
+    let histogram = Histogram::new(&[0.0, 0.1, 0.5, 0.99, 1.0], 4);
+    assert_eq!((histogram.min, histogram.max), (0.0, 1.0));
+    assert_eq!(histogram.counts, vec![2, 0, 1, 2]);
+    assert_eq!(histogram.bin_start(2), 0.5);
+
+    assert_eq!(Histogram::new(&[3.0; 5], 4).counts, vec![5, 0, 0, 0]);
+    assert_eq!(Histogram::new(&[], 3).counts, vec![0, 0, 0]);
+}
+
+#[test]
+fn report_counts_transforms_domains_and_ranks_errors() {
+    // Transforms 0, 1, 1, 2, ... and domains 0, 0, 0, 1, 1, 2, 3, 4
+    let domains = [0, 0, 0, 1, 1, 2, 3, 4];
+    let blocks: Vec<EncodedBlock> = (0..8)
+        .map(|i| EncodedBlock::affine(((i + 1) as u32 / 2) << 16 | domains[i], i as f32 / 8.0, 100.0))
+        .collect();
+    let errors = [5.0, 1.0, 9.0, 0.0, 9.0, 2.0, 3.0, 4.0];
+    let report = BlockReport::new(&header(), &blocks, &errors, 4, 3);
+
+    assert_eq!(report.transforms, [1, 2, 2, 2, 1, 0, 0, 0]);
+    assert_eq!(report.domain_uses, vec![3, 2, 1, 1, 1, 0, 0, 0]);
+    assert_eq!(report.most_reused(2), vec![(0, 3), (1, 2)]);
+    assert_eq!(report.worst.iter().map(|r| r.index).collect::<Vec<_>>(), vec![2, 4, 0]);
+    assert_eq!(report.mean_error, 33.0 / 8.0);
+    assert_eq!(report.alpha.counts, vec![2, 2, 2, 2]);
+    assert_eq!(report.beta.counts, vec![8, 0, 0, 0]);
+
+    let text = report.to_string();
+    assert!(text.contains("5 of 8 used"));
+    assert!(text.contains("#0 at (0, 0) x3"));
+}
+
+#[test]
+fn heatmap_goes_from_black_to_white() {
+    let errors = [0.0, 100.0, 25.0, 0.0, 0.0, 0.0, 0.0, 400.0];
+    let heatmap = error_heatmap(&header(), &errors, 2, 100.0);
+    assert_eq!(heatmap.dimensions(), (32, 16));
+    assert_eq!(heatmap.get_pixel(1, 1), &Rgb([0, 0, 0]));
+    assert_eq!(heatmap.get_pixel(9, 7), &Rgb([255, 255, 255]));
+    // Past max_error stays white
+    assert_eq!(heatmap.get_pixel(31, 15), &Rgb([255, 255, 255]));
+    // Half the RMSE of the maximum is orange-red
+    let half = heatmap.get_pixel(17, 1);
+    assert_eq!((half[0], half[2]), (255, 0));
+    assert!(half[1] > 0 && half[1] < 255);
+}
@@ -0,0 +1,101 @@
+use crate::alpha_beta::{compute_alpha_beta, compute_mse, BlockSums};
+use crate::encode::Luminance;
+use crate::simd::{self, available_kernels};
+
+// 4x4, 8x8 and 16x16 blocks, plus a length with a scalar tail
+const BLOCK_LENS: [usize; 4] = [16, 64, 256, 20];
+
+// Deterministic pseudo-random pixels in 0..=255
+fn block(len: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as f32
+        })
+        .collect()
+}
+
+fn assert_close(actual: f32, expected: f32, what: &str) {
+    let tolerance = 1e-5 * expected.abs().max(1.0);
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{what}: {actual} vs {expected}"
+    );
+}
+
+#[test]
+fn kernels_match_scalar_sums() {
+    for kernels in available_kernels() {
+        for len in BLOCK_LENS {
+            let d = block(len, 1);
+            let r = block(len, 2);
+            let name = format!("{} len {len}", kernels.name);
+
+            let dot: f32 = d.iter().zip(&r).map(|(a, b)| a * b).sum();
+            let sum: f32 = d.iter().sum();
+            assert_close(kernels.dot(&d, &r), dot, &format!("{name} dot"));
+            assert_close(kernels.sum(&d), sum, &format!("{name} sum"));
+        }
+    }
+}
+
+// A block read in place from a wider image, against a contiguous copy of it
+#[test]
+fn dot_rows_matches_dot_of_a_copy() {
+    for kernels in available_kernels() {
+        for size in [4, 8, 16, 5] {
+            let pitch = size + 3;
+            let image = block(pitch * size, 6);
+            let r = block(size * size, 7);
+            let copy: Vec<f32> = image.chunks(pitch).flat_map(|row| &row[..size]).copied().collect();
+
+            let expected = kernels.dot(&copy, &r);
+            assert_close(kernels.dot_rows(&image, pitch, size, &r), expected, &format!("{} size {size}", kernels.name));
+        }
+    }
+}
+
+#[test]
+fn sse_matches_compute_mse() {
+    for kernels in available_kernels() {
+        for len in BLOCK_LENS {
+            let d = block(len, 3);
+            let r = block(len, 4);
+            let (alpha, beta) = compute_alpha_beta(&d, &r, Luminance::Affine);
+
+            let expected = compute_mse(&d, &r, alpha, beta) * len as f32;
+            let actual = kernels.sse(&d, &r, alpha, beta);
+            assert_close(actual, expected, &format!("{} len {len} sse", kernels.name));
+        }
+    }
+}
+
+#[test]
+fn sums_reproduce_compute_alpha_beta() {
+    for kernels in available_kernels() {
+        for len in BLOCK_LENS {
+            let d = block(len, 5);
+            let r: Vec<f32> = d.iter().map(|v| 0.5 * v + 20.0).collect();
+            let sums = BlockSums {
+                n: len as f32,
+                sum_d: kernels.sum(&d),
+                sum_r: kernels.sum(&r),
+                sum_dr: kernels.dot(&d, &r),
+                sum_d2: kernels.dot(&d, &d),
+                sum_r2: kernels.dot(&r, &r),
+            };
+
+            let (alpha, beta) = sums.alpha_beta();
+            let (expected_alpha, expected_beta) = compute_alpha_beta(&d, &r, Luminance::Affine);
+            assert_close(alpha, expected_alpha, &format!("{} len {len} alpha", kernels.name));
+            assert_close(beta, expected_beta, &format!("{} len {len} beta", kernels.name));
+        }
+    }
+}
+
+#[test]
+fn dispatch_uses_best_available_kernels() {
+    assert_eq!(simd::kernels().name, available_kernels()[0].name);
+    assert_eq!(available_kernels().last().unwrap().name, simd::SCALAR.name);
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use image::GrayImage;
+
+use crate::decode::reconstruct;
+use crate::encode::{encode_luma, EncodeOptions, EncodedBlock, Luminance};
+use crate::tests::temp_path;
+use crate::util::{save_fic_file, FicHeader, FicReader, FicWriter};
+
+fn header(luminance: Luminance, num_blocks: u32) -> FicHeader {
+    FicHeader {
+        width: 16,
+        height: 8,
+        block_size: 4,
+        stride: 4,
+        num_blocks,
+        luminance,
+    }
+}
+
+fn blocks() -> Vec<EncodedBlock> {
+    (0..8)
+        .map(|i| EncodedBlock::affine((i % 8) << 16 | i, 0.25 * i as f32 - 1.0, 10.0 * i as f32))
+        .collect()
+}
+
+fn write_to_vec(header: &FicHeader, blocks: &[EncodedBlock]) -> Vec<u8> {
+    let mut writer = FicWriter::new(Vec::new(), header).unwrap();
+    for block in blocks {
+        writer.write_block(block).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+#[test]
+fn streams_match_the_file_helpers() {
+    for luminance in [Luminance::Affine, Luminance::Orthogonal, Luminance::Polynomial] {
+        let header = header(luminance, 8);
+        let bytes = write_to_vec(&header, &blocks());
+
+        let path = temp_path(&format!("stream_{luminance}.fic"));
+        save_fic_file(&path, &header, &blocks()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes, "{luminance}");
+        fs::remove_file(path).unwrap();
+
+        let reader = FicReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.header().luminance, luminance);
+        assert_eq!(reader.remaining(), 8);
+        assert_eq!(reader.read_all().unwrap(), blocks());
+    }
+}
+
+#[test]
+fn reader_stops_at_the_declared_count() {
+    // Whatever follows the blocks is left in the source
+    let mut bytes = write_to_vec(&header(Luminance::Affine, 8), &blocks());
+    bytes.extend_from_slice(b"trailer");
+    let mut cursor = Cursor::new(bytes);
+
+    let mut reader = FicReader::new(&mut cursor).unwrap();
+    assert_eq!(reader.read_block().unwrap(), Some(blocks()[0]));
+    assert_eq!(reader.by_ref().count(), 7);
+    assert_eq!(reader.read_block().unwrap(), None);
+    assert_eq!(&cursor.get_ref()[cursor.position() as usize..], b"trailer");
+}
+
+#[test]
+fn truncated_streams_fail_on_the_cut_block() {
+    let bytes = write_to_vec(&header(Luminance::Affine, 8), &blocks());
+    let read: Vec<_> = FicReader::new(&bytes[..bytes.len() - 3]).unwrap().collect();
+    assert_eq!(read.len(), 8);
+    assert!(read[..7].iter().all(Result::is_ok));
+    assert_eq!(read[7].as_ref().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn writer_checks_the_block_count() {
+    let mut writer = FicWriter::new(Vec::new(), &header(Luminance::Affine, 2)).unwrap();
+    writer.write_block(&blocks()[0]).unwrap();
+    assert!(writer.finish().is_err());
+
+    let mut writer = FicWriter::new(Vec::new(), &header(Luminance::Affine, 1)).unwrap();
+    writer.write_block(&blocks()[0]).unwrap();
+    assert!(writer.write_block(&blocks()[1]).is_err());
+}
+
+#[test]
+fn encode_and_decode_through_memory() {
+    let image = GrayImage::from_fn(16, 16, |x, y| image::Luma([(x * 12 + y * 3) as u8]));
+    let options = EncodeOptions::new(4, 4);
+    let encoded = encode_luma(&image, &options).unwrap();
+    let header = FicHeader::for_encode(16, 16, &options, encoded.len());
+    let bytes = write_to_vec(&header, &encoded);
+
+    let reader = FicReader::new(bytes.as_slice()).unwrap();
+    let header = *reader.header();
+    let decoded = reconstruct(&header, &reader.read_all().unwrap(), 8);
+    assert_eq!(decoded, reconstruct(&header, &encoded, 8));
+}
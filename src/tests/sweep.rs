@@ -0,0 +1,90 @@
+use crate::encode::{EncodeOptions, Luminance, SearchMode};
+use crate::metrics::bits_per_pixel;
+use crate::sweep::{coded_bits_per_pixel, SweepGrid};
+use crate::util::{FicHeader, FIC_POLYNOMIAL_BLOCK_BYTES};
+
+// A 32x32 image in 8x8 blocks at stride 8: 16 ranges and 16 domains, so a
+// domain index takes 4 bits
+fn header(luminance: Luminance) -> FicHeader {
+    FicHeader {
+        width: 32,
+        height: 32,
+        block_size: 8,
+        stride: 8,
+        num_blocks: 16,
+        luminance,
+    }
+}
+
+fn options(quant_bits: Option<u8>, search: SearchMode, luminance: Luminance) -> EncodeOptions {
+    EncodeOptions {
+        quant_bits,
+        search,
+        luminance,
+        ..EncodeOptions::new(8, 8)
+    }
+}
+
+#[test]
+fn coded_bpp_counts_every_field() {
+    // 10 byte header, then 4 index + 3 transform + 2 x 32 coefficient bits per block
+    let affine = options(None, SearchMode::Full, Luminance::Affine);
+    assert_eq!(coded_bits_per_pixel(&header(Luminance::Affine), &affine), (80.0 + 16.0 * 71.0) / 1024.0);
+
+    // Identity search drops the transform, 8-bit quantization packs the pair in 16 bits
+    let packed = options(Some(8), SearchMode::Identity, Luminance::Affine);
+    assert_eq!(coded_bits_per_pixel(&header(Luminance::Affine), &packed), (80.0 + 16.0 * 20.0) / 1024.0);
+
+    // A single domain still takes an index bit
+    let single = FicHeader {
+        width: 8,
+        height: 8,
+        num_blocks: 1,
+        ..header(Luminance::Affine)
+    };
+    assert_eq!(coded_bits_per_pixel(&single, &affine), (80.0 + 68.0) / 64.0);
+}
+
+// Polynomial blocks are 28 bytes on disk; packed they carry three more f32s
+// than affine ones, behind the 15 byte versioned header
+#[test]
+fn coded_bpp_of_polynomial_blocks() {
+    let header = header(Luminance::Polynomial);
+    let polynomial = options(None, SearchMode::Full, Luminance::Polynomial);
+    assert_eq!(header.block_bytes(), FIC_POLYNOMIAL_BLOCK_BYTES);
+    assert_eq!(header.file_size(), 15 + 16 * 28);
+    assert_eq!(coded_bits_per_pixel(&header, &polynomial), (120.0 + 16.0 * 167.0) / 1024.0);
+    assert_eq!(bits_per_pixel(&header, header.file_size() as u64), 463.0 * 8.0 / 1024.0);
+}
+
+#[test]
+fn grid_expands_every_combination_in_order() {
+    let grid = SweepGrid {
+        block_sizes: vec![4, 8],
+        strides: vec![4],
+        quant_bits: vec![None, Some(6)],
+        searches: vec![SearchMode::Full],
+        luminances: vec![Luminance::Affine, Luminance::Polynomial],
+    };
+    let points = grid.points();
+    let keys: Vec<_> = points.iter().map(|p| (p.block_size, p.stride, p.quant_bits, p.luminance)).collect();
+    assert_eq!(
+        keys,
+        [
+            (4, 4, None, Luminance::Affine),
+            (4, 4, None, Luminance::Polynomial),
+            (4, 4, Some(6), Luminance::Affine),
+            (4, 4, Some(6), Luminance::Polynomial),
+            (8, 4, None, Luminance::Affine),
+            (8, 4, None, Luminance::Polynomial),
+            (8, 4, Some(6), Luminance::Affine),
+            (8, 4, Some(6), Luminance::Polynomial),
+        ]
+    );
+    // Everything the grid doesn't vary keeps its default
+    let defaults = EncodeOptions::new(8, 4);
+    assert!(points.iter().all(|p| p.search == SearchMode::Full && p.matcher == defaults.matcher));
+
+    let empty = SweepGrid { block_sizes: vec![], ..grid };
+    assert!(empty.points().is_empty());
+}
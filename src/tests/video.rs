@@ -0,0 +1,150 @@
+use std::fs;
+
+use image::{Delay, GrayImage};
+
+use crate::encode::{EncodeOptions, EncodedBlock, Luminance};
+use crate::tests::temp_path;
+use crate::util::{read_fic_file, save_fic_file, FicHeader};
+use crate::video::{
+    decode_frame, decode_video, encode_video, read_video_file, save_video_file, video_file_size, EncodedFrame,
+    EncodedVideo, Frame, FrameStats, VideoOptions, SKIP_BLOCK,
+};
+
+// Flat 4x4 cells panning right by `shift` pixels per frame. Aligned cells
+// decode exactly, so the encoder's previous frame is the original.
+fn clip(frames: u32, shift: u32) -> Vec<Frame> {
+    (0..frames)
+        .map(|k| Frame {
+            image: GrayImage::from_fn(24, 16, |x, y| {
+                let x = x + k * shift;
+                image::Luma([((x / 4 * 7 + y / 4 * 13) % 5 * 40 + 20) as u8])
+            }),
+            delay: Delay::from_numer_denom_ms(100 + k, 3),
+        })
+        .collect()
+}
+
+fn options() -> VideoOptions {
+    VideoOptions {
+        encode: EncodeOptions {
+            luminance: Luminance::Orthogonal,
+            ..EncodeOptions::new(4, 2)
+        },
+        skip_threshold: 0.0,
+        iterations: 6,
+    }
+}
+
+#[test]
+fn static_frames_are_skipped() {
+    let mut frames = clip(1, 0);
+    frames.push(frames[0].clone());
+    let video = encode_video(&frames, &options()).unwrap();
+
+    let stats: Vec<FrameStats> = video.frames.iter().map(|f| f.stats).collect();
+    for frame in &video.frames {
+        assert_eq!(frame.stats, FrameStats::of(&video.header, &frame.blocks));
+    }
+    assert_eq!(stats[0].skipped + stats[0].temporal, 0);
+    assert_eq!(stats[1].skipped, video.header.num_blocks as usize);
+
+    let decoded = decode_video(&video).unwrap();
+    assert_eq!(decoded[0].image, decoded[1].image);
+}
+
+#[test]
+fn moving_frames_use_the_previous_frame() {
+    let frames = clip(3, 4);
+    let video = encode_video(&frames, &options()).unwrap();
+    for frame in &video.frames[1..] {
+        assert!(frame.stats.temporal > 0);
+    }
+    assert_eq!(decode_video(&video).unwrap().len(), 3);
+}
+
+#[test]
+fn video_files_round_trip() {
+    let frames = clip(3, 2);
+    let video = encode_video(&frames, &options()).unwrap();
+    let path = temp_path("clip.fic");
+    save_video_file(&path, &video).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len() as usize, video_file_size(&video));
+
+    let read = read_video_file(&path).unwrap();
+    assert_eq!(read.frames, video.frames);
+    assert_eq!(read.iterations, 6);
+    assert_eq!(read.header.luminance, Luminance::Orthogonal);
+    assert_eq!(read.frames[2].delay, Delay::from_numer_denom_ms(102, 3));
+
+    let decoded = decode_video(&read).unwrap();
+    assert_eq!(decoded, decode_video(&video).unwrap());
+    assert_eq!(decoded[1].delay, frames[1].delay);
+
+    // Each reader refuses the other kind of file
+    assert!(read_fic_file(&path).is_err());
+    let still = temp_path("still.fic");
+    save_fic_file(&still, &read.header, &read.frames[0].blocks).unwrap();
+    assert!(read_video_file(&still).is_err());
+    fs::remove_file(path).unwrap();
+    fs::remove_file(still).unwrap();
+}
+
+#[test]
+fn first_frame_cannot_reference_a_previous_one() {
+    let header = FicHeader {
+        width: 8,
+        height: 8,
+        block_size: 4,
+        stride: 4,
+        num_blocks: 4,
+        luminance: Luminance::Affine,
+    };
+    let skip = vec![EncodedBlock::affine(SKIP_BLOCK, 0.0, 0.0); 4];
+    assert!(decode_frame(&header, &skip, None, 1).is_err());
+    let temporal = vec![EncodedBlock::affine(4, 1.0, 0.0); 4];
+    assert!(decode_frame(&header, &temporal, None, 1).is_err());
+
+    let previous: Vec<f32> = (0..64).map(|i| i as f32).collect();
+    assert_eq!(decode_frame(&header, &skip, Some(&previous), 1).unwrap(), previous);
+}
+
+
+// A 16x16 clip of 4x4 blocks has 16 ranges per frame. A header claiming 17
+// used to send the last block past the frame.
+#[test]
+fn block_counts_that_overrun_the_frame_are_rejected() {
+    let header = FicHeader {
+        width: 16,
+        height: 16,
+        block_size: 4,
+        stride: 4,
+        num_blocks: 17,
+        luminance: Luminance::Affine,
+    };
+    let video = EncodedVideo {
+        header,
+        iterations: 1,
+        frames: vec![EncodedFrame {
+            delay: Delay::from_numer_denom_ms(40, 1),
+            blocks: vec![EncodedBlock::affine(0, 0.5, 10.0); 17],
+            stats: FrameStats::default(),
+        }],
+    };
+    let path = temp_path("overrun.fic");
+    save_video_file(&path, &video).unwrap();
+    let error = read_video_file(&path).unwrap_err();
+    assert!(error.to_string().contains("17 blocks per frame"), "{error}");
+    fs::remove_file(path).unwrap();
+
+    // Decoding the blocks directly skips the one with no range
+    let decoded = decode_video(&video).unwrap();
+    let valid = EncodedVideo {
+        header: FicHeader { num_blocks: 16, ..header },
+        frames: vec![EncodedFrame {
+            blocks: video.frames[0].blocks[..16].to_vec(),
+            ..video.frames[0].clone()
+        }],
+        ..video.clone()
+    };
+    assert_eq!(decoded, decode_video(&valid).unwrap());
+}
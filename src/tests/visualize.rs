@@ -0,0 +1,95 @@
+use image::{GrayImage, Rgb};
+
+use crate::encode::{EncodedBlock, Luminance};
+use crate::util::FicHeader;
+use crate::visualize::{render, RangeFilter, TRANSFORM_COLOURS};
+
+// 16x8 image of 4x4 ranges; domains every 4 pixels, 4 per row
+fn header() -> FicHeader {
+    FicHeader {
+        width: 16,
+        height: 8,
+        block_size: 4,
+        stride: 4,
+        num_blocks: 8,
+        luminance: Luminance::Affine,
+    }
+}
+
+// Range i uses transform i and the domain mirrored along the row order
+fn blocks() -> Vec<EncodedBlock> {
+    (0..8u32).map(|i| EncodedBlock::affine(i << 16 | (7 - i), 0.5, 0.0)).collect()
+}
+
+fn is_gray(pixel: &Rgb<u8>) -> bool {
+    pixel[0] == pixel[1] && pixel[1] == pixel[2]
+}
+
+#[test]
+fn spans_parse_single_indices_and_ranges() {
+    assert_eq!(RangeFilter::parse_spans("3, 10-20,4"), Some(vec![3..=3, 10..=20, 4..=4]));
+    assert_eq!(RangeFilter::parse_spans("5-2"), None);
+    assert_eq!(RangeFilter::parse_spans("a"), None);
+    assert_eq!(RangeFilter::parse_spans(""), None);
+}
+
+#[test]
+fn filters_need_every_condition() {
+    let block = EncodedBlock::affine(3 << 16 | 9, 0.5, 0.0);
+    assert!(RangeFilter::default().matches(0, &block, 0.0));
+
+    let filter = RangeFilter {
+        ranges: vec![0..=4],
+        transforms: vec![3, 5],
+        ..RangeFilter::default()
+    };
+    assert!(filter.matches(4, &block, 0.0));
+    assert!(!filter.matches(5, &block, 0.0));
+    assert!(!filter.matches(4, &EncodedBlock::affine(9, 0.5, 0.0), 0.0));
+
+    let filter = RangeFilter {
+        domains: vec![9],
+        min_error: Some(10.0),
+        ..RangeFilter::default()
+    };
+    assert!(filter.matches(0, &block, 10.0));
+    assert!(!filter.matches(0, &block, 9.9));
+}
+
+#[test]
+fn selected_ranges_are_tinted_and_linked_to_their_domain() {
+    let background = GrayImage::from_pixel(16, 8, image::Luma([100]));
+    let filter = RangeFilter {
+        ranges: vec![1..=1],
+        ..RangeFilter::default()
+    };
+    let (canvas, counts) = render(&header(), &blocks(), &background, 3, &filter, &[0.0; 8]).unwrap();
+    assert_eq!(canvas.dimensions(), (48, 24));
+    assert_eq!(counts, [0, 1, 0, 0, 0, 0, 0, 0]);
+
+    // Range 1 spans x 12..24, y 0..12 at 3x, and links to domain 6 at (24, 12)
+    let colour = Rgb(TRANSFORM_COLOURS[1]);
+    let inside = canvas.get_pixel(14, 3);
+    assert!(!is_gray(inside) && inside != &colour);
+    assert!(is_gray(canvas.get_pixel(2, 3)));
+    assert!(is_gray(canvas.get_pixel(14, 15)));
+    assert_eq!(canvas.get_pixel(30, 18), &colour);
+    assert_eq!(canvas.get_pixel(24, 12), &colour);
+
+    // Every pixel off the grid and the line is the lightened background
+    let lightened = (100.0 * 0.75 + 48.0) as u8;
+    assert_eq!(canvas.get_pixel(40, 4), &Rgb([lightened; 3]));
+}
+
+#[test]
+fn rendering_checks_its_inputs() {
+    let filter = RangeFilter::default();
+    let errors = [0.0; 8];
+    assert!(render(&header(), &blocks(), &GrayImage::new(8, 8), 1, &filter, &errors).is_err());
+
+    let background = GrayImage::new(16, 8);
+    let mut outside = blocks();
+    outside[2].meta = 8;
+    assert!(render(&header(), &outside, &background, 1, &filter, &errors).is_err());
+    assert!(render(&header(), &blocks(), &background, 1, &filter, &errors[1..]).is_err());
+}
@@ -15,8 +15,7 @@ fn main() {
 fn test_alpha_beta() {
     let (device, queue) = init_wgpu();
 
-    let shader_source = std::fs::read_to_string("src/tests/test_alpha_beta.wgsl")
-        .expect("Failed to read shader");
+    let shader_source = include_str!("test_alpha_beta.wgsl");
 
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("test shader"),
@@ -131,7 +130,7 @@ fn test_alpha_beta() {
 
     let slice = staging.slice(..);
     slice.map_async(wgpu::MapMode::Read, |_| {});
-    device.poll(wgpu::PollType::Wait);
+    device.poll(wgpu::PollType::Wait).expect("poll failed");
 
     let data = slice.get_mapped_range();
     let alpha = f32::from_le_bytes(data[0..4].try_into().unwrap());
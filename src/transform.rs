@@ -1,4 +1,6 @@
-pub fn apply_d4_transform(block: &[f32], width: usize, transform_id: u8) -> Vec<f32> {
+use crate::block_extractor::BlockPixels;
+
+pub fn apply_d4_transform<B: BlockPixels + ?Sized>(block: &B, width: usize, transform_id: u8) -> Vec<f32> {
     /*
     0 - No change
     1 - Rotate 90°
@@ -11,25 +13,33 @@ pub fn apply_d4_transform(block: &[f32], width: usize, transform_id: u8) -> Vec<
     */
     let mut output = vec![0.0; width * width];
 
-    for y in 0..width {
-        for x in 0..width {
-            let in_idx = y * width + x;
-            let (tx, ty) = match transform_id {
-                0 => (x, y),
-                1 => (width - 1 - y, x),
-                2 => (width - 1 - x, width - 1 - y),
-                3 => (y, width - 1 - x),
-                4 => (width - 1 - x, y),
-                5 => (x, width - 1 - y),
-                6 => (y, x),
-                7 => (width - 1 - y, width - 1 - x),
-                _ => panic!("Invalid transform ID: {}", transform_id),
-            };
+    for (in_idx, value) in block.pixels().enumerate() {
+        let (x, y) = (in_idx % width, in_idx / width);
+        let (tx, ty) = match transform_id {
+            0 => (x, y),
+            1 => (width - 1 - y, x),
+            2 => (width - 1 - x, width - 1 - y),
+            3 => (y, width - 1 - x),
+            4 => (width - 1 - x, y),
+            5 => (x, width - 1 - y),
+            6 => (y, x),
+            7 => (width - 1 - y, width - 1 - x),
+            _ => panic!("Invalid transform ID: {}", transform_id),
+        };
 
-            let out_idx = ty * width + tx;
-            output[out_idx] = block[in_idx];
-        }
+        let out_idx = ty * width + tx;
+        output[out_idx] = value;
     }
 
     output
 }
+
+// The isometry that undoes `transform_id`: the rotations by 90° and 270°
+// undo each other, every other one is its own inverse
+pub fn inverse_d4_transform(transform_id: u8) -> u8 {
+    match transform_id {
+        1 => 3,
+        3 => 1,
+        _ => transform_id,
+    }
+}
@@ -1,7 +1,137 @@
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::Path;
-use crate::encode::EncodedBlock;
+use crate::encode::{EncodeOptions, EncodedBlock, Luminance};
+
+pub const FIC_HEADER_BYTES: usize = 10;
+pub const FIC_BLOCK_BYTES: usize = 16;
+// Blocks of polynomial files carry gamma, ramp_x and ramp_y after beta
+pub const FIC_POLYNOMIAL_BLOCK_BYTES: usize = FIC_BLOCK_BYTES + 12;
+
+// Files that need anything beyond the original layout start with this magic,
+// a version byte and a flags byte before the original 10-byte header. Files
+// without flags keep the original header, so older readers can still open
+// them. A legacy file would need a width of 18758 to begin with "FIC".
+pub const FIC_MAGIC: [u8; 3] = *b"FIC";
+pub const FIC_VERSION: u8 = 2;
+// Multi-frame files (see `video`) share the prefix with their own version.
+// Files without the prefix count as version 1.
+pub const FIC_VIDEO_VERSION: u8 = 3;
+pub const FIC_LEGACY_VERSION: u8 = 1;
+pub const FIC_VERSIONED_HEADER_BYTES: usize = FIC_MAGIC.len() + 2 + FIC_HEADER_BYTES;
+
+// Header flags
+pub const FLAG_ORTHOGONAL: u8 = 1 << 0;
+pub const FLAG_POLYNOMIAL: u8 = 1 << 1;
+const KNOWN_FLAGS: u8 = FLAG_ORTHOGONAL | FLAG_POLYNOMIAL;
+
+#[derive(Clone, Copy, Debug)]
+pub struct FicHeader {
+    pub width: u16,
+    pub height: u16,
+    pub block_size: u8,
+    pub stride: u8,
+    pub num_blocks: u32,
+    pub luminance: Luminance,
+}
+
+impl FicHeader {
+    // Header for an image encoded on the CPU with these options
+    pub fn for_encode(width: u32, height: u32, options: &EncodeOptions, num_blocks: usize) -> Self {
+        Self {
+            width: width as u16,
+            height: height as u16,
+            block_size: options.block_size as u8,
+            stride: options.stride as u8,
+            num_blocks: num_blocks as u32,
+            luminance: options.luminance,
+        }
+    }
+
+    // Domain Blocks are laid out row by row, `stride` pixels apart
+    pub fn domains_per_row(&self) -> usize {
+        (self.width as usize - self.block_size as usize) / self.stride as usize + 1
+    }
+
+    pub fn domains_per_col(&self) -> usize {
+        (self.height as usize - self.block_size as usize) / self.stride as usize + 1
+    }
+
+    pub fn domain_origin(&self, domain_index: usize) -> (usize, usize) {
+        let per_row = self.domains_per_row();
+        (
+            (domain_index % per_row) * self.stride as usize,
+            (domain_index / per_row) * self.stride as usize,
+        )
+    }
+
+    // Range Blocks tile the image, so they step by the block size. Older GPU
+    // encodes placed a range every `stride` pixels instead, so fall back to
+    // that layout when the block count only matches it.
+    pub fn range_step(&self) -> usize {
+        let bs = self.block_size as usize;
+        let w = self.width as usize;
+        let h = self.height as usize;
+        let tiled = ((w - bs) / bs + 1) * ((h - bs) / bs + 1);
+        if self.num_blocks as usize != tiled && self.num_blocks as usize == self.domains_per_row() * self.domains_per_col() {
+            self.stride as usize
+        } else {
+            bs
+        }
+    }
+
+    pub fn ranges_per_row(&self) -> usize {
+        (self.width as usize - self.block_size as usize) / self.range_step() + 1
+    }
+
+    pub fn ranges_per_col(&self) -> usize {
+        (self.height as usize - self.block_size as usize) / self.range_step() + 1
+    }
+
+    pub fn range_origin(&self, range_index: usize) -> (usize, usize) {
+        let step = self.range_step();
+        let per_row = self.ranges_per_row();
+        ((range_index % per_row) * step, (range_index / per_row) * step)
+    }
+
+    // Dimensions every reader relies on to lay out the blocks
+    pub fn check(&self) -> Result<(), String> {
+        if self.block_size == 0 || self.stride == 0 || self.block_size as u16 > self.width.min(self.height) {
+            return Err(format!(
+                "invalid header: {}x{}, block size {}, stride {}",
+                self.width, self.height, self.block_size, self.stride
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn flags(&self) -> u8 {
+        match self.luminance {
+            Luminance::Affine => 0,
+            Luminance::Orthogonal => FLAG_ORTHOGONAL,
+            Luminance::Polynomial => FLAG_POLYNOMIAL,
+        }
+    }
+
+    pub fn header_bytes(&self) -> usize {
+        if self.flags() == 0 {
+            FIC_HEADER_BYTES
+        } else {
+            FIC_VERSIONED_HEADER_BYTES
+        }
+    }
+
+    pub fn block_bytes(&self) -> usize {
+        match self.luminance {
+            Luminance::Polynomial => FIC_POLYNOMIAL_BLOCK_BYTES,
+            Luminance::Affine | Luminance::Orthogonal => FIC_BLOCK_BYTES,
+        }
+    }
+
+    pub fn file_size(&self) -> usize {
+        self.header_bytes() + self.num_blocks as usize * self.block_bytes()
+    }
+}
 
 pub fn save_fic_file_as_txt(
     path: &Path,
@@ -21,7 +151,7 @@ pub fn save_fic_file_as_txt(
     writeln!(writer, "Block size: {}", block_size).unwrap();
     writeln!(writer, "Stride: {}", stride).unwrap();
     writeln!(writer, "Block count: {}", encoded_blocks.len()).unwrap();
-    writeln!(writer, "").unwrap();
+    writeln!(writer).unwrap();
 
     // --- Write each encoded block ---
     for (i, block) in encoded_blocks.iter().enumerate() {
@@ -37,33 +167,289 @@ pub fn save_fic_file_as_txt(
 }
 
 
-pub fn save_fic_file(path: &Path, blocks: &[EncodedBlock], width: u16, height: u16, block_size: u8, stride: u8) {
-    use std::io::Write;
-    let mut file = std::fs::File::create(path).expect("Failed to create file");
-
-    file.write_all(&width.to_le_bytes()).unwrap();          // 2 bytes
-    file.write_all(&height.to_le_bytes()).unwrap();         // 2 bytes
-    file.write_all(&[block_size]).unwrap();                 // 1 byte
-    file.write_all(&[stride]).unwrap();                     // 1 byte
-    file.write_all(&(blocks.len() as u32).to_le_bytes()).unwrap(); // 4 bytes
+// The block count is taken from `blocks`, not from `header.num_blocks`
+pub fn save_fic_file(path: &Path, header: &FicHeader, blocks: &[EncodedBlock]) -> io::Result<()> {
+    let header = FicHeader {
+        num_blocks: blocks.len() as u32,
+        ..*header
+    };
 
+    let mut writer = FicWriter::new(BufWriter::new(File::create(path)?), &header)?;
     for block in blocks {
-        file.write_all(&block.meta.to_le_bytes()).unwrap();         // 4 bytes
-        file.write_all(&block._unused.to_le_bytes()).unwrap();      // 4 bytes
-        file.write_all(&block.alpha.to_bits().to_le_bytes()).unwrap(); // 4 bytes
-        file.write_all(&block.beta.to_bits().to_le_bytes()).unwrap();  // 4 bytes
+        writer.write_block(block)?;
+    }
+    writer.finish()?;
+
+    println!("save_fic_file() writing {} blocks ({} bytes)", blocks.len(), header.file_size());
+    Ok(())
+}
+
+// Writes a still-image .fic to any sink, one block at a time. The header goes
+// out first, so the block count has to be known up front; `finish` checks
+// that exactly that many blocks were written.
+pub struct FicWriter<W: Write> {
+    writer: W,
+    header: FicHeader,
+    written: u32,
+}
+
+impl<W: Write> FicWriter<W> {
+    pub fn new(mut writer: W, header: &FicHeader) -> io::Result<Self> {
+        write_header(&mut writer, header, FIC_VERSION)?;
+        Ok(Self {
+            writer,
+            header: *header,
+            written: 0,
+        })
+    }
+
+    pub fn write_block(&mut self, block: &EncodedBlock) -> io::Result<()> {
+        if self.written == self.header.num_blocks {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the header declares {} blocks, can't write another", self.header.num_blocks),
+            ));
+        }
+        write_block(&mut self.writer, self.header.luminance, block)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    // Flushes and hands back the sink
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.written != self.header.num_blocks {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("wrote {} blocks but the header declares {}", self.written, self.header.num_blocks),
+            ));
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Still images only get the versioned prefix when they need flags, so
+// affine files keep the original layout
+pub fn write_header<W: Write>(writer: &mut W, header: &FicHeader, version: u8) -> io::Result<()> {
+    let flags = header.flags();
+    if version != FIC_VERSION || flags != 0 {
+        writer.write_all(&FIC_MAGIC)?;                // 3 bytes
+        writer.write_all(&[version, flags])?;         // 2 bytes
+    }
+    writer.write_all(&header.width.to_le_bytes())?;          // 2 bytes
+    writer.write_all(&header.height.to_le_bytes())?;         // 2 bytes
+    writer.write_all(&[header.block_size])?;                 // 1 byte
+    writer.write_all(&[header.stride])?;                     // 1 byte
+    writer.write_all(&header.num_blocks.to_le_bytes())?;     // 4 bytes
+    Ok(())
+}
+
+pub fn write_block<W: Write>(writer: &mut W, luminance: Luminance, block: &EncodedBlock) -> io::Result<()> {
+    writer.write_all(&block.meta.to_le_bytes())?;         // 4 bytes
+    writer.write_all(&block.error.to_bits().to_le_bytes())?; // 4 bytes
+    writer.write_all(&block.alpha.to_bits().to_le_bytes())?; // 4 bytes
+    writer.write_all(&block.beta.to_bits().to_le_bytes())?;  // 4 bytes
+    if luminance == Luminance::Polynomial {
+        writer.write_all(&block.gamma.to_bits().to_le_bytes())?;  // 4 bytes
+        writer.write_all(&block.ramp_x.to_bits().to_le_bytes())?; // 4 bytes
+        writer.write_all(&block.ramp_y.to_bits().to_le_bytes())?; // 4 bytes
+    }
+    Ok(())
+}
+
+pub fn read_fic_header(path: &Path) -> io::Result<FicHeader> {
+    let reader = FicReader::new(BufReader::new(File::open(path)?))?;
+    Ok(*reader.header())
+}
+
+pub fn read_fic_file(path: &Path) -> io::Result<(FicHeader, Vec<EncodedBlock>)> {
+    let actual_size = std::fs::metadata(path)?.len() as usize;
+    let reader = FicReader::new(BufReader::new(File::open(path)?))?;
+    let header = *reader.header();
+
+    if header.file_size() != actual_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "header declares {} blocks ({} bytes) but file is {} bytes",
+                header.num_blocks,
+                header.file_size(),
+                actual_size
+            ),
+        ));
     }
 
-    println!("save_fic_file() writing {} blocks ({} bytes)", blocks.len(), 10 + blocks.len() * 16);
+    let blocks = reader.read_all()?;
+    Ok((header, blocks))
+}
 
+// Reads a still-image .fic from any source. The header is parsed up front and
+// the blocks are handed out one at a time, as an iterator or through
+// `read_block`. A stream has no length to check against the header, so a
+// truncated one shows up as an `UnexpectedEof` on the block that's cut off.
+pub struct FicReader<R: Read> {
+    reader: R,
+    header: FicHeader,
+    remaining: u32,
 }
 
+impl<R: Read> FicReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let header = read_header(&mut reader)?;
+        Ok(Self {
+            reader,
+            header,
+            remaining: header.num_blocks,
+        })
+    }
+
+    pub fn header(&self) -> &FicHeader {
+        &self.header
+    }
+
+    // Blocks still to be read
+    pub fn remaining(&self) -> usize {
+        self.remaining as usize
+    }
+
+    // The next block, or None once the header's block count has been read or
+    // a read has failed
+    pub fn read_block(&mut self) -> io::Result<Option<EncodedBlock>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        // A failed read leaves the source somewhere inside a block, so
+        // nothing after it can be trusted
+        let block = read_block(&mut self.reader, self.header.luminance);
+        self.remaining = if block.is_ok() { self.remaining - 1 } else { 0 };
+        block.map(Some)
+    }
+
+    pub fn read_all(self) -> io::Result<Vec<EncodedBlock>> {
+        self.collect()
+    }
+}
+
+impl<R: Read> Iterator for FicReader<R> {
+    type Item = io::Result<EncodedBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining()))
+    }
+}
+
+pub fn read_block<R: Read>(reader: &mut R, luminance: Luminance) -> io::Result<EncodedBlock> {
+    let mut buf4 = [0u8; 4];
+    let mut read_field = |buf: &mut [u8; 4]| -> io::Result<[u8; 4]> {
+        reader.read_exact(buf)?;
+        Ok(*buf)
+    };
+    let meta = u32::from_le_bytes(read_field(&mut buf4)?);
+    let error = f32::from_le_bytes(read_field(&mut buf4)?);
+    let alpha = f32::from_le_bytes(read_field(&mut buf4)?);
+    let beta = f32::from_le_bytes(read_field(&mut buf4)?);
+    let (mut gamma, mut ramp_x, mut ramp_y) = (0.0, 0.0, 0.0);
+    if luminance == Luminance::Polynomial {
+        gamma = f32::from_le_bytes(read_field(&mut buf4)?);
+        ramp_x = f32::from_le_bytes(read_field(&mut buf4)?);
+        ramp_y = f32::from_le_bytes(read_field(&mut buf4)?);
+    }
+
+    Ok(EncodedBlock {
+        meta,
+        error,
+        alpha,
+        beta,
+        gamma,
+        ramp_x,
+        ramp_y,
+    })
+}
+
+fn read_header<R: Read>(reader: &mut R) -> io::Result<FicHeader> {
+    let (header, version) = read_header_version(reader)?;
+    if version == FIC_VIDEO_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "this is a multi-frame .fic, decode it with `video decode`",
+        ));
+    }
+    Ok(header)
+}
+
+// Reads the header of a still or multi-frame file, along with its version
+pub fn read_header_version<R: Read>(reader: &mut R) -> io::Result<(FicHeader, u8)> {
+    let mut start = [0u8; FIC_MAGIC.len()];
+    reader.read_exact(&mut start)?;
+
+    let mut flags = 0;
+    let mut version = FIC_LEGACY_VERSION;
+    let mut legacy = [0u8; FIC_HEADER_BYTES];
+    if start == FIC_MAGIC {
+        let mut version_flags = [0u8; 2];
+        reader.read_exact(&mut version_flags)?;
+        let [file_version, file_flags] = version_flags;
+        if file_version != FIC_VERSION && file_version != FIC_VIDEO_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported .fic version {file_version} (this build reads versions {FIC_VERSION} and {FIC_VIDEO_VERSION})"),
+            ));
+        }
+        if file_flags & !KNOWN_FLAGS != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported .fic header flags {file_flags:#04x}"),
+            ));
+        }
+        if file_flags & FLAG_ORTHOGONAL != 0 && file_flags & FLAG_POLYNOMIAL != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "a .fic file can't use both the orthogonal and the polynomial model",
+            ));
+        }
+        flags = file_flags;
+        version = file_version;
+        reader.read_exact(&mut legacy)?;
+    } else {
+        legacy[..start.len()].copy_from_slice(&start);
+        reader.read_exact(&mut legacy[start.len()..])?;
+    }
+
+    let width = u16::from_le_bytes([legacy[0], legacy[1]]);
+    let height = u16::from_le_bytes([legacy[2], legacy[3]]);
+    let block_size = legacy[4];
+    let stride = legacy[5];
+    let num_blocks = u32::from_le_bytes([legacy[6], legacy[7], legacy[8], legacy[9]]);
+
+    let header = FicHeader {
+        width,
+        height,
+        block_size,
+        stride,
+        num_blocks,
+        luminance: if flags & FLAG_ORTHOGONAL != 0 {
+            Luminance::Orthogonal
+        } else if flags & FLAG_POLYNOMIAL != 0 {
+            Luminance::Polynomial
+        } else {
+            Luminance::Affine
+        },
+    };
+    header
+        .check()
+        .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))?;
+    Ok((header, version))
+}
 
 
 
+#[allow(dead_code)]
 pub fn save_debug_txt(path: &Path, encoded_blocks: &[EncodedBlock]) {
     let mut file = std::fs::File::create(path).expect("Failed to create debug txt");
-    use std::io::Write;
 
     writeln!(file, "GPU Output Debug Dump").unwrap();
     writeln!(file, "Total blocks: {}", encoded_blocks.len()).unwrap();
@@ -0,0 +1,398 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use image::{Delay, GrayImage};
+
+use crate::alpha_beta::compute_mse;
+use crate::block_extractor::{BlockExtractor, BlockStats, BlockView, IntegralImage};
+use crate::decode::{apply_block, domain_moments, to_image};
+use crate::encode::{
+    check_encode_params, encode_block, encode_in_parallel, EncodeOptions, EncodedBlock, MAX_DOMAIN_BLOCKS,
+};
+use crate::transform::apply_d4_transform;
+use crate::util::{
+    read_block, read_header_version, write_block, write_header, FicHeader, FIC_VERSIONED_HEADER_BYTES,
+    FIC_VIDEO_VERSION,
+};
+
+// Clips are coded frame by frame. A range of frame k can match a domain of
+// frame k itself (resolved by iterating, like a still image), a domain of
+// frame k-1 as the decoder reconstructed it (applied once), or be a skip
+// block that copies the co-located block of frame k-1. The encoder decodes
+// every frame it codes, so both sides always reference the same frame k-1.
+//
+// Domain indices cover both frames: the current frame's domains come first,
+// then the same grid over the previous frame.
+
+// Set in `EncodedBlock::meta`, above the transform id, on skip blocks
+pub const SKIP_BLOCK: u32 = 1 << 24;
+
+pub const DEFAULT_SKIP_THRESHOLD: f32 = 4.0;
+// Frame count and decoder iterations after the header, delay per frame
+const VIDEO_HEADER_EXTRA_BYTES: usize = 6;
+const FRAME_DELAY_BYTES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VideoOptions {
+    pub encode: EncodeOptions,
+    // Ranges within this MSE of the co-located block of the previous frame
+    // are skipped
+    pub skip_threshold: f32,
+    // Decoder iterations per frame, stored in the file so the decoder
+    // rebuilds the exact frames the encoder referenced
+    pub iterations: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub image: GrayImage,
+    pub delay: Delay,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedFrame {
+    pub delay: Delay,
+    pub blocks: Vec<EncodedBlock>,
+    pub stats: FrameStats,
+}
+
+// `header.num_blocks` is the block count of every frame
+#[derive(Clone, Debug)]
+pub struct EncodedVideo {
+    pub header: FicHeader,
+    pub iterations: u16,
+    pub frames: Vec<EncodedFrame>,
+}
+
+// Where each frame's blocks point
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub intra: usize,
+    pub temporal: usize,
+    pub skipped: usize,
+}
+
+impl FrameStats {
+    pub fn of(header: &FicHeader, blocks: &[EncodedBlock]) -> Self {
+        let domains = header.domains_per_row() * header.domains_per_col();
+        let mut stats = FrameStats::default();
+        for block in blocks {
+            if block.meta & SKIP_BLOCK != 0 {
+                stats.skipped += 1;
+            } else if block.domain_index() < domains {
+                stats.intra += 1;
+            } else {
+                stats.temporal += 1;
+            }
+        }
+        stats
+    }
+}
+
+pub fn encode_video(frames: &[Frame], options: &VideoOptions) -> Result<EncodedVideo, String> {
+    let first = frames.first().ok_or("no frames to encode")?;
+    let (width, height) = first.image.dimensions();
+    check_encode_params(width as usize, height as usize, &options.encode)?;
+    if let Some((i, frame)) = frames.iter().enumerate().find(|(_, f)| f.image.dimensions() != (width, height)) {
+        return Err(format!(
+            "frame {i} is {}x{}, the first frame is {width}x{height}",
+            frame.image.width(),
+            frame.image.height()
+        ));
+    }
+    let iterations = u16::try_from(options.iterations).map_err(|_| "too many decoder iterations".to_string())?;
+
+    let (w, h, bs) = (width as usize, height as usize, options.encode.block_size);
+    let domains = ((w - bs) / options.encode.stride + 1) * ((h - bs) / options.encode.stride + 1);
+    if frames.len() > 1 && 2 * domains > MAX_DOMAIN_BLOCKS {
+        return Err(format!(
+            "{domains} domain blocks per frame, twice that exceeds the 16-bit domain index; use a larger stride"
+        ));
+    }
+    let ranges = ((w - bs) / bs + 1) * ((h - bs) / bs + 1);
+    let header = FicHeader::for_encode(width, height, &options.encode, ranges);
+
+    let mut encoded = Vec::with_capacity(frames.len());
+    let mut previous: Option<Vec<f32>> = None;
+    for frame in frames {
+        let pixels: Vec<f32> = frame.image.pixels().map(|p| p[0] as f32).collect();
+        let blocks = encode_frame(&header, pixels, previous.as_deref(), options);
+        let decoded = decode_frame(&header, &blocks, previous.as_deref(), options.iterations)?;
+
+        encoded.push(EncodedFrame {
+            delay: frame.delay,
+            stats: FrameStats::of(&header, &blocks),
+            blocks,
+        });
+        previous = Some(decoded);
+    }
+
+    Ok(EncodedVideo {
+        header,
+        iterations,
+        frames: encoded,
+    })
+}
+
+fn encode_frame(
+    header: &FicHeader,
+    pixels: Vec<f32>,
+    previous: Option<&[f32]>,
+    options: &VideoOptions,
+) -> Vec<EncodedBlock> {
+    let encode = &options.encode;
+    let (width, height, bs) = (header.width as usize, header.height as usize, encode.block_size);
+    let extractor = BlockExtractor::new(pixels, width, height, bs, encode.stride);
+
+    let mut domains: Vec<BlockView> = extractor.domain_views().collect();
+    let mut domain_stats = extractor.domain_stats();
+    if let Some(previous) = previous {
+        let integral = IntegralImage::new(previous, width, height);
+        for (x, y) in extractor.domain_positions() {
+            domains.push(BlockView::new(previous, width, x, y, bs));
+            domain_stats.push(integral.block_stats(x, y, bs));
+        }
+    }
+
+    let ranges: Vec<BlockView> = extractor.range_views().collect();
+    let range_stats = extractor.range_stats();
+    // The error of copying the co-located block, for the ranges that will
+    let skip_errors: Vec<Option<f32>> = ranges
+        .iter()
+        .map(|range| {
+            let previous = previous?;
+            let co_located = BlockView::new(previous, width, range.x, range.y, bs);
+            Some(compute_mse(&co_located, range, 1.0, 0.0)).filter(|&mse| mse <= options.skip_threshold)
+        })
+        .collect();
+
+    let intra_domains = extractor.domain_positions().count();
+    encode_in_parallel(ranges.len(), encode.threads, |i| {
+        let range = &ranges[i];
+        if let Some(error) = skip_errors[i] {
+            return EncodedBlock::affine(SKIP_BLOCK, 0.0, 0.0).with_error(error);
+        }
+
+        // The previous frame's domains go first, so on equal error a block
+        // takes the one that decodes in a single pass. An intra domain
+        // overlapping its own range fits it almost for free but only
+        // reproduces what the decoder starts from, the previous frame, so
+        // those are left out.
+        let temporal = intra_domains..domains.len();
+        let intra = (0..intra_domains)
+            .filter(|&index| domains[index].x.abs_diff(range.x) >= bs || domains[index].y.abs_diff(range.y) >= bs);
+        let mut indices: Vec<usize> = temporal.chain(intra).collect();
+        if indices.is_empty() {
+            // Images barely larger than a block have nothing else to offer
+            indices = (0..domains.len()).collect();
+        }
+        let candidates: Vec<BlockView> = indices.iter().map(|&index| domains[index]).collect();
+        let stats: Vec<BlockStats> = indices.iter().map(|&index| domain_stats[index]).collect();
+        let mut block = encode_block(range, range_stats[i], &candidates, &stats, encode);
+        let index = indices[block.domain_index()] as u32;
+        block.meta = (block.meta & !0xFFFF) | index;
+        block
+    })
+}
+
+// Rebuilds one frame. Frames that reference themselves are iterated from
+// the previous frame, which is already close; the rest need a single pass.
+pub fn decode_frame(
+    header: &FicHeader,
+    blocks: &[EncodedBlock],
+    previous: Option<&[f32]>,
+    iterations: usize,
+) -> Result<Vec<f32>, String> {
+    let (width, height, bs) = (header.width as usize, header.height as usize, header.block_size as usize);
+    let domains = header.domains_per_row() * header.domains_per_col();
+
+    for (i, block) in blocks.iter().enumerate() {
+        let temporal = block.meta & SKIP_BLOCK != 0 || block.domain_index() >= domains;
+        if temporal && previous.is_none() {
+            return Err(format!("block {i} references a previous frame, but this is the first frame"));
+        }
+        if block.domain_index() >= 2 * domains {
+            return Err(format!("block {i} references domain {}, past the end", block.domain_index()));
+        }
+    }
+
+    if let Some(previous) = previous
+        && previous.len() != width * height
+    {
+        return Err(format!("previous frame has {} pixels, expected {}", previous.len(), width * height));
+    }
+
+    let mut current = previous.map_or_else(|| vec![128.0; width * height], <[f32]>::to_vec);
+    let mut next = current.clone();
+    let stats = FrameStats::of(header, blocks);
+    let passes = if stats.intra > 0 { iterations.max(1) } else { 1 };
+    let previous_integral = previous.map(|previous| IntegralImage::new(previous, width, height));
+
+    for _ in 0..passes {
+        let integral = IntegralImage::new(&current, width, height);
+        for (i, block) in blocks.iter().enumerate() {
+            let (bx, by) = header.range_origin(i);
+            if bx + bs > width || by + bs > height {
+                // Blocks past the last range have nowhere to go
+                continue;
+            }
+            let reconstructed = if block.meta & SKIP_BLOCK != 0 {
+                BlockView::new(previous.unwrap_or(&current), width, bx, by, bs).to_vec()
+            } else {
+                let (source, source_integral, index) = match block.domain_index() {
+                    index if index < domains => (current.as_slice(), &integral, index),
+                    index => (
+                        previous.unwrap_or(&current),
+                        previous_integral.as_ref().unwrap_or(&integral),
+                        index - domains,
+                    ),
+                };
+                let (dx, dy) = header.domain_origin(index);
+                if dx + bs > width || dy + bs > height {
+                    continue;
+                }
+                let domain = BlockView::new(source, width, dx, dy, bs);
+                let transformed = apply_d4_transform(&domain, bs, block.transform_id());
+                let moments = domain_moments(source_integral, dx, dy, bs);
+                apply_block(block, &transformed, moments, header.luminance, bs)
+            };
+
+            for (y, row) in reconstructed.chunks(bs).enumerate() {
+                for (x, value) in row.iter().enumerate() {
+                    next[(by + y) * width + bx + x] = value.clamp(0.0, 255.0);
+                }
+            }
+        }
+        current.copy_from_slice(&next);
+    }
+
+    Ok(current)
+}
+
+pub fn decode_video(video: &EncodedVideo) -> Result<Vec<Frame>, String> {
+    let (width, height) = (video.header.width as usize, video.header.height as usize);
+    let mut frames = Vec::with_capacity(video.frames.len());
+    let mut previous: Option<Vec<f32>> = None;
+    for (i, frame) in video.frames.iter().enumerate() {
+        let decoded = decode_frame(&video.header, &frame.blocks, previous.as_deref(), video.iterations as usize)
+            .map_err(|e| format!("frame {i}: {e}"))?;
+        frames.push(Frame {
+            image: to_image(&decoded, width, height),
+            delay: frame.delay,
+        });
+        previous = Some(decoded);
+    }
+    Ok(frames)
+}
+
+// Multi-frame .fic layout: the versioned header with version 3, where the
+// block count is per frame, then
+//   frame count u32, decoder iterations u16
+// and for every frame its display time as a fraction of milliseconds
+//   delay numerator u32, delay denominator u32
+// followed by its blocks in the still-image block layout
+pub fn video_file_size(video: &EncodedVideo) -> usize {
+    let header = &video.header;
+    let frame_bytes = FRAME_DELAY_BYTES + header.num_blocks as usize * header.block_bytes();
+    FIC_VERSIONED_HEADER_BYTES + VIDEO_HEADER_EXTRA_BYTES + video.frames.len() * frame_bytes
+}
+
+pub fn save_video_file(path: &Path, video: &EncodedVideo) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_header(&mut file, &video.header, FIC_VIDEO_VERSION)?;
+    file.write_all(&(video.frames.len() as u32).to_le_bytes())?;   // 4 bytes
+    file.write_all(&video.iterations.to_le_bytes())?;              // 2 bytes
+
+    for frame in &video.frames {
+        if frame.blocks.len() != video.header.num_blocks as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame has {} blocks, the header says {}", frame.blocks.len(), video.header.num_blocks),
+            ));
+        }
+        let (numerator, denominator) = frame.delay.numer_denom_ms();
+        file.write_all(&numerator.to_le_bytes())?;                 // 4 bytes
+        file.write_all(&denominator.to_le_bytes())?;               // 4 bytes
+        for block in &frame.blocks {
+            write_block(&mut file, video.header.luminance, block)?;
+        }
+    }
+    file.flush()?;
+
+    println!(
+        "save_video_file() writing {} frames of {} blocks ({} bytes)",
+        video.frames.len(),
+        video.header.num_blocks,
+        video_file_size(video)
+    );
+    Ok(())
+}
+
+pub fn read_video_file(path: &Path) -> io::Result<EncodedVideo> {
+    let actual_size = std::fs::metadata(path)?.len() as usize;
+    let mut reader = BufReader::new(File::open(path)?);
+    let (header, version) = read_header_version(&mut reader)?;
+    if version != FIC_VIDEO_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "this is a single-image .fic, decode it with `decode`",
+        ));
+    }
+
+    // Every frame tiles the whole image, so the block count is fixed by the
+    // dimensions; anything else would decode past the frame
+    let ranges = header.ranges_per_row() * header.ranges_per_col();
+    if header.num_blocks as usize != ranges {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "header declares {} blocks per frame, but {}x{} frames with {}x{} blocks have {ranges}",
+                header.num_blocks, header.width, header.height, header.block_size, header.block_size
+            ),
+        ));
+    }
+
+    let mut buf4 = [0u8; 4];
+    let mut buf2 = [0u8; 2];
+    reader.read_exact(&mut buf4)?;
+    let frame_count = u32::from_le_bytes(buf4) as usize;
+    reader.read_exact(&mut buf2)?;
+    let iterations = u16::from_le_bytes(buf2);
+
+    let mut video = EncodedVideo {
+        header,
+        iterations,
+        frames: Vec::new(),
+    };
+    let frame_bytes = FRAME_DELAY_BYTES + header.num_blocks as usize * header.block_bytes();
+    let expected = video_file_size(&video) + frame_count * frame_bytes;
+    if expected != actual_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "header declares {frame_count} frames of {} blocks ({expected} bytes) but file is {actual_size} bytes",
+                header.num_blocks
+            ),
+        ));
+    }
+
+    for _ in 0..frame_count {
+        reader.read_exact(&mut buf4)?;
+        let numerator = u32::from_le_bytes(buf4);
+        reader.read_exact(&mut buf4)?;
+        let denominator = u32::from_le_bytes(buf4);
+        if denominator == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame delay has a zero denominator"));
+        }
+        let blocks = (0..header.num_blocks)
+            .map(|_| read_block(&mut reader, header.luminance))
+            .collect::<io::Result<Vec<_>>>()?;
+        video.frames.push(EncodedFrame {
+            delay: Delay::from_numer_denom_ms(numerator, denominator),
+            stats: FrameStats::of(&header, &blocks),
+            blocks,
+        });
+    }
+    Ok(video)
+}
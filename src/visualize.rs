@@ -0,0 +1,206 @@
+use std::ops::RangeInclusive;
+
+use image::{GrayImage, Rgb, RgbImage};
+
+use crate::encode::EncodedBlock;
+use crate::util::FicHeader;
+
+// One colour per D4 transform, in `apply_d4_transform` order: the rotations
+// are warm, the flips cool
+pub const TRANSFORM_COLOURS: [[u8; 3]; 8] = [
+    [230, 25, 75],
+    [245, 130, 48],
+    [255, 225, 25],
+    [170, 110, 40],
+    [60, 180, 75],
+    [0, 130, 200],
+    [145, 30, 180],
+    [240, 50, 230],
+];
+pub const TRANSFORM_NAMES: [&str; 8] = [
+    "identity",
+    "rotate 90",
+    "rotate 180",
+    "rotate 270",
+    "flip horizontal",
+    "flip vertical",
+    "flip diagonal",
+    "flip anti-diagonal",
+];
+
+// How much of a selected range's pixels is its transform colour
+const TINT: f32 = 0.45;
+const GRID: Rgb<u8> = Rgb([32, 32, 32]);
+// Output images are scaled up to at least this size by default
+const MIN_DEFAULT_SIZE: usize = 512;
+const MAX_DEFAULT_SCALE: usize = 8;
+
+// Which ranges get tinted and linked to their domain. Every non-empty list
+// has to contain the block, so an empty filter selects everything.
+#[derive(Clone, Debug, Default)]
+pub struct RangeFilter {
+    pub ranges: Vec<RangeInclusive<usize>>,
+    pub transforms: Vec<u8>,
+    pub domains: Vec<usize>,
+    // Collage MSE
+    pub min_error: Option<f32>,
+}
+
+impl RangeFilter {
+    // "3,10-20" style lists of indices
+    pub fn parse_spans(text: &str) -> Option<Vec<RangeInclusive<usize>>> {
+        text.split(',')
+            .map(|span| {
+                let span = span.trim();
+                match span.split_once('-') {
+                    Some((start, end)) => {
+                        let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+                        (start <= end).then_some(start..=end)
+                    }
+                    None => span.parse().ok().map(|i| i..=i),
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && self.transforms.is_empty() && self.domains.is_empty() && self.min_error.is_none()
+    }
+
+    pub fn matches(&self, index: usize, block: &EncodedBlock, error: f32) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|span| span.contains(&index)))
+            && (self.transforms.is_empty() || self.transforms.contains(&block.transform_id()))
+            && (self.domains.is_empty() || self.domains.contains(&block.domain_index()))
+            && self.min_error.is_none_or(|min| error >= min)
+    }
+}
+
+// Smallest whole factor that brings the longer side up to MIN_DEFAULT_SIZE
+pub fn default_scale(header: &FicHeader) -> usize {
+    let longest = header.width.max(header.height) as usize;
+    MIN_DEFAULT_SIZE.div_ceil(longest.max(1)).clamp(1, MAX_DEFAULT_SCALE)
+}
+
+// Draws `background`, scaled up by `scale`, under the range grid. Selected
+// ranges are tinted with their transform's colour and joined to the centre of
+// their domain by a line in the same colour, ending in a dot on the domain;
+// when the filter narrows the selection the domains are outlined too.
+// Returns the image and how many ranges were selected per transform.
+pub fn render(
+    header: &FicHeader,
+    blocks: &[EncodedBlock],
+    background: &GrayImage,
+    scale: usize,
+    filter: &RangeFilter,
+    errors: &[f32],
+) -> Result<(RgbImage, [usize; 8]), String> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    if background.dimensions() != (width as u32, height as u32) {
+        return Err(format!(
+            "image is {}x{} but the .fic is {width}x{height}",
+            background.width(),
+            background.height()
+        ));
+    }
+    if errors.len() != blocks.len() {
+        return Err(format!("{} errors for {} blocks", errors.len(), blocks.len()));
+    }
+    let scale = scale.max(1);
+    let bs = header.block_size as usize;
+
+    // Lightened a little so the tints and the grid stand out
+    let mut canvas = RgbImage::from_fn((width * scale) as u32, (height * scale) as u32, |x, y| {
+        let v = background.get_pixel(x / scale as u32, y / scale as u32)[0] as f32;
+        let v = (v * 0.75 + 48.0) as u8;
+        Rgb([v, v, v])
+    });
+
+    let mut selected = Vec::new();
+    let mut counts = [0usize; 8];
+    for (i, block) in blocks.iter().enumerate() {
+        let (bx, by) = header.range_origin(i);
+        let (dx, dy) = header.domain_origin(block.domain_index());
+        let transform = block.transform_id() as usize;
+        if bx + bs > width || by + bs > height || dx + bs > width || dy + bs > height || transform >= 8 {
+            return Err(format!("block {i} lies outside the {width}x{height} image"));
+        }
+        if filter.matches(i, block, errors[i]) {
+            selected.push(i);
+            counts[transform] += 1;
+        }
+    }
+
+    for &i in &selected {
+        let (bx, by) = header.range_origin(i);
+        let colour = TRANSFORM_COLOURS[blocks[i].transform_id() as usize];
+        for y in by * scale..(by + bs) * scale {
+            for x in bx * scale..(bx + bs) * scale {
+                let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+                for c in 0..3 {
+                    pixel[c] = (pixel[c] as f32 * (1.0 - TINT) + colour[c] as f32 * TINT) as u8;
+                }
+            }
+        }
+    }
+
+    for i in 0..blocks.len() {
+        let (bx, by) = header.range_origin(i);
+        outline(&mut canvas, bx * scale, by * scale, bs * scale, GRID);
+    }
+
+    // Lines go on top of every tint and outline, so draw them last
+    let centre = |(x, y): (usize, usize)| ((x * scale + bs * scale / 2) as i64, (y * scale + bs * scale / 2) as i64);
+    for &i in &selected {
+        let colour = Rgb(TRANSFORM_COLOURS[blocks[i].transform_id() as usize]);
+        let domain = header.domain_origin(blocks[i].domain_index());
+        if !filter.is_empty() {
+            outline(&mut canvas, domain.0 * scale, domain.1 * scale, bs * scale, colour);
+        }
+        let (from, to) = (centre(header.range_origin(i)), centre(domain));
+        line(&mut canvas, from, to, colour);
+        for (ox, oy) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)] {
+            put(&mut canvas, to.0 + ox, to.1 + oy, colour);
+        }
+    }
+
+    Ok((canvas, counts))
+}
+
+fn put(canvas: &mut RgbImage, x: i64, y: i64, colour: Rgb<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < canvas.width() && (y as u32) < canvas.height() {
+        canvas.put_pixel(x as u32, y as u32, colour);
+    }
+}
+
+// Square of side `size` with its top-left corner at (x, y)
+fn outline(canvas: &mut RgbImage, x: usize, y: usize, size: usize, colour: Rgb<u8>) {
+    let (x, y, last) = (x as i64, y as i64, size as i64 - 1);
+    for t in 0..=last {
+        put(canvas, x + t, y, colour);
+        put(canvas, x + t, y + last, colour);
+        put(canvas, x, y + t, colour);
+        put(canvas, x + last, y + t, colour);
+    }
+}
+
+// Bresenham
+fn line(canvas: &mut RgbImage, (x0, y0): (i64, i64), (x1, y1): (i64, i64), colour: Rgb<u8>) {
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+    loop {
+        put(canvas, x, y, colour);
+        if (x, y) == (x1, y1) {
+            return;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}